@@ -0,0 +1,27 @@
+use std::{sync::Arc, time::Instant};
+
+use glam::vec3;
+use software_raymarcher::{
+    materials::{Unlit, RED},
+    raymarcher::Raymarcher,
+    surfaces::{bake, perlin_sphere, SurfaceList},
+};
+
+/// Bakes the slow perlin sphere into a grid once, so it can be orbited smoothly
+fn main() {
+    let sphere = perlin_sphere(10.0, 0.5, Arc::new(Unlit::new(RED)));
+    let bounds = sphere.bounds().unwrap().expand(1.0);
+    let start = Instant::now();
+    let baked = bake(&sphere, bounds, 128);
+    println!(
+        "baked {} samples in {:?}",
+        baked.grid().counts(),
+        start.elapsed()
+    );
+
+    let surfaces: SurfaceList = Arc::new(vec![Arc::new(baked)]);
+    let light_pos = vec3(-15.0, 20.0, -30.0);
+    let camera_pos = vec3(-8.0, 0.0, -15.0);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos).with_orbit();
+    pixelated::run(app)
+}
@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use glam::vec3;
+use software_raymarcher::{expr::parse_surface, raymarcher::Raymarcher};
+
+/// Type expressions into the terminal to replace the scene, for example
+/// `smin(sphere(1, red) @ (0, 1, 0), box(2, 0.5, 2, blue), 0.5)`
+fn main() {
+    let surface = parse_surface("plane((0, 1, 0), -2, white)").unwrap();
+    let light_pos = vec3(-2.0, 3.0, -2.0);
+    let camera_pos = vec3(0.0, 1.0, -5.0);
+    let app = Raymarcher::new(Arc::new(vec![surface]), camera_pos, light_pos).with_console();
+    pixelated::run(app)
+}
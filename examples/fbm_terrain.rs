@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use glam::vec3;
+use software_raymarcher::{
+    fbm::{FbmSettings, NoiseKind},
+    materials::Textured,
+    raymarcher::Raymarcher,
+    surfaces::{fbm_terrain, SurfaceList},
+};
+
+/// Walk over endless ridged hills
+fn main() {
+    let grass = Arc::new(Textured::new("assets/grass.jpeg").with_scale(0.5));
+    let noise = FbmSettings {
+        kind: NoiseKind::Simplex,
+        seed: 42,
+        ridged: true,
+        ..FbmSettings::new(0.1, 5.0)
+    };
+    let surfaces: SurfaceList = Arc::new(vec![fbm_terrain(noise, grass)]);
+    let light_pos = vec3(-20.0, 30.0, -10.0);
+    let camera_pos = vec3(0.0, 8.0, 0.0);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos).with_walking(0.5);
+    pixelated::run(app)
+}
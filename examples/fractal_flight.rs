@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use software_raymarcher::{
+    materials::{Unlit, RED},
+    raymarcher::Raymarcher,
+    surfaces::{infinite_repetition, menger_sponge, SurfaceList},
+};
+
+/// Fly through the tunnels of an endless Menger sponge
+fn main() {
+    let sponge = menger_sponge(1.0, Arc::new(Unlit::new(RED)));
+    let surfaces: SurfaceList = Arc::new(vec![infinite_repetition(Vec3::splat(2.0), sponge)]);
+    let light_pos = vec3(0.0, 0.0, 0.0);
+    let camera_pos = vec3(0.0, 0.0, -3.0);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos).with_collision(0.05);
+    pixelated::run(app)
+}
@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use glam::vec3;
+use software_raymarcher::{
+    materials::TrapGradient,
+    raymarcher::Raymarcher,
+    surfaces::{mandelbulb, SurfaceList},
+};
+
+fn main() {
+    let material = Arc::new(TrapGradient::new(vec3(1.0, 0.9, 0.4), vec3(0.2, 0.3, 0.9)));
+    let surfaces: SurfaceList = Arc::new(vec![mandelbulb(8.0, material)]);
+    let light_pos = vec3(-3.0, 4.0, -3.0);
+    let camera_pos = vec3(0.0, 0.0, -3.0);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos).with_orbit();
+    pixelated::run(app)
+}
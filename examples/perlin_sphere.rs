@@ -15,6 +15,6 @@ fn main() {
     ]);
     let light_pos = vec3(-15.0, 20.0, -30.0);
     let camera_pos = vec3(-8.0, 0.0, -15.0);
-    let app = Raymarcher::new(surfaces, camera_pos, light_pos);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos).with_orbit();
     pixelated::run(app)
 }
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use glam::vec3;
+use software_raymarcher::{
+    camera_path::CameraPath,
+    materials::{Textured, Unlit, RED},
+    raymarcher::Raymarcher,
+    surfaces::{plane, sphere, SurfaceList},
+};
+
+/// Renders a camera path recorded in the plane example without opening a window
+fn main() {
+    let checkerboard_mat = Arc::new(Textured::new("assets/checkerboard.jpeg"));
+    let surfaces: SurfaceList = Arc::new(vec![
+        plane(vec3(0.0, 1.0, 0.0), -2.0, checkerboard_mat),
+        sphere(1.0, Arc::new(Unlit::new(RED))),
+    ]);
+    let light_pos = vec3(-2.0, 1.0, -2.0);
+    let camera_pos = vec3(0.0, 1.0, -5.0);
+    let mut app = Raymarcher::new(surfaces, camera_pos, light_pos);
+
+    let path = CameraPath::load("outputs/camera_path.txt").unwrap();
+    let log = app
+        .render_camera_path(&path, 30.0, "outputs/frames")
+        .unwrap();
+    for (i, stats) in log.frames().enumerate() {
+        println!("frame {}: {:?}", i, stats.frame_time);
+    }
+    println!(
+        "rendered {} frames, avg {:?}",
+        log.len(),
+        log.average_frame_time()
+    );
+}
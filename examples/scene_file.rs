@@ -0,0 +1,15 @@
+use software_raymarcher::raymarcher::Raymarcher;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "scenes/plane.ron".to_string());
+    let app = match Raymarcher::load(&path) {
+        Ok(app) => app,
+        Err(err) => {
+            eprintln!("could not load scene: {}", err);
+            std::process::exit(1);
+        }
+    };
+    pixelated::run(app)
+}
@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use glam::{vec2, vec3};
+use software_raymarcher::{
+    materials::Textured,
+    raymarcher::Raymarcher,
+    surfaces::{heightfield, SurfaceList},
+};
+
+/// Walk over hills loaded from a grayscale heightmap
+fn main() {
+    let grass = Arc::new(Textured::new("assets/grass.jpeg").with_scale(0.5));
+    let terrain = heightfield("assets/heightmap.png", vec2(24.0, 24.0), 5.0, grass);
+    let surfaces: SurfaceList = Arc::new(vec![terrain]);
+    let light_pos = vec3(-12.0, 20.0, -8.0);
+    let camera_pos = vec3(0.0, 8.0, -10.0);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos).with_walking(0.5);
+    pixelated::run(app)
+}
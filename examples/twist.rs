@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use glam::{vec3, Mat3, Vec3};
+use software_raymarcher::{
+    materials,
+    raymarcher::Raymarcher,
+    surfaces::{from_fn, plane, FnSurface, SurfaceList},
+};
+
+/// A twisted box from a closure, twisting stretches the distance so it needs a lipschitz hint
+fn main() {
+    let twisted_box = |p: Vec3| {
+        let q = Mat3::from_rotation_y(p.y * 1.5) * p;
+        let d = q.abs() - vec3(0.5, 1.5, 0.5);
+        d.max(Vec3::ZERO).length() + d.max_element().min(0.0)
+    };
+    let stripes = materials::from_fn(|p| {
+        let stripe = (p.pos.y * 8.0).sin() * 0.5 + 0.5;
+        vec3(1.0, stripe, 0.2) * (p.normal.dot(Vec3::Y) * 0.3 + 0.7)
+    });
+
+    let surfaces: SurfaceList = Arc::new(vec![
+        plane(
+            vec3(0.0, 1.0, 0.0),
+            -2.0,
+            materials::from_fn(|_| Vec3::splat(0.8)),
+        ),
+        Arc::new(FnSurface::new(twisted_box, stripes).with_lipschitz(1.5)),
+        from_fn(
+            |p| (p - vec3(2.0, 0.0, 0.0)).length() - 0.75,
+            materials::from_fn(|p| p.normal * 0.5 + 0.5),
+        ),
+    ]);
+    let light_pos = vec3(-2.0, 3.0, -2.0);
+    let camera_pos = vec3(0.0, 1.0, -5.0);
+    pixelated::run(Raymarcher::new(surfaces, camera_pos, light_pos))
+}
@@ -44,6 +44,8 @@ fn main() {
     ]);
     let light_pos = vec3(2.0, 2.0, -3.0);
     let camera_pos = vec3(0.0, 3.0, -5.0);
-    let app = Raymarcher::new(surfaces, camera_pos, light_pos);
+    let app = Raymarcher::new(surfaces, camera_pos, light_pos)
+        .with_collision(0.25)
+        .with_walking(1.5);
     pixelated::run(app)
 }
@@ -0,0 +1,302 @@
+use std::{env, path::Path, process, str::FromStr, sync::Arc, time::Instant};
+
+use glam::{vec3, Vec3};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use software_raymarcher::{
+    expr::Env,
+    raymarcher::{Antialiasing, Raymarcher, RenderSettings, Shadows},
+    scene_file::{CameraDesc, SceneFile},
+    stats::{draw_overlay, StatsLog},
+};
+
+const USAGE: &str = "\
+Renders a scene file without opening a window
+
+usage: render <scene.ron> [options]
+       render --expr <expression> [options]
+
+options:
+    -e, --expr <expression>     surfaces written in the expression language, replaces the
+                                surfaces of the scene file if one is given
+    -o, --output <path>         image to write, default outputs/render.png
+    -f, --format <png|jpeg>     image format, default from the output extension
+    -r, --resolution <WxH>      default 512x512
+        --camera <x,y,z>        camera position, default from the scene
+        --yaw <degrees>         camera yaw, default from the scene
+        --pitch <degrees>       camera pitch, default from the scene
+        --light <x,y,z>         light position, default from the scene
+        --shadows <mode>        none, hard or soft[:k], default soft:16
+        --aa <1|4>              samples per pixel, default 1
+        --threads <n>           render threads, default one per core
+        --repeat <n>            times to render for the timings, default 1
+        --stats <path>          writes the stats of every frame as csv, or json for .json
+        --overlay               draws the stats of the last frame onto the image
+    -h, --help                  print this message";
+
+const DEFAULT_OUTPUT: &str = "outputs/render.png";
+const DEFAULT_SOFTNESS: f32 = 16.0;
+
+/// Everything set on the command line, None keeps the value from the scene file
+struct Options {
+    scene: Option<String>,
+    expr: Option<String>,
+    output: String,
+    format: Option<ImageFormat>,
+    settings: RenderSettings,
+    camera: Option<Vec3>,
+    yaw: Option<f32>,
+    pitch: Option<f32>,
+    light: Option<Vec3>,
+    repeat: u32,
+    stats: Option<String>,
+    overlay: bool,
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => exit_with(&format!("{}\n\n{}", err, USAGE)),
+    };
+    if let Err(err) = run(&options) {
+        exit_with(&err);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let start = Instant::now();
+    let scene = match &options.scene {
+        Some(path) => SceneFile::load(path).map_err(|err| err.to_string())?,
+        None => default_scene(),
+    };
+    let load_time = start.elapsed();
+
+    let start = Instant::now();
+    let surfaces = match &options.expr {
+        Some(text) => Env::new()
+            .eval_surface(text, "--expr")
+            .map(|surface| Arc::new(vec![surface])),
+        None => scene.build_surfaces(options.scene.as_deref().unwrap_or("default scene")),
+    }
+    .map_err(|err| err.to_string())?;
+    let build_time = start.elapsed();
+
+    let mut pose = scene.camera_pose();
+    pose.position = options.camera.unwrap_or(pose.position);
+    pose.yaw = options.yaw.map_or(pose.yaw, f32::to_radians);
+    pose.pitch = options.pitch.map_or(pose.pitch, f32::to_radians);
+    let light = options.light.unwrap_or(scene.light);
+    let mut raymarcher =
+        Raymarcher::new(surfaces, pose.position, light).with_settings(options.settings);
+    raymarcher.set_camera_pose(pose);
+
+    let settings = options.settings;
+    let size = buffer_size(settings.width, settings.height).expect("parse_args checks the size");
+    let mut pixels = vec![0; size];
+    let mut log = StatsLog::new();
+    for _ in 0..options.repeat {
+        log.push(raymarcher.render(&mut pixels));
+    }
+    let last = *log.frames().last().unwrap();
+    if options.overlay {
+        draw_overlay(&mut pixels, settings.width, &last);
+    }
+
+    let start = Instant::now();
+    save(options, pixels)?;
+    let save_time = start.elapsed();
+
+    if let Some(path) = &options.stats {
+        log.save(path)
+            .map_err(|err| format!("could not save {}: {}", path, err))?;
+    }
+
+    let render_times = log
+        .frames()
+        .map(|stats| stats.frame_time)
+        .collect::<Vec<_>>();
+    let average = log.total().frame_time / options.repeat;
+    let megapixels = (settings.width * settings.height) as f64 / 1e6;
+    println!("load:   {:?}", load_time);
+    println!("build:  {:?}", build_time);
+    println!(
+        "render: avg {:?}, min {:?}, max {:?} over {} frames",
+        average,
+        render_times.iter().min().unwrap(),
+        render_times.iter().max().unwrap(),
+        options.repeat
+    );
+    println!(
+        "        {:.2} megapixels/s at {}x{}",
+        megapixels / average.as_secs_f64(),
+        settings.width,
+        settings.height
+    );
+    println!("save:   {:?} to {}", save_time, options.output);
+    println!("stats of the last frame:");
+    for line in last.lines() {
+        println!("        {}", line);
+    }
+    Ok(())
+}
+
+/// Camera and light used when only an expression is given
+fn default_scene() -> SceneFile {
+    SceneFile {
+        camera: CameraDesc {
+            position: vec3(0.0, 1.0, -5.0),
+            yaw: 0.0,
+            pitch: 0.0,
+        },
+        light: vec3(-2.0, 3.0, -2.0),
+        materials: Default::default(),
+        surfaces: Vec::new(),
+    }
+}
+
+fn save(options: &Options, pixels: Vec<u8>) -> Result<(), String> {
+    let settings = options.settings;
+    let image = RgbaImage::from_raw(settings.width, settings.height, pixels).unwrap();
+    let format = match options.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&options.output).map_err(|err| err.to_string())?,
+    };
+    if let Some(dir) = Path::new(&options.output).parent() {
+        std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+
+    // Jpeg has no alpha channel
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb8()),
+        _ => DynamicImage::ImageRgba8(image),
+    };
+    image
+        .save_with_format(&options.output, format)
+        .map_err(|err| format!("could not save {}: {}", options.output, err))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        scene: None,
+        expr: None,
+        output: DEFAULT_OUTPUT.to_string(),
+        format: None,
+        settings: RenderSettings::default(),
+        camera: None,
+        yaw: None,
+        pitch: None,
+        light: None,
+        repeat: 1,
+        stats: None,
+        overlay: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-e" | "--expr" => options.expr = Some(value()?),
+            "-o" | "--output" => options.output = value()?,
+            "-f" | "--format" => options.format = Some(parse_format(&value()?)?),
+            "-r" | "--resolution" => {
+                let value = value()?;
+                let (width, height) = value
+                    .split_once('x')
+                    .ok_or_else(|| format!("expected WxH, got \"{}\"", value))?;
+                options.settings.width = parse_number(width)?;
+                options.settings.height = parse_number(height)?;
+                if options.settings.width == 0 || options.settings.height == 0 {
+                    return Err(format!("resolution \"{}\" is empty", value));
+                }
+                if buffer_size(options.settings.width, options.settings.height).is_none() {
+                    return Err(format!("resolution \"{}\" is too large", value));
+                }
+            }
+            "--camera" => options.camera = Some(parse_vec3(&value()?)?),
+            "--yaw" => options.yaw = Some(parse_number(&value()?)?),
+            "--pitch" => options.pitch = Some(parse_number(&value()?)?),
+            "--light" => options.light = Some(parse_vec3(&value()?)?),
+            "--shadows" => options.settings.shadows = parse_shadows(&value()?)?,
+            "--aa" => {
+                options.settings.anti_aliasing = match value()?.as_str() {
+                    "1" => Antialiasing::None,
+                    "4" => Antialiasing::AAx4,
+                    other => return Err(format!("unsupported aa level \"{}\"", other)),
+                }
+            }
+            "--threads" => options.settings.threads = parse_number(&value()?)?,
+            "--repeat" => options.repeat = parse_number::<u32>(&value()?)?.max(1),
+            "--stats" => {
+                options.stats = Some(value()?);
+                options.settings.timings = true;
+            }
+            "--overlay" => {
+                options.overlay = true;
+                options.settings.timings = true;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option \"{}\"", arg)),
+            _ if options.scene.is_none() => options.scene = Some(arg),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    if options.scene.is_none() && options.expr.is_none() {
+        return Err("missing scene file or expression".to_string());
+    }
+    Ok(options)
+}
+
+/// Bytes of the rgba pixels at a resolution, None past what the renderer can index with a u32
+fn buffer_size(width: u32, height: u32) -> Option<usize> {
+    let pixels = (width as usize).checked_mul(height as usize)?;
+    if pixels > u32::MAX as usize {
+        return None;
+    }
+    pixels.checked_mul(4)
+}
+
+fn parse_number<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number \"{}\"", value))
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let values = value
+        .split(',')
+        .map(parse_number)
+        .collect::<Result<Vec<f32>, _>>()?;
+    match values[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected x,y,z, got \"{}\"", value)),
+    }
+}
+
+fn parse_shadows(value: &str) -> Result<Shadows, String> {
+    match value.split_once(':') {
+        None if value == "none" => Ok(Shadows::None),
+        None if value == "hard" => Ok(Shadows::Hard),
+        None if value == "soft" => Ok(Shadows::Soft(DEFAULT_SOFTNESS)),
+        Some(("soft", k)) => Ok(Shadows::Soft(parse_number(k)?)),
+        _ => Err(format!("unknown shadow mode \"{}\"", value)),
+    }
+}
+
+fn parse_format(value: &str) -> Result<ImageFormat, String> {
+    match value {
+        "png" => Ok(ImageFormat::Png),
+        "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+        _ => Err(format!("unsupported format \"{}\"", value)),
+    }
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+};
+
+use glam::{vec3, Vec3};
+
+use crate::surfaces::interpolate_f32;
+
+/// Position and orientation of the camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl CameraPose {
+    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+        Self {
+            position,
+            yaw,
+            pitch,
+        }
+    }
+
+    /// p should be in range [0,1]
+    pub fn interpolate(&self, other: &CameraPose, p: f32) -> CameraPose {
+        CameraPose {
+            position: self.position.lerp(other.position, p),
+            yaw: interpolate_f32(self.yaw, other.yaw, p),
+            pitch: interpolate_f32(self.pitch, other.pitch, p),
+        }
+    }
+}
+
+/// Camera poses with the time in seconds they were recorded at
+///
+/// Saved as text with one "time x y z yaw pitch" line per pose
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    poses: Vec<(f32, CameraPose)>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pose, time should not be before the previous pose
+    pub fn push(&mut self, time: f32, pose: CameraPose) {
+        self.poses.push((time, pose));
+    }
+
+    pub fn len(&self) -> usize {
+        self.poses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.poses.is_empty()
+    }
+
+    /// Time of the last pose
+    pub fn duration(&self) -> f32 {
+        self.poses.last().map_or(0.0, |(time, _)| *time)
+    }
+
+    /// Pose at time, interpolated between the recorded poses
+    ///
+    /// Clamps to the first and last pose, None if the path is empty
+    pub fn sample(&self, time: f32) -> Option<CameraPose> {
+        let next = self.poses.partition_point(|(t, _)| *t <= time);
+        if next == 0 {
+            return self.poses.first().map(|(_, pose)| *pose);
+        }
+        if next == self.poses.len() {
+            return self.poses.last().map(|(_, pose)| *pose);
+        }
+
+        let (time1, pose1) = self.poses[next - 1];
+        let (time2, pose2) = self.poses[next];
+        let p = (time - time1) / (time2 - time1);
+        Some(pose1.interpolate(&pose2, p))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut text = String::new();
+        for (time, pose) in self.poses.iter() {
+            let p = pose.position;
+            text += &format!(
+                "{} {} {} {} {} {}\n",
+                time, p.x, p.y, p.z, pose.yaw, pose.pitch
+            );
+        }
+        fs::write(path, text)
+    }
+
+    /// Errors on lines that aren't poses or whose time is before the previous pose
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut camera_path = CameraPath::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[time, x, y, z, yaw, pitch]) => {
+                    let error = |message: String| {
+                        io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("{}:{}: {}", path, i + 1, message),
+                        )
+                    };
+                    if !time.is_finite() {
+                        return Err(error(format!("time {} is not finite", time)));
+                    }
+                    // sample looks poses up by time, so they have to be in order
+                    if let Some(&(previous, _)) = camera_path.poses.last() {
+                        if time < previous {
+                            return Err(error(format!(
+                                "time {} is before the previous pose at {}",
+                                time, previous
+                            )));
+                        }
+                    }
+                    camera_path.push(time, CameraPose::new(vec3(x, y, z), yaw, pitch))
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{}:{}: expected \"time x y z yaw pitch\"", path, i + 1),
+                    ))
+                }
+            }
+        }
+        Ok(camera_path)
+    }
+}
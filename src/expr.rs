@@ -0,0 +1,1442 @@
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    fmt,
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use glam::{EulerRot, Quat, Vec2, Vec3};
+
+use crate::{
+    fbm::FbmSettings,
+    materials::{Normal, Textured, TrapGradient, Unlit, BLUE, GREEN, PINK, RED, WHITE, YELLOW},
+    scene_file::SurfaceDesc,
+    surfaces::{
+        bake, box_frame, capped_cone, capped_cylinder, capped_torus, capsule, cylinder, displace,
+        ellipsoid, exact_box, fbm_terrain, hexagonal_prism, infinite_repetition, intersection,
+        link, mandelbox, mandelbulb, menger_sponge, octahedron, perlin_sphere, pertrubed_sphere,
+        plane, pyramid, rotation, round_cone, rounded_box, scale, sierpinski_tetrahedron,
+        smooth_intersection, smooth_subtraction, smooth_union, sphere, subtraction, torus,
+        translation, triangular_prism, union, Aabb, Heightfield, Mandelbox, Mandelbulb, Material,
+        MengerSponge, MeshSurface, SierpinskiTetrahedron, Surface, SurfaceTrait, VoxelSdf,
+    },
+};
+
+/// Calls deeper than this are treated as infinite recursion, keeps the stack from overflowing
+const MAX_CALL_DEPTH: usize = 64;
+/// Most calls a single evaluation may make, functions calling each other several times can
+/// make exponentially many calls without nesting deep
+const MAX_CALLS: usize = 10_000;
+/// Parentheses, calls and negations nested deeper than this are rejected by the parser
+const MAX_NESTING: usize = 128;
+/// bake leaves this fraction of the longest side of the bounds as room around the surface
+const BAKE_MARGIN: f32 = 0.1;
+
+//
+// Values
+//
+
+/// Result of evaluating an expression
+#[derive(Clone)]
+pub enum Value {
+    Number(f32),
+    Vector(Vec3),
+    Text(String),
+    Surface(Surface),
+    Material(Material),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Vector(_) => "a vector",
+            Value::Text(_) => "a string",
+            Value::Surface(_) => "a surface",
+            Value::Material(_) => "a material",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Vector(vector) => write!(f, "({}, {}, {})", vector.x, vector.y, vector.z),
+            Value::Text(text) => write!(f, "{:?}", text),
+            Value::Surface(_) => write!(f, "surface"),
+            Value::Material(_) => write!(f, "material"),
+        }
+    }
+}
+
+//
+// Environment
+//
+
+/// Variables and functions of the expression language
+///
+/// ```text
+/// # Comments start with #
+/// let r = 0.5;
+/// fn pillar(h) = box(r, h, r) @ (0, h, 0);
+/// smin(sphere(1, red) @ (0, 1, 0), pillar(2), 0.5) - 0.05
+/// ```
+///
+/// A program is any number of `let` and `fn` statements followed by an optional expression.
+/// Numbers, vectors written as (x, y, z) and surfaces can be combined with `+ - * /`, on
+/// surfaces these apply to the distance. `surface @ offset` translates a surface.
+/// Primitives take an optional material as their last argument, either a color or one of
+/// unlit(color), normal() and textured(path, scale, blend_sharpness), the default is normal()
+pub struct Env {
+    variables: HashMap<String, Value>,
+    functions: HashMap<String, Arc<Function>>,
+    /// Source of the let and fn statements run so far, saved together with surfaces
+    definitions: Vec<String>,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        let mut variables = HashMap::new();
+        variables.insert("pi".to_string(), Value::Number(PI));
+        for (name, color) in [
+            ("red", RED),
+            ("green", GREEN),
+            ("blue", BLUE),
+            ("white", WHITE),
+            ("yellow", YELLOW),
+            ("pink", PINK),
+        ] {
+            variables.insert(name.to_string(), Value::Vector(color));
+        }
+        Self {
+            variables,
+            functions: HashMap::new(),
+            definitions: Vec::new(),
+        }
+    }
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the statements of text and returns the value of the final expression, if any
+    ///
+    /// Definitions stay in the environment for later calls. Surfaces are returned as
+    /// Expression so they can be saved. name is only used in error messages
+    pub fn eval(&mut self, text: &str, name: &str) -> io::Result<Option<Value>> {
+        self.run(text).map_err(|err| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}:{}:{}: {}", name, err.pos.line, err.pos.col, err.message),
+            )
+        })
+    }
+
+    /// Like eval but the final expression has to be a surface
+    pub fn eval_surface(&mut self, text: &str, name: &str) -> io::Result<Surface> {
+        match self.eval(text, name)? {
+            Some(Value::Surface(surface)) => Ok(surface),
+            other => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{}: expected a surface, got {}",
+                    name,
+                    other.map_or("nothing", |value| value.type_name())
+                ),
+            )),
+        }
+    }
+
+    fn run(&mut self, text: &str) -> Result<Option<Value>, ExprError> {
+        let program = Parser::new(text)?.program()?;
+        let mut calls = 0;
+        for statement in program.statements {
+            match statement.kind {
+                StatementKind::Let(name, node) => {
+                    let value = self.eval_node(&node, &HashMap::new(), 0, &mut calls)?;
+                    self.variables.insert(name, value);
+                }
+                StatementKind::Fn(function) => {
+                    self.functions
+                        .insert(function.name.clone(), Arc::new(function));
+                }
+            }
+            self.definitions
+                .push(text[statement.span.0..statement.span.1].to_string());
+        }
+
+        let Some((node, span)) = program.result else {
+            return Ok(None);
+        };
+        match self.eval_node(&node, &HashMap::new(), 0, &mut calls)? {
+            Value::Surface(surface) => {
+                let mut source = self.definitions.join("\n");
+                if !source.is_empty() {
+                    source.push('\n');
+                }
+                source += &text[span.0..span.1];
+                Ok(Some(Value::Surface(expression(source, surface))))
+            }
+            value => Ok(Some(value)),
+        }
+    }
+
+    fn eval_node(
+        &self,
+        node: &Node,
+        locals: &HashMap<String, Value>,
+        depth: usize,
+        calls: &mut usize,
+    ) -> Result<Value, ExprError> {
+        let error = |message: String| ExprError::new(node.pos, message);
+        match &node.kind {
+            NodeKind::Number(number) => Ok(Value::Number(*number)),
+            NodeKind::Text(text) => Ok(Value::Text(text.clone())),
+            NodeKind::Variable(name) => locals
+                .get(name)
+                .or_else(|| self.variables.get(name))
+                .cloned()
+                .ok_or_else(|| error(format!("unknown variable \"{}\"", name))),
+            NodeKind::Vector(nodes) => {
+                let mut components = [0.0; 3];
+                for (component, node) in components.iter_mut().zip(nodes) {
+                    match self.eval_node(node, locals, depth, calls)? {
+                        Value::Number(number) => *component = number,
+                        value => {
+                            return Err(ExprError::new(
+                                node.pos,
+                                format!("expected a number, got {}", value.type_name()),
+                            ))
+                        }
+                    }
+                }
+                Ok(Value::Vector(Vec3::from(components)))
+            }
+            NodeKind::Negate(node) => match self.eval_node(node, locals, depth, calls)? {
+                Value::Number(number) => Ok(Value::Number(-number)),
+                Value::Vector(vector) => Ok(Value::Vector(-vector)),
+                Value::Surface(surface) => Ok(Value::Surface(arithmetic(
+                    ArithmeticOp::Sub,
+                    Operand::Number(0.0),
+                    Operand::Surface(surface),
+                ))),
+                value => Err(error(format!("can't negate {}", value.type_name()))),
+            },
+            NodeKind::Binary(op, lhs, rhs) => {
+                let lhs = self.eval_node(lhs, locals, depth, calls)?;
+                let rhs = self.eval_node(rhs, locals, depth, calls)?;
+                binary(*op, lhs, rhs).map_err(error)
+            }
+            NodeKind::Call(name, arg_nodes) => {
+                if depth >= MAX_CALL_DEPTH {
+                    return Err(error(format!(
+                        "calls nested deeper than {}",
+                        MAX_CALL_DEPTH
+                    )));
+                }
+                *calls += 1;
+                if *calls > MAX_CALLS {
+                    return Err(error(format!("more than {} calls", MAX_CALLS)));
+                }
+                let args = arg_nodes
+                    .iter()
+                    .map(|node| Ok((self.eval_node(node, locals, depth, calls)?, node.pos)))
+                    .collect::<Result<Vec<_>, ExprError>>()?;
+
+                if let Some(function) = self.functions.get(name) {
+                    if args.len() != function.params.len() {
+                        return Err(error(format!(
+                            "{} takes {} arguments, got {}",
+                            name,
+                            function.params.len(),
+                            args.len()
+                        )));
+                    }
+                    let locals = function
+                        .params
+                        .iter()
+                        .cloned()
+                        .zip(args.into_iter().map(|(value, _)| value))
+                        .collect();
+                    return self.eval_node(&function.body, &locals, depth + 1, calls);
+                }
+                call_builtin(&Args {
+                    name,
+                    pos: node.pos,
+                    values: args,
+                })
+            }
+        }
+    }
+}
+
+/// Parses a program whose final expression is a surface, see Env for the syntax
+pub fn parse_surface(text: &str) -> io::Result<Surface> {
+    Env::new().eval_surface(text, "<expr>")
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+    use Value::{Number, Surface, Vector};
+
+    let mismatch = |lhs: &Value, rhs: &Value| {
+        format!(
+            "can't apply {} to {} and {}",
+            op.symbol(),
+            lhs.type_name(),
+            rhs.type_name()
+        )
+    };
+    if op == BinaryOp::Translate {
+        return match (lhs, rhs) {
+            (Surface(surface), Vector(offset)) => Ok(Surface(translation(offset, surface))),
+            (lhs, rhs) => Err(mismatch(&lhs, &rhs)),
+        };
+    }
+    let op = match op {
+        BinaryOp::Add => ArithmeticOp::Add,
+        BinaryOp::Sub => ArithmeticOp::Sub,
+        BinaryOp::Mul => ArithmeticOp::Mul,
+        BinaryOp::Div => ArithmeticOp::Div,
+        BinaryOp::Translate => unreachable!(),
+    };
+
+    Ok(match (lhs, rhs) {
+        (Number(a), Number(b)) => Number(op.apply(a, b)),
+        (Vector(a), Vector(b)) => Vector(op.apply_vec3(a, b)),
+        (Vector(a), Number(b)) => Vector(op.apply_vec3(a, Vec3::splat(b))),
+        (Number(a), Vector(b)) => Vector(op.apply_vec3(Vec3::splat(a), b)),
+        (Surface(a), Number(b)) => Surface(arithmetic(op, Operand::Surface(a), Operand::Number(b))),
+        (Number(a), Surface(b)) => Surface(arithmetic(op, Operand::Number(a), Operand::Surface(b))),
+        (Surface(a), Surface(b)) => {
+            Surface(arithmetic(op, Operand::Surface(a), Operand::Surface(b)))
+        }
+        (lhs, rhs) => return Err(mismatch(&lhs, &rhs)),
+    })
+}
+
+//
+// Builtin functions
+//
+
+/// Evaluated arguments of a call with their positions
+struct Args<'a> {
+    name: &'a str,
+    pos: Pos,
+    values: Vec<(Value, Pos)>,
+}
+
+impl Args<'_> {
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn expect_len(&self, min: usize, max: usize) -> Result<(), ExprError> {
+        if (min..=max).contains(&self.len()) {
+            return Ok(());
+        }
+        let expected = if min == max {
+            min.to_string()
+        } else {
+            format!("{} to {}", min, max)
+        };
+        Err(ExprError::new(
+            self.pos,
+            format!(
+                "{} takes {} arguments, got {}",
+                self.name,
+                expected,
+                self.len()
+            ),
+        ))
+    }
+
+    fn wrong_type(&self, i: usize, expected: &str) -> ExprError {
+        let (value, pos) = &self.values[i];
+        ExprError::new(
+            *pos,
+            format!(
+                "argument {} of {} should be {}, got {}",
+                i + 1,
+                self.name,
+                expected,
+                value.type_name()
+            ),
+        )
+    }
+
+    fn number(&self, i: usize) -> Result<f32, ExprError> {
+        match &self.values[i].0 {
+            Value::Number(number) => Ok(*number),
+            _ => Err(self.wrong_type(i, "a number")),
+        }
+    }
+
+    fn vector(&self, i: usize) -> Result<Vec3, ExprError> {
+        match &self.values[i].0 {
+            Value::Vector(vector) => Ok(*vector),
+            _ => Err(self.wrong_type(i, "a vector")),
+        }
+    }
+
+    fn text(&self, i: usize) -> Result<&str, ExprError> {
+        match &self.values[i].0 {
+            Value::Text(text) => Ok(text),
+            _ => Err(self.wrong_type(i, "a string")),
+        }
+    }
+
+    fn surface(&self, i: usize) -> Result<Surface, ExprError> {
+        match &self.values[i].0 {
+            Value::Surface(surface) => Ok(surface.clone()),
+            _ => Err(self.wrong_type(i, "a surface")),
+        }
+    }
+
+    /// Material argument, a color is turned into an unlit material. Normal if not given
+    fn material(&self, i: usize) -> Result<Material, ExprError> {
+        match self.values.get(i).map(|(value, _)| value) {
+            None => Ok(Arc::new(Normal)),
+            Some(Value::Material(material)) => Ok(material.clone()),
+            Some(Value::Vector(color)) => Ok(Arc::new(Unlit::new(*color))),
+            Some(_) => Err(self.wrong_type(i, "a material or color")),
+        }
+    }
+
+    /// fBm with amplitude and frequency at i, followed by octaves and seed if count allows
+    fn fbm(&self, i: usize, count: usize) -> Result<FbmSettings, ExprError> {
+        let mut settings = FbmSettings::new(self.number(i + 1)?, self.number(i)?);
+        if count > 2 {
+            settings.octaves = self.number(i + 2)?.max(0.0) as u32;
+        }
+        if count > 3 {
+            settings.seed = self.number(i + 3)?.max(0.0) as u32;
+        }
+        Ok(settings)
+    }
+
+    /// All arguments as surfaces combined from left to right
+    fn fold(
+        &self,
+        args: std::ops::Range<usize>,
+        combine: &dyn Fn(Surface, Surface) -> Surface,
+    ) -> Result<Surface, ExprError> {
+        let mut combined = self.surface(args.start)?;
+        for i in args.start + 1..args.end {
+            combined = combine(combined, self.surface(i)?);
+        }
+        Ok(combined)
+    }
+
+    fn all_numbers(&self) -> bool {
+        self.values
+            .iter()
+            .all(|(value, _)| matches!(value, Value::Number(_)))
+    }
+
+    /// Count of numbers before the first argument of another type
+    fn leading_numbers(&self) -> usize {
+        self.values
+            .iter()
+            .take_while(|(value, _)| matches!(value, Value::Number(_)))
+            .count()
+    }
+}
+
+fn call_builtin(args: &Args) -> Result<Value, ExprError> {
+    let n = args.len();
+    let number = |f: fn(f32) -> f32| -> Result<Value, ExprError> {
+        args.expect_len(1, 1)?;
+        Ok(Value::Number(f(args.number(0)?)))
+    };
+
+    let surface = match args.name {
+        // Numbers
+        "sqrt" => return number(f32::sqrt),
+        "abs" => return number(f32::abs),
+        "sin" => return number(f32::sin),
+        "cos" => return number(f32::cos),
+        "tan" => return number(f32::tan),
+        "pow" => {
+            args.expect_len(2, 2)?;
+            return Ok(Value::Number(args.number(0)?.powf(args.number(1)?)));
+        }
+        "min" | "max" if args.all_numbers() => {
+            args.expect_len(1, usize::MAX)?;
+            let numbers = (0..n)
+                .map(|i| args.number(i))
+                .collect::<Result<Vec<_>, _>>()?;
+            let fold = if args.name == "min" {
+                f32::min
+            } else {
+                f32::max
+            };
+            return Ok(Value::Number(numbers.into_iter().reduce(fold).unwrap()));
+        }
+
+        // Materials
+        "unlit" => {
+            args.expect_len(1, 1)?;
+            return Ok(Value::Material(Arc::new(Unlit::new(args.vector(0)?))));
+        }
+        "trap_gradient" => {
+            args.expect_len(2, 2)?;
+            return Ok(Value::Material(Arc::new(TrapGradient::new(
+                args.vector(0)?,
+                args.vector(1)?,
+            ))));
+        }
+        "normal" => {
+            args.expect_len(0, 0)?;
+            return Ok(Value::Material(Arc::new(Normal)));
+        }
+        "textured" => {
+            args.expect_len(1, 3)?;
+            let path = args.text(0)?;
+            let textured = Textured::load(path).map_err(|err| {
+                ExprError::new(
+                    args.pos,
+                    format!("could not load texture \"{}\": {}", path, err),
+                )
+            })?;
+            let scale = if n > 1 { args.number(1)? } else { 1.0 };
+            let blend_sharpness = if n > 2 { args.number(2)? } else { 1.0 };
+            return Ok(Value::Material(Arc::new(
+                textured
+                    .with_scale(scale)
+                    .with_blend_sharpness(blend_sharpness),
+            )));
+        }
+
+        // Primitives
+        "sphere" => {
+            args.expect_len(1, 2)?;
+            sphere(args.number(0)?, args.material(1)?)
+        }
+        "box" if (n == 3 && args.all_numbers()) || n == 4 => {
+            args.expect_len(3, 4)?;
+            let half_size = Vec3::new(args.number(0)?, args.number(1)?, args.number(2)?);
+            exact_box(half_size, args.material(3)?)
+        }
+        "box" => {
+            args.expect_len(1, 2)?;
+            exact_box(args.vector(0)?, args.material(1)?)
+        }
+        "plane" => {
+            args.expect_len(2, 3)?;
+            plane(args.vector(0)?, args.number(1)?, args.material(2)?)
+        }
+        "perlin_sphere" => {
+            args.expect_len(2, 3)?;
+            perlin_sphere(args.number(0)?, args.number(1)?, args.material(2)?)
+        }
+        "pertrubed_sphere" => {
+            args.expect_len(3, 4)?;
+            pertrubed_sphere(
+                args.number(0)?,
+                args.number(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "torus" => {
+            args.expect_len(2, 3)?;
+            torus(args.number(0)?, args.number(1)?, args.material(2)?)
+        }
+        "capped_torus" => {
+            args.expect_len(3, 4)?;
+            capped_torus(
+                args.number(0)?.to_radians(),
+                args.number(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "link" => {
+            args.expect_len(3, 4)?;
+            link(
+                args.number(0)?,
+                args.number(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "capsule" => {
+            args.expect_len(3, 4)?;
+            capsule(
+                args.vector(0)?,
+                args.vector(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "cylinder" if n >= 3 && matches!(args.values[0].0, Value::Vector(_)) => {
+            args.expect_len(3, 4)?;
+            capped_cylinder(
+                args.vector(0)?,
+                args.vector(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "cylinder" => {
+            args.expect_len(2, 3)?;
+            cylinder(args.number(0)?, args.number(1)?, args.material(2)?)
+        }
+        "cone" => {
+            args.expect_len(3, 4)?;
+            capped_cone(
+                args.number(0)?,
+                args.number(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "round_cone" => {
+            args.expect_len(3, 4)?;
+            round_cone(
+                args.number(0)?,
+                args.number(1)?,
+                args.number(2)?,
+                args.material(3)?,
+            )
+        }
+        "rounded_box" if (n == 4 && args.all_numbers()) || n == 5 => {
+            let half_size = Vec3::new(args.number(0)?, args.number(1)?, args.number(2)?);
+            rounded_box(half_size, args.number(3)?, args.material(4)?)
+        }
+        "rounded_box" => {
+            args.expect_len(2, 3)?;
+            rounded_box(args.vector(0)?, args.number(1)?, args.material(2)?)
+        }
+        "box_frame" => {
+            args.expect_len(2, 3)?;
+            box_frame(args.vector(0)?, args.number(1)?, args.material(2)?)
+        }
+        "ellipsoid" => {
+            args.expect_len(1, 2)?;
+            ellipsoid(args.vector(0)?, args.material(1)?)
+        }
+        "octahedron" => {
+            args.expect_len(1, 2)?;
+            octahedron(args.number(0)?, args.material(1)?)
+        }
+        "pyramid" => {
+            args.expect_len(2, 3)?;
+            pyramid(args.number(0)?, args.number(1)?, args.material(2)?)
+        }
+        "hex_prism" => {
+            args.expect_len(2, 3)?;
+            hexagonal_prism(args.number(0)?, args.number(1)?, args.material(2)?)
+        }
+        "tri_prism" => {
+            args.expect_len(2, 3)?;
+            triangular_prism(args.number(0)?, args.number(1)?, args.material(2)?)
+        }
+        "mandelbulb" if n >= 3 => {
+            args.expect_len(3, 4)?;
+            let iterations = args.number(1)?.max(0.0) as u32;
+            Arc::new(
+                Mandelbulb::new(args.number(0)?, args.material(3)?)
+                    .with_iterations(iterations)
+                    .with_bailout(args.number(2)?),
+            )
+        }
+        "mandelbulb" => {
+            args.expect_len(1, 2)?;
+            mandelbulb(args.number(0)?, args.material(1)?)
+        }
+        "menger" if n >= 2 && matches!(args.values[1].0, Value::Number(_)) => {
+            args.expect_len(2, 3)?;
+            let iterations = args.number(1)?.max(0.0) as u32;
+            Arc::new(
+                MengerSponge::new(args.number(0)?, args.material(2)?).with_iterations(iterations),
+            )
+        }
+        "menger" => {
+            args.expect_len(1, 2)?;
+            menger_sponge(args.number(0)?, args.material(1)?)
+        }
+        "sierpinski" if n >= 2 && matches!(args.values[1].0, Value::Number(_)) => {
+            args.expect_len(2, 3)?;
+            let iterations = args.number(1)?.max(0.0) as u32;
+            Arc::new(
+                SierpinskiTetrahedron::new(args.number(0)?, args.material(2)?)
+                    .with_iterations(iterations),
+            )
+        }
+        "sierpinski" => {
+            args.expect_len(1, 2)?;
+            sierpinski_tetrahedron(args.number(0)?, args.material(1)?)
+        }
+        "mandelbox" if n >= 3 => {
+            args.expect_len(4, 5)?;
+            let iterations = args.number(3)?.max(0.0) as u32;
+            Arc::new(
+                Mandelbox::new(args.number(0)?, args.material(4)?)
+                    .with_min_radius(args.number(1)?)
+                    .with_fold_limit(args.number(2)?)
+                    .with_iterations(iterations),
+            )
+        }
+        "mandelbox" => {
+            args.expect_len(1, 2)?;
+            mandelbox(args.number(0)?, args.material(1)?)
+        }
+        "mesh" => {
+            args.expect_len(1, 2)?;
+            let path = args.text(0)?;
+            let mesh = MeshSurface::load(path, args.material(1)?).map_err(|err| {
+                ExprError::new(
+                    args.pos,
+                    format!("could not load mesh \"{}\": {}", path, err),
+                )
+            })?;
+            Arc::new(mesh)
+        }
+        "heightfield" => {
+            args.expect_len(4, 5)?;
+            let path = args.text(0)?;
+            let size = Vec2::new(args.number(1)?, args.number(2)?);
+            let heightfield = Heightfield::load(path, size, args.number(3)?, args.material(4)?)
+                .map_err(|err| {
+                    ExprError::new(
+                        args.pos,
+                        format!("could not load heightmap \"{}\": {}", path, err),
+                    )
+                })?;
+            Arc::new(heightfield)
+        }
+        "terrain" => {
+            let numbers = args.leading_numbers().min(4);
+            args.expect_len(2, numbers.max(2) + 1)?;
+            fbm_terrain(args.fbm(0, numbers)?, args.material(numbers)?)
+        }
+        "voxels" => {
+            args.expect_len(1, 2)?;
+            let path = args.text(0)?;
+            let voxels = VoxelSdf::load(path, args.material(1)?).map_err(|err| {
+                ExprError::new(
+                    args.pos,
+                    format!("could not load voxels \"{}\": {}", path, err),
+                )
+            })?;
+            Arc::new(voxels)
+        }
+
+        // Combinations
+        "union" | "min" => {
+            args.expect_len(1, usize::MAX)?;
+            args.fold(0..n, &union)?
+        }
+        "intersection" | "max" => {
+            args.expect_len(1, usize::MAX)?;
+            args.fold(0..n, &intersection)?
+        }
+        "subtraction" => {
+            args.expect_len(2, 2)?;
+            subtraction(args.surface(0)?, args.surface(1)?)
+        }
+        "smooth_union" | "smin" => {
+            args.expect_len(2, usize::MAX)?;
+            let blend = args.number(n - 1)?;
+            args.fold(0..n - 1, &|a, b| smooth_union(a, b, blend))?
+        }
+        "smooth_intersection" | "smax" => {
+            args.expect_len(2, usize::MAX)?;
+            let blend = args.number(n - 1)?;
+            args.fold(0..n - 1, &|a, b| smooth_intersection(a, b, blend))?
+        }
+        "smooth_subtraction" => {
+            args.expect_len(3, 3)?;
+            smooth_subtraction(args.surface(0)?, args.surface(1)?, args.number(2)?)
+        }
+
+        // Caching
+        "bake" => {
+            args.expect_len(2, 2)?;
+            let surface = args.surface(0)?;
+            let bounds = surface.bounds().ok_or_else(|| {
+                ExprError::new(args.pos, "can't bake a surface without bounds".to_string())
+            })?;
+            let resolution = args.number(1)?.max(2.0) as u32;
+            let margin = bounds.size().max_element() * BAKE_MARGIN;
+            Arc::new(bake(&surface, bounds.expand(margin), resolution))
+        }
+
+        // Transformations
+        "translate" => {
+            args.expect_len(2, 2)?;
+            translation(args.vector(1)?, args.surface(0)?)
+        }
+        "rotate" => {
+            args.expect_len(2, 2)?;
+            let degrees = args.vector(1)?;
+            let quat = Quat::from_euler(
+                EulerRot::XYZ,
+                degrees.x.to_radians(),
+                degrees.y.to_radians(),
+                degrees.z.to_radians(),
+            );
+            rotation(quat, args.surface(0)?)
+        }
+        "scale" => {
+            args.expect_len(2, 2)?;
+            scale(args.number(1)?, args.surface(0)?)
+        }
+        "repeat" => {
+            args.expect_len(2, 2)?;
+            infinite_repetition(args.vector(1)?, args.surface(0)?)
+        }
+        "displace" => {
+            args.expect_len(3, 5)?;
+            displace(args.fbm(1, n - 1)?, args.surface(0)?)
+        }
+
+        name => {
+            return Err(ExprError::new(
+                args.pos,
+                format!("unknown function \"{}\"", name),
+            ))
+        }
+    };
+    Ok(Value::Surface(surface))
+}
+
+//
+// Arithmetic
+//
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl ArithmeticOp {
+    fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            ArithmeticOp::Add => a + b,
+            ArithmeticOp::Sub => a - b,
+            ArithmeticOp::Mul => a * b,
+            ArithmeticOp::Div => a / b,
+        }
+    }
+
+    fn apply_vec3(self, a: Vec3, b: Vec3) -> Vec3 {
+        match self {
+            ArithmeticOp::Add => a + b,
+            ArithmeticOp::Sub => a - b,
+            ArithmeticOp::Mul => a * b,
+            ArithmeticOp::Div => a / b,
+        }
+    }
+}
+
+pub enum Operand {
+    Surface(Surface),
+    Number(f32),
+}
+
+impl Operand {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        match self {
+            Operand::Surface(surface) => surface.sdf(pos),
+            Operand::Number(number) => *number,
+        }
+    }
+
+    fn lipschitz(&self) -> f32 {
+        match self {
+            Operand::Surface(surface) => surface.lipschitz(),
+            Operand::Number(_) => 0.0,
+        }
+    }
+}
+
+/// Distance of a surface combined with a number or the distance of another surface
+///
+/// Subtracting a number rounds a surface, multiplying scales its distance. The color comes
+/// from the closest surface
+pub struct Arithmetic {
+    op: ArithmeticOp,
+    lhs: Operand,
+    rhs: Operand,
+}
+
+impl Arithmetic {
+    pub fn new(op: ArithmeticOp, lhs: Operand, rhs: Operand) -> Self {
+        Self { op, lhs, rhs }
+    }
+}
+
+impl SurfaceTrait for Arithmetic {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        self.op.apply(self.lhs.sdf(pos), self.rhs.sdf(pos))
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        match self.closest_child(pos) {
+            Some((_, surface, pos)) => surface.color(ray, pos, normal, light_pos),
+            None => Vec3::ZERO,
+        }
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        match (&self.lhs, &self.rhs) {
+            (Operand::Surface(a), Operand::Surface(b)) => {
+                if a.sdf(pos).abs() <= b.sdf(pos).abs() {
+                    Some((0, a, pos))
+                } else {
+                    Some((1, b, pos))
+                }
+            }
+            (Operand::Surface(a), _) => Some((0, a, pos)),
+            (_, Operand::Surface(b)) => Some((1, b, pos)),
+            _ => None,
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        use ArithmeticOp::{Add, Div, Mul, Sub};
+        use Operand::{Number, Surface};
+
+        match (&self.lhs, self.op, &self.rhs) {
+            // Growing by a negative amount keeps the surface inside its bounds
+            (Surface(a), Sub, Number(b)) => Some(a.bounds()?.expand(b.max(0.0))),
+            (Surface(a), Add, Number(b)) | (Number(b), Add, Surface(a)) if *b >= 0.0 => a.bounds(),
+            // Scaling the distance by a positive number keeps the zero crossing
+            (Surface(a), Mul | Div, Number(b)) | (Number(b), Mul, Surface(a)) if *b > 0.0 => {
+                a.bounds()
+            }
+            _ => None,
+        }
+    }
+
+    fn lipschitz(&self) -> f32 {
+        use ArithmeticOp::{Add, Div, Mul, Sub};
+        use Operand::{Number, Surface};
+
+        match (&self.lhs, self.op, &self.rhs) {
+            (Surface(a), Add | Sub, Number(_)) | (Number(_), Add | Sub, Surface(a)) => {
+                a.lipschitz()
+            }
+            (Surface(a), Mul, Number(b)) | (Number(b), Mul, Surface(a)) => a.lipschitz() * b.abs(),
+            (Surface(a), Div, Number(b)) => a.lipschitz() / b.abs(),
+            (Surface(a), Add | Sub, Surface(b)) => a.lipschitz() + b.lipschitz(),
+            // Products and quotients of distances have no bound, assume the larger one
+            (lhs, _, rhs) => lhs.lipschitz().max(rhs.lipschitz()),
+        }
+    }
+}
+pub fn arithmetic(op: ArithmeticOp, lhs: Operand, rhs: Operand) -> Surface {
+    Arc::new(Arithmetic::new(op, lhs, rhs))
+}
+
+//
+// Expression
+//
+
+/// Surface built from an expression, keeps the source so the scene can be saved
+pub struct Expression {
+    source: String,
+    surface: Surface,
+}
+
+impl Expression {
+    pub fn new(source: String, surface: Surface) -> Self {
+        Self { source, surface }
+    }
+}
+
+impl SurfaceTrait for Expression {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        self.surface.sdf(pos)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.surface.color(ray, pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        Some((0, &self.surface, pos))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.surface.bounds()
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface.lipschitz()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Expr(self.source.clone()))
+    }
+}
+pub fn expression(source: String, surface: Surface) -> Surface {
+    Arc::new(Expression::new(source, surface))
+}
+
+//
+// Syntax
+//
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pos {
+    line: usize,
+    col: usize,
+}
+
+#[derive(Debug)]
+struct ExprError {
+    pos: Pos,
+    message: String,
+}
+
+impl ExprError {
+    fn new(pos: Pos, message: String) -> Self {
+        Self { pos, message }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    Text(String),
+    Symbol(char),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Ident(ident) => write!(f, "{}", ident),
+            Token::Text(text) => write!(f, "{:?}", text),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+/// Token with its position and byte range in the source
+struct Spanned {
+    token: Token,
+    pos: Pos,
+    span: (usize, usize),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Spanned>, ExprError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let (mut line, mut line_start) = (1, 0);
+
+    while let Some(&(start, c)) = chars.peek() {
+        let pos = Pos {
+            line,
+            col: text[line_start..start].chars().count() + 1,
+        };
+        let token = match c {
+            '\n' => {
+                chars.next();
+                line += 1;
+                line_start = start + 1;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '#' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = start;
+                while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_ascii_digit() || c == '.') {
+                    end = i + c.len_utf8();
+                }
+                let number = &text[start..end];
+                Token::Number(
+                    number.parse().map_err(|_| {
+                        ExprError::new(pos, format!("invalid number \"{}\"", number))
+                    })?,
+                )
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_') {
+                    end = i + c.len_utf8();
+                }
+                Token::Ident(text[start..end].to_string())
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\n')) | None => {
+                            return Err(ExprError::new(pos, "unterminated string".to_string()))
+                        }
+                        Some((_, c)) => string.push(c),
+                    }
+                }
+                Token::Text(string)
+            }
+            '(' | ')' | ',' | ';' | '=' | '+' | '-' | '*' | '/' | '@' => {
+                chars.next();
+                Token::Symbol(c)
+            }
+            c => return Err(ExprError::new(pos, format!("unexpected character '{}'", c))),
+        };
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+        tokens.push(Spanned {
+            token,
+            pos,
+            span: (start, end),
+        });
+    }
+
+    let col = text[line_start..].chars().count() + 1;
+    tokens.push(Spanned {
+        token: Token::End,
+        pos: Pos { line, col },
+        span: (text.len(), text.len()),
+    });
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Translate,
+}
+
+impl BinaryOp {
+    fn symbol(self) -> char {
+        match self {
+            BinaryOp::Add => '+',
+            BinaryOp::Sub => '-',
+            BinaryOp::Mul => '*',
+            BinaryOp::Div => '/',
+            BinaryOp::Translate => '@',
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    pos: Pos,
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Number(f32),
+    Text(String),
+    Variable(String),
+    Vector(Vec<Node>),
+    Call(String, Vec<Node>),
+    Negate(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Node,
+}
+
+struct Statement {
+    kind: StatementKind,
+    /// Byte range in the source
+    span: (usize, usize),
+}
+
+enum StatementKind {
+    Let(String, Node),
+    Fn(Function),
+}
+
+struct Program {
+    statements: Vec<Statement>,
+    /// Final expression with its byte range in the source
+    result: Option<(Node, (usize, usize))>,
+}
+
+/// Recursive descent parser, from lowest to highest precedence:
+/// + -, * /, unary -, @, calls and parentheses
+struct Parser {
+    tokens: Vec<Spanned>,
+    next: usize,
+    /// Parentheses, calls and negations currently open
+    depth: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, ExprError> {
+        Ok(Self {
+            tokens: tokenize(text)?,
+            next: 0,
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].token
+    }
+
+    fn pos(&self) -> Pos {
+        self.tokens[self.next].pos
+    }
+
+    fn advance(&mut self) -> &Spanned {
+        let spanned = &self.tokens[self.next];
+        self.next = (self.next + 1).min(self.tokens.len() - 1);
+        spanned
+    }
+
+    /// Consumes the next token if it is symbol
+    fn accept(&mut self, symbol: char) -> bool {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ExprError> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ExprError {
+        ExprError::new(
+            self.pos(),
+            format!("expected {}, found {}", expected, self.peek()),
+        )
+    }
+
+    fn ident(&mut self) -> Result<String, ExprError> {
+        match self.peek().clone() {
+            Token::Ident(ident) if !is_keyword(&ident) => {
+                self.advance();
+                Ok(ident)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    /// Runs parse one level deeper, recursive descent would overflow the stack on deep nesting
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ExprError>,
+    ) -> Result<T, ExprError> {
+        if self.depth >= MAX_NESTING {
+            return Err(ExprError::new(
+                self.pos(),
+                format!("expressions nested deeper than {}", MAX_NESTING),
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// End of the byte range of the previous token
+    fn previous_end(&self) -> usize {
+        self.tokens[self.next.saturating_sub(1)].span.1
+    }
+
+    fn program(mut self) -> Result<Program, ExprError> {
+        let mut statements = Vec::new();
+        loop {
+            let start = self.tokens[self.next].span.0;
+            let kind = match self.peek() {
+                Token::Ident(keyword) if keyword == "let" => {
+                    self.advance();
+                    let name = self.ident()?;
+                    self.expect('=')?;
+                    StatementKind::Let(name, self.expr()?)
+                }
+                Token::Ident(keyword) if keyword == "fn" => {
+                    self.advance();
+                    let name = self.ident()?;
+                    self.expect('(')?;
+                    let mut params = Vec::new();
+                    if !self.accept(')') {
+                        loop {
+                            params.push(self.ident()?);
+                            if self.accept(')') {
+                                break;
+                            }
+                            if !self.accept(',') {
+                                return Err(self.unexpected("',' or ')'"));
+                            }
+                        }
+                    }
+                    self.expect('=')?;
+                    StatementKind::Fn(Function {
+                        name,
+                        params,
+                        body: self.expr()?,
+                    })
+                }
+                _ => break,
+            };
+            self.expect(';')?;
+            statements.push(Statement {
+                kind,
+                span: (start, self.previous_end()),
+            });
+        }
+
+        let result = if *self.peek() == Token::End {
+            None
+        } else {
+            let start = self.tokens[self.next].span.0;
+            let node = self.expr()?;
+            let end = self.previous_end();
+            self.accept(';');
+            Some((node, (start, end)))
+        };
+        if *self.peek() != Token::End {
+            return Err(self.unexpected("an operator or end of input"));
+        }
+        Ok(Program { statements, result })
+    }
+
+    fn expr(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.term()?;
+        loop {
+            let pos = self.pos();
+            let op = match self.peek() {
+                Token::Symbol('+') => BinaryOp::Add,
+                Token::Symbol('-') => BinaryOp::Sub,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = binary_node(op, node, self.term()?, pos);
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.unary()?;
+        loop {
+            let pos = self.pos();
+            let op = match self.peek() {
+                Token::Symbol('*') => BinaryOp::Mul,
+                Token::Symbol('/') => BinaryOp::Div,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = binary_node(op, node, self.unary()?, pos);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        let pos = self.pos();
+        if self.accept('-') {
+            return Ok(Node {
+                kind: NodeKind::Negate(Box::new(self.nested(Self::unary)?)),
+                pos,
+            });
+        }
+        self.translate()
+    }
+
+    fn translate(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.primary()?;
+        loop {
+            let pos = self.pos();
+            if !self.accept('@') {
+                return Ok(node);
+            }
+            node = binary_node(BinaryOp::Translate, node, self.primary()?, pos);
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, ExprError> {
+        let pos = self.pos();
+        let kind = match self.peek().clone() {
+            Token::Number(number) => {
+                self.advance();
+                NodeKind::Number(number)
+            }
+            Token::Text(text) => {
+                self.advance();
+                NodeKind::Text(text)
+            }
+            Token::Ident(_) => {
+                let name = self.ident()?;
+                if self.accept('(') {
+                    NodeKind::Call(name, self.nested(Self::list)?)
+                } else {
+                    NodeKind::Variable(name)
+                }
+            }
+            Token::Symbol('(') => {
+                self.advance();
+                let mut nodes = self.nested(Self::list)?;
+                match nodes.len() {
+                    1 => return Ok(nodes.pop().unwrap()),
+                    3 => NodeKind::Vector(nodes),
+                    _ => {
+                        return Err(ExprError::new(
+                            pos,
+                            "expected an expression in parentheses or a vector (x, y, z)"
+                                .to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        Ok(Node { kind, pos })
+    }
+
+    /// Comma separated expressions up to and including the closing parenthesis
+    fn list(&mut self) -> Result<Vec<Node>, ExprError> {
+        let mut nodes = Vec::new();
+        if self.accept(')') {
+            return Ok(nodes);
+        }
+        loop {
+            nodes.push(self.expr()?);
+            if self.accept(')') {
+                return Ok(nodes);
+            }
+            if !self.accept(',') {
+                return Err(self.unexpected("',' or ')'"));
+            }
+        }
+    }
+}
+
+fn binary_node(op: BinaryOp, lhs: Node, rhs: Node, pos: Pos) -> Node {
+    Node {
+        kind: NodeKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        pos,
+    }
+}
+
+fn is_keyword(ident: &str) -> bool {
+    ident == "let" || ident == "fn"
+}
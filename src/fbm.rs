@@ -0,0 +1,200 @@
+use glam::Vec3;
+use noise::{NoiseFn, Perlin, SuperSimplex, Value};
+use serde::{Deserialize, Serialize};
+
+pub const FBM_OCTAVES: u32 = 4;
+pub const FBM_LACUNARITY: f32 = 2.0;
+pub const FBM_GAIN: f32 = 0.5;
+
+// Most each kind of noise changes per unit moved, derived from the kernels of the noise crate.
+// The quintic fade 6t^5-15t^4+10t^3 changes by at most 15/8, at t = 1/2.
+//
+// Perlin blends the dot products of the 8 corner gradients, which have two components of ±1,
+// and scales by 2/sqrt(3). Along an axis the blended gradients add at most 1 and the fade adds
+// at most 15/8 times the weighted dot products of both faces. Summed over both faces those are
+// at most 1 from the axis itself and 1 from each of the other two, as the fade weighs the far
+// corner less. So every axis is below (1 + 3 * 15/8) * 2/sqrt(3) and the gradient below sqrt(3)
+// times that
+const PERLIN_GRADIENT: f32 = 2.0 * (1.0 + 3.0 * 15.0 / 8.0);
+// SuperSimplex sums kernels (3/4 - r^2)^4 * dot(g, d) with unit gradients g at the points of a
+// body centered lattice, 4 from each half, and scales by 1/0.0867664. The gradient of a kernel is
+// at most (3/4)^4 at r = 0 and 3/16 from r = sqrt(3)/4 on. The lattice points are sqrt(3)/2 apart,
+// so only one of the 8 is closer than that. Skewing to the lattice is a reflection, so it doesn't
+// stretch distances
+const SIMPLEX_GRADIENT: f32 = (81.0 / 256.0 + 7.0 * 3.0 / 16.0) / 0.086_766_4;
+// Value blends random values in [0,1] and maps them to [-1,1], so every axis changes by at most
+// 2 * 15/8 and the gradient by sqrt(3) times that
+const VALUE_GRADIENT: f32 = 2.0 * 15.0 / 8.0 * 1.732_050_8;
+
+/// Noise that every octave of fBm is made of, all stay within [-1,1]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoiseKind {
+    #[default]
+    Perlin,
+    /// SuperSimplex, fewer grid artifacts than Perlin
+    Simplex,
+    /// Blends random values at grid points, blocky at low octaves
+    Value,
+}
+
+impl NoiseKind {
+    fn max_gradient(self) -> f32 {
+        match self {
+            NoiseKind::Perlin => PERLIN_GRADIENT,
+            NoiseKind::Simplex => SIMPLEX_GRADIENT,
+            NoiseKind::Value => VALUE_GRADIENT,
+        }
+    }
+}
+
+/// Fractal Brownian motion, octaves of noise at rising frequencies and falling amplitudes
+///
+/// Every octave multiplies the frequency by lacunarity and the amplitude by gain. Ridged noise
+/// folds every octave into [0,1] with sharp crests where the noise crosses zero
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FbmSettings {
+    pub kind: NoiseKind,
+    /// Every octave uses the next seed
+    pub seed: u32,
+    /// Of the first octave
+    pub frequency: f32,
+    /// Most the sum of all octaves reaches
+    pub amplitude: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub ridged: bool,
+}
+
+impl Default for FbmSettings {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Perlin,
+            seed: 0,
+            frequency: 1.0,
+            amplitude: 1.0,
+            octaves: FBM_OCTAVES,
+            lacunarity: FBM_LACUNARITY,
+            gain: FBM_GAIN,
+            ridged: false,
+        }
+    }
+}
+
+impl FbmSettings {
+    pub fn new(frequency: f32, amplitude: f32) -> Self {
+        Self {
+            frequency,
+            amplitude,
+            ..Self::default()
+        }
+    }
+}
+
+enum Source {
+    Perlin(Perlin),
+    Simplex(SuperSimplex),
+    Value(Value),
+}
+
+impl Source {
+    fn new(kind: NoiseKind, seed: u32) -> Self {
+        match kind {
+            NoiseKind::Perlin => Source::Perlin(Perlin::new(seed)),
+            NoiseKind::Simplex => Source::Simplex(SuperSimplex::new(seed)),
+            NoiseKind::Value => Source::Value(Value::new(seed)),
+        }
+    }
+
+    fn get(&self, pos: Vec3) -> f32 {
+        let point = pos.as_dvec3().to_array();
+        let value = match self {
+            Source::Perlin(noise) => noise.get(point),
+            Source::Simplex(noise) => noise.get(point),
+            Source::Value(noise) => noise.get(point),
+        };
+        value as f32
+    }
+}
+
+//
+// fBm
+//
+
+pub struct Fbm {
+    settings: FbmSettings,
+    /// One per octave
+    sources: Vec<Source>,
+    /// Sum of the octave amplitudes before scaling, keeps the total within amplitude
+    total_weight: f32,
+}
+
+impl Fbm {
+    pub fn new(settings: FbmSettings) -> Self {
+        let sources = (0..settings.octaves)
+            .map(|octave| Source::new(settings.kind, settings.seed.wrapping_add(octave)))
+            .collect();
+        let total_weight = (0..settings.octaves)
+            .map(|octave| settings.gain.abs().powi(octave as i32))
+            .sum();
+        Self {
+            settings,
+            sources,
+            total_weight,
+        }
+    }
+
+    pub fn settings(&self) -> FbmSettings {
+        self.settings
+    }
+
+    /// Noise at pos, within [-amplitude, amplitude] or [0, amplitude] for ridged noise
+    pub fn get(&self, pos: Vec3) -> f32 {
+        if self.sources.is_empty() {
+            return 0.0;
+        }
+        let mut sum = 0.0;
+        let mut frequency = self.settings.frequency;
+        let mut weight = 1.0;
+        for source in &self.sources {
+            let value = source.get(pos * frequency);
+            let value = if self.settings.ridged {
+                1.0 - value.abs()
+            } else {
+                value
+            };
+            sum += value * weight;
+            frequency *= self.settings.lacunarity;
+            weight *= self.settings.gain;
+        }
+        sum / self.total_weight * self.settings.amplitude
+    }
+
+    /// Most get returns in either direction
+    pub fn max_value(&self) -> f32 {
+        self.settings.amplitude.abs()
+    }
+
+    /// Most get changes per unit moved
+    ///
+    /// Every octave is bounded by the gradient of its kind of noise, so surfaces stepped with it
+    /// never overshoot, though steps are shorter than the noise usually needs
+    pub fn max_gradient(&self) -> f32 {
+        if self.sources.is_empty() {
+            return 0.0;
+        }
+        let FbmSettings {
+            frequency,
+            lacunarity,
+            gain,
+            ..
+        } = self.settings;
+        // Every octave is as steep as its frequency times its weight
+        let octaves = (0..self.settings.octaves)
+            .map(|octave| (gain * lacunarity).abs().powi(octave as i32))
+            .sum::<f32>();
+        self.settings.kind.max_gradient() * frequency.abs() * octaves / self.total_weight
+            * self.max_value()
+    }
+}
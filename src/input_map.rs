@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+};
+
+use pixelated::{
+    input::{self, KeyCode},
+    Context,
+};
+
+/// Something the viewer can do in response to a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    /// Held to move faster, see InputMap::fast_multiplier
+    MoveFast,
+    /// Held to pan the orbit camera instead of rotating it
+    Pan,
+    LightForward,
+    LightBack,
+    LightLeft,
+    LightRight,
+    LightUp,
+    LightDown,
+    Pause,
+    Screenshot,
+    ToggleCamera,
+    FrameScene,
+    ViewShaded,
+    ViewSteps,
+    ViewStepLimit,
+    ViewNormals,
+    ViewDepth,
+    /// Enters the slice view, pressing again cycles through the axes
+    ViewSlice,
+    SliceForward,
+    SliceBack,
+    ToggleRecording,
+    TogglePlayback,
+    /// Shows render stats in the top left corner
+    ToggleStats,
+    SaveStats,
+}
+
+/// Actions with their name in the config file
+const ACTIONS: [(Action, &str); 30] = [
+    (Action::MoveForward, "move_forward"),
+    (Action::MoveBack, "move_back"),
+    (Action::MoveLeft, "move_left"),
+    (Action::MoveRight, "move_right"),
+    (Action::MoveUp, "move_up"),
+    (Action::MoveDown, "move_down"),
+    (Action::MoveFast, "move_fast"),
+    (Action::Pan, "pan"),
+    (Action::LightForward, "light_forward"),
+    (Action::LightBack, "light_back"),
+    (Action::LightLeft, "light_left"),
+    (Action::LightRight, "light_right"),
+    (Action::LightUp, "light_up"),
+    (Action::LightDown, "light_down"),
+    (Action::Pause, "pause"),
+    (Action::Screenshot, "screenshot"),
+    (Action::ToggleCamera, "toggle_camera"),
+    (Action::FrameScene, "frame_scene"),
+    (Action::ViewShaded, "view_shaded"),
+    (Action::ViewSteps, "view_steps"),
+    (Action::ViewStepLimit, "view_step_limit"),
+    (Action::ViewNormals, "view_normals"),
+    (Action::ViewDepth, "view_depth"),
+    (Action::ViewSlice, "view_slice"),
+    (Action::SliceForward, "slice_forward"),
+    (Action::SliceBack, "slice_back"),
+    (Action::ToggleRecording, "toggle_recording"),
+    (Action::TogglePlayback, "toggle_playback"),
+    (Action::ToggleStats, "toggle_stats"),
+    (Action::SaveStats, "save_stats"),
+];
+
+impl Action {
+    /// Name used in the config file
+    pub fn name(&self) -> &'static str {
+        ACTIONS.iter().find(|(action, _)| action == self).unwrap().1
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(_, action_name)| *action_name == name)
+            .map(|(action, _)| *action)
+    }
+}
+
+/// Generates key_name and key_from_name for the listed KeyCode variants
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        /// Name used in the config file, the name of the KeyCode variant
+        pub fn key_name(key: KeyCode) -> Option<&'static str> {
+            match key {
+                $(KeyCode::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+
+        pub fn key_from_name(name: &str) -> Option<KeyCode> {
+            match name {
+                $(stringify!($key) => Some(KeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+key_names! {
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Tab, Space, Back, Return, Insert, Delete, Home, End, PageUp, PageDown,
+    Left, Up, Right, Down,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, RBracket, Minus, Period, Plus,
+    Semicolon, Slash,
+}
+
+/// Keys bound to each action and the speeds of the camera controls
+///
+/// Saved as text with one "action = key, key" or "setting = value" line each, lines starting
+/// with # are comments. Keys are named after their KeyCode, like W, LShift or PageUp
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<KeyCode>>,
+    /// Units per second the camera and light move
+    pub move_speed: f32,
+    /// Radians per pixel the mouse moves
+    pub rotate_speed: f32,
+    /// Move speed multiplier while MoveFast is held
+    pub fast_multiplier: f32,
+    /// Move speed multiplier per step of the scroll wheel
+    pub scroll_multiplier: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut input_map = Self {
+            bindings: HashMap::new(),
+            move_speed: 2.0,
+            rotate_speed: 0.001,
+            fast_multiplier: 4.0,
+            scroll_multiplier: 1.25,
+        };
+        for (action, keys) in [
+            (Action::MoveForward, &[KeyCode::W][..]),
+            (Action::MoveBack, &[KeyCode::S]),
+            (Action::MoveLeft, &[KeyCode::A]),
+            (Action::MoveRight, &[KeyCode::D]),
+            (Action::MoveUp, &[KeyCode::X]),
+            (Action::MoveDown, &[KeyCode::Z]),
+            (Action::MoveFast, &[KeyCode::LShift]),
+            (Action::Pan, &[KeyCode::LShift, KeyCode::RShift]),
+            (Action::LightForward, &[KeyCode::Up]),
+            (Action::LightBack, &[KeyCode::Down]),
+            (Action::LightLeft, &[KeyCode::Left]),
+            (Action::LightRight, &[KeyCode::Right]),
+            (Action::LightUp, &[KeyCode::Home]),
+            (Action::LightDown, &[KeyCode::End]),
+            (Action::Pause, &[KeyCode::Escape]),
+            (Action::Screenshot, &[KeyCode::Space]),
+            (Action::ToggleCamera, &[KeyCode::Tab]),
+            (Action::FrameScene, &[KeyCode::F]),
+            (Action::ViewShaded, &[KeyCode::F1]),
+            (Action::ViewSteps, &[KeyCode::F2]),
+            (Action::ViewStepLimit, &[KeyCode::F3]),
+            (Action::ViewNormals, &[KeyCode::F4]),
+            (Action::ViewDepth, &[KeyCode::F5]),
+            (Action::ViewSlice, &[KeyCode::F6]),
+            (Action::SliceForward, &[KeyCode::PageUp]),
+            (Action::SliceBack, &[KeyCode::PageDown]),
+            (Action::ToggleRecording, &[KeyCode::R]),
+            (Action::TogglePlayback, &[KeyCode::P]),
+            (Action::ToggleStats, &[KeyCode::F7]),
+            (Action::SaveStats, &[KeyCode::F8]),
+        ] {
+            input_map.bind(action, keys);
+        }
+        input_map
+    }
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the keys bound to action, an empty slice unbinds it
+    pub fn bind(&mut self, action: Action, keys: &[KeyCode]) {
+        self.bindings.insert(action, keys.to_vec());
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], |keys| keys)
+    }
+
+    /// True while any key bound to action is held
+    pub fn pressed(&self, ctx: &Context, action: Action) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|key| input::key_pressed(ctx, *key))
+    }
+
+    /// True on the frame any key bound to action is pressed
+    pub fn just_pressed(&self, ctx: &Context, action: Action) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|key| input::key_just_pressed(ctx, *key))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut text = String::new();
+        text += &format!("move_speed = {}\n", self.move_speed);
+        text += &format!("rotate_speed = {}\n", self.rotate_speed);
+        text += &format!("fast_multiplier = {}\n", self.fast_multiplier);
+        text += &format!("scroll_multiplier = {}\n", self.scroll_multiplier);
+        for (action, name) in ACTIONS {
+            let keys = self
+                .keys(action)
+                .iter()
+                .filter_map(|key| key_name(*key))
+                .collect::<Vec<_>>();
+            text += &format!("{} = {}\n", name, keys.join(", "));
+        }
+        fs::write(path, text)
+    }
+
+    /// Loads a config file, actions and settings not in the file keep their default
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut input_map = InputMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path, i + 1, message),
+                )
+            };
+
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(error("expected \"action = key, key\"".to_string()));
+            };
+            let (name, value) = (name.trim(), value.trim());
+            let setting = match name {
+                "move_speed" => Some(&mut input_map.move_speed),
+                "rotate_speed" => Some(&mut input_map.rotate_speed),
+                "fast_multiplier" => Some(&mut input_map.fast_multiplier),
+                "scroll_multiplier" => Some(&mut input_map.scroll_multiplier),
+                _ => None,
+            };
+            if let Some(setting) = setting {
+                *setting = value
+                    .parse()
+                    .map_err(|_| error(format!("invalid number \"{}\"", value)))?;
+                continue;
+            }
+
+            let action = Action::from_name(name)
+                .ok_or_else(|| error(format!("unknown action \"{}\"", name)))?;
+            let keys = value
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(|key| {
+                    key_from_name(key).ok_or_else(|| error(format!("unknown key \"{}\"", key)))
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            input_map.bind(action, &keys);
+        }
+        Ok(input_map)
+    }
+}
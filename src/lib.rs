@@ -1,3 +1,12 @@
+pub mod camera_path;
+pub mod expr;
+pub mod fbm;
+pub mod input_map;
 pub mod materials;
+pub mod mesh;
 pub mod raymarcher;
+pub mod scene;
+pub mod scene_file;
+pub mod stats;
 pub mod surfaces;
+pub mod voxels;
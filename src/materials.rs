@@ -1,5 +1,12 @@
+use std::sync::Arc;
+
 use glam::{vec3, Vec3};
-use image::{DynamicImage, GenericImageView, Pixel};
+use image::{DynamicImage, GenericImageView, ImageResult, Pixel};
+
+use crate::{
+    scene_file::MaterialDesc,
+    surfaces::{interpolate_vec3, Material},
+};
 
 pub const RED: Vec3 = vec3(1.0, 0.0, 0.0);
 pub const GREEN: Vec3 = vec3(0.0, 1.0, 0.0);
@@ -10,6 +17,93 @@ pub const PINK: Vec3 = vec3(1.0, 0.5, 0.5);
 
 pub trait MaterialTrait {
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3;
+
+    /// Color on a fractal, which calls this instead of color with the orbit trap at pos
+    fn color_with_trap(
+        &self,
+        ray: Vec3,
+        pos: Vec3,
+        normal: Vec3,
+        light_pos: Vec3,
+        _trap: &OrbitTrap,
+    ) -> Vec3 {
+        self.color(ray, pos, normal, light_pos)
+    }
+
+    /// Description for the scene file format, None for materials that can't be saved
+    fn describe(&self) -> Option<MaterialDesc> {
+        None
+    }
+}
+
+/// Point on a surface being colored, the arguments of MaterialTrait::color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadingPoint {
+    /// Direction of the ray that hit the surface
+    pub ray: Vec3,
+    pub pos: Vec3,
+    pub normal: Vec3,
+    pub light_pos: Vec3,
+    /// Set on fractals, see MaterialTrait::color_with_trap
+    pub trap: Option<OrbitTrap>,
+}
+
+/// How close the orbit of a point came to a few shapes while iterating a fractal
+///
+/// Points next to each other on the surface have similar traps, which makes them useful for
+/// coloring
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OrbitTrap {
+    /// Smallest distance to the origin
+    pub origin: f32,
+    /// Smallest distances to the yz, xz and xy planes
+    pub planes: Vec3,
+    /// Iterations done before the orbit escaped, divided by the maximum, in range [0,1]
+    pub escape: f32,
+}
+
+/// Material with the color given by a closure
+pub struct FnMaterial<F> {
+    color: F,
+}
+
+impl<F: Fn(&ShadingPoint) -> Vec3 + Sync + Send> FnMaterial<F> {
+    pub fn new(color: F) -> Self {
+        Self { color }
+    }
+}
+
+impl<F: Fn(&ShadingPoint) -> Vec3 + Sync + Send> MaterialTrait for FnMaterial<F> {
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        (self.color)(&ShadingPoint {
+            ray,
+            pos,
+            normal,
+            light_pos,
+            trap: None,
+        })
+    }
+
+    fn color_with_trap(
+        &self,
+        ray: Vec3,
+        pos: Vec3,
+        normal: Vec3,
+        light_pos: Vec3,
+        trap: &OrbitTrap,
+    ) -> Vec3 {
+        (self.color)(&ShadingPoint {
+            ray,
+            pos,
+            normal,
+            light_pos,
+            trap: Some(*trap),
+        })
+    }
+}
+/// Material with the color given by a closure, for example `from_fn(|p| p.normal.abs())`
+pub fn from_fn(color: impl Fn(&ShadingPoint) -> Vec3 + Sync + Send + 'static) -> Material {
+    Arc::new(FnMaterial::new(color))
 }
 
 /// Material that outputs a flat color
@@ -27,6 +121,10 @@ impl MaterialTrait for Unlit {
     fn color(&self, _ray: Vec3, _pos: Vec3, _normal: Vec3, _light_pos: Vec3) -> Vec3 {
         self.color
     }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Unlit(self.color))
+    }
 }
 
 /// Material that outputs the normal as a color
@@ -36,23 +134,73 @@ impl MaterialTrait for Normal {
     fn color(&self, _ray: Vec3, _pos: Vec3, normal: Vec3, _light_pos: Vec3) -> Vec3 {
         normal
     }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Normal)
+    }
+}
+
+/// Material that blends from near to far with the distance of the orbit trap to the origin
+///
+/// Only varies on fractals, elsewhere it is near
+pub struct TrapGradient {
+    near: Vec3,
+    far: Vec3,
+}
+
+impl TrapGradient {
+    pub fn new(near: Vec3, far: Vec3) -> Self {
+        Self { near, far }
+    }
+}
+
+impl MaterialTrait for TrapGradient {
+    fn color(&self, _ray: Vec3, _pos: Vec3, _normal: Vec3, _light_pos: Vec3) -> Vec3 {
+        self.near
+    }
+
+    fn color_with_trap(
+        &self,
+        _ray: Vec3,
+        _pos: Vec3,
+        _normal: Vec3,
+        _light_pos: Vec3,
+        trap: &OrbitTrap,
+    ) -> Vec3 {
+        interpolate_vec3(self.near, self.far, trap.origin.clamp(0.0, 1.0))
+    }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::TrapGradient {
+            near: self.near,
+            far: self.far,
+        })
+    }
 }
 
 // Material that samples from a pixel based of world position
 pub struct Textured {
     texture: Texture,
+    /// Kept so the material can be saved
+    texture_path: String,
     blend_sharpness: f32,
     scale: f32,
 }
 
 impl Textured {
     pub fn new(texture_path: &str) -> Self {
-        let texture = Texture::new(texture_path);
-        Self {
+        Self::load(texture_path).unwrap()
+    }
+
+    /// Like new, but returns an error instead of panicking if the texture can't be opened
+    pub fn load(texture_path: &str) -> ImageResult<Self> {
+        let texture = Texture::load(texture_path)?;
+        Ok(Self {
             texture,
+            texture_path: texture_path.to_string(),
             blend_sharpness: 1.0,
             scale: 1.0,
-        }
+        })
     }
 
     pub fn with_scale(mut self, scale: f32) -> Self {
@@ -78,6 +226,14 @@ impl MaterialTrait for Textured {
 
         x * weight.x + y * weight.y + z * weight.z
     }
+
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Textured {
+            path: self.texture_path.clone(),
+            scale: self.scale,
+            blend_sharpness: self.blend_sharpness,
+        })
+    }
 }
 
 /// Uses repeating if sampled outside unit quad
@@ -87,8 +243,12 @@ pub struct Texture {
 
 impl Texture {
     pub fn new(path: &str) -> Self {
-        let image = image::open(path).unwrap();
-        Self { image }
+        Self::load(path).unwrap()
+    }
+
+    pub fn load(path: &str) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        Ok(Self { image })
     }
 
     /// Returns the color of the pixel at world position (x,y)
@@ -0,0 +1,469 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use glam::Vec3;
+
+use crate::surfaces::Aabb;
+
+/// Nodes with at most this many triangles aren't split further
+const BVH_LEAF_SIZE: usize = 4;
+/// Every level of the tree adds at most one node to the stack of a query, and median splits keep
+/// the depth at log2 of the triangle count
+const BVH_STACK_SIZE: usize = 64;
+/// Size of the header and the triangle count at the start of a binary STL file
+const STL_HEADER_SIZE: usize = 84;
+/// Normal, 3 vertices and the attribute byte count
+const STL_TRIANGLE_SIZE: usize = 50;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+//
+// Mesh
+//
+
+/// Triangle mesh with a bounding volume hierarchy for closest point queries
+///
+/// Vertices at the same position are merged, so the sign of the distance is only right for closed
+/// meshes where every edge is shared by two triangles
+pub struct Mesh {
+    vertices: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+    face_normals: Vec<Vec3>,
+    /// Angle weighted normals of the vertices
+    vertex_normals: Vec<Vec3>,
+    /// Sum of the normals of the faces next to each edge, in the order ab, bc, ca
+    edge_normals: Vec<[Vec3; 3]>,
+    bvh: Vec<BvhNode>,
+}
+
+/// Part of the triangle the closest point is on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Feature {
+    Face,
+    /// Edge starting at the given corner
+    Edge(usize),
+    Vertex(usize),
+}
+
+impl Mesh {
+    /// Builds a mesh from corners of triangles, triangles without area are skipped
+    pub fn new(triangles: impl IntoIterator<Item = [Vec3; 3]>) -> Self {
+        let mut vertices = Vec::new();
+        let mut indices = HashMap::new();
+        let mut indexed = Vec::new();
+        for corners in triangles {
+            let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            if normal.length_squared() == 0.0 || !normal.is_finite() {
+                continue;
+            }
+            indexed.push(corners.map(|corner| {
+                let key = corner.to_array().map(f32::to_bits);
+                *indices.entry(key).or_insert_with(|| {
+                    vertices.push(corner);
+                    vertices.len() as u32 - 1
+                })
+            }));
+        }
+
+        let mut mesh = Self {
+            vertices,
+            triangles: indexed,
+            face_normals: Vec::new(),
+            vertex_normals: Vec::new(),
+            edge_normals: Vec::new(),
+            bvh: Vec::new(),
+        };
+        mesh.compute_normals();
+        mesh.build_bvh();
+        mesh
+    }
+
+    /// Loads an OBJ or STL file, chosen by the extension
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("obj") => Self::parse_obj(&fs::read_to_string(path)?),
+            Some("stl") => Self::parse_stl(&fs::read(path)?),
+            _ => Err(invalid_data(format!(
+                "{}: unknown mesh format, expected .obj or .stl",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Reads the vertices and faces of a Wavefront OBJ file, faces with more than 3 corners are
+    /// split into fans
+    pub fn parse_obj(text: &str) -> io::Result<Self> {
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let error =
+                |message: &str| invalid_data(format!("line {}: {message}", line_number + 1));
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let coords = words
+                        .take(3)
+                        .map(str::parse)
+                        .collect::<Result<Vec<f32>, _>>()
+                        .map_err(|_| error("invalid vertex"))?;
+                    if coords.len() != 3 {
+                        return Err(error("vertex needs 3 coordinates"));
+                    }
+                    positions.push(Vec3::from_slice(&coords));
+                }
+                Some("f") => {
+                    let corners = words
+                        .map(|word| {
+                            // Only the position of v/vt/vn
+                            let index: i64 = word
+                                .split('/')
+                                .next()
+                                .unwrap()
+                                .parse()
+                                .map_err(|_| error("invalid face index"))?;
+                            // Negative indices count back from the last vertex
+                            let index = if index < 0 {
+                                positions.len() as i64 + index
+                            } else {
+                                index - 1
+                            };
+                            positions
+                                .get(index as usize)
+                                .copied()
+                                .filter(|_| index >= 0)
+                                .ok_or_else(|| error("face index out of range"))
+                        })
+                        .collect::<io::Result<Vec<_>>>()?;
+                    if corners.len() < 3 {
+                        return Err(error("face needs at least 3 corners"));
+                    }
+                    for i in 1..corners.len() - 1 {
+                        triangles.push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self::new(triangles))
+    }
+
+    /// Reads a binary or ASCII STL file
+    pub fn parse_stl(bytes: &[u8]) -> io::Result<Self> {
+        // ASCII files start with "solid", but so do the headers of some binary files
+        if bytes.len() >= STL_HEADER_SIZE {
+            let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+            if bytes.len() == STL_HEADER_SIZE + count * STL_TRIANGLE_SIZE {
+                return Ok(Self::parse_binary_stl(&bytes[STL_HEADER_SIZE..]));
+            }
+        }
+        if !bytes.starts_with(b"solid") {
+            return Err(invalid_data(
+                "not an STL file, the size doesn't match the triangle count".to_string(),
+            ));
+        }
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| invalid_data("ASCII STL is not valid UTF-8".to_string()))?;
+        Self::parse_ascii_stl(text)
+    }
+
+    fn parse_binary_stl(bytes: &[u8]) -> Self {
+        let float = |bytes: &[u8]| f32::from_le_bytes(bytes.try_into().unwrap());
+        let triangles = bytes.chunks_exact(STL_TRIANGLE_SIZE).map(|triangle| {
+            // Skips the stored normal, it is recomputed from the winding
+            [0, 1, 2].map(|corner| {
+                let start = 12 + corner * 12;
+                let v = &triangle[start..start + 12];
+                Vec3::new(float(&v[0..4]), float(&v[4..8]), float(&v[8..12]))
+            })
+        });
+        Self::new(triangles)
+    }
+
+    fn parse_ascii_stl(text: &str) -> io::Result<Self> {
+        let mut corners = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("vertex") {
+                continue;
+            }
+            let coords = words
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()
+                .ok()
+                .filter(|coords| coords.len() == 3)
+                .ok_or_else(|| invalid_data(format!("line {}: invalid vertex", line_number + 1)))?;
+            corners.push(Vec3::from_slice(&coords));
+        }
+        if corners.len() % 3 != 0 {
+            return Err(invalid_data(format!(
+                "{} vertices don't make whole triangles",
+                corners.len()
+            )));
+        }
+        Ok(Self::new(
+            corners
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]]),
+        ))
+    }
+
+    /// Number of triangles left after skipping those without area
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Number of distinct vertex positions
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// None for meshes without triangles
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bvh.first().map(|node| node.bounds)
+    }
+
+    /// Signed distance to the closest triangle, negative inside
+    ///
+    /// The sign comes from the angle weighted pseudo normal of the closest feature, which is
+    /// right for closed meshes even at edges and corners
+    pub fn signed_distance(&self, pos: Vec3) -> f32 {
+        let Some((dist_squared, point, normal)) = self.closest_point(pos) else {
+            return f32::INFINITY;
+        };
+        let dist = dist_squared.sqrt();
+        if (pos - point).dot(normal) < 0.0 {
+            -dist
+        } else {
+            dist
+        }
+    }
+
+    /// Squared distance to, position of and pseudo normal at the closest point on the mesh
+    fn closest_point(&self, pos: Vec3) -> Option<(f32, Vec3, Vec3)> {
+        let mut best: Option<(f32, Vec3, usize, Feature)> = None;
+        let mut best_dist = f32::INFINITY;
+        // Nodes to visit with the squared distance to their boxes
+        let mut stack = [(0, 0.0); BVH_STACK_SIZE];
+        let mut len = usize::from(!self.bvh.is_empty());
+
+        while len > 0 {
+            len -= 1;
+            let (index, box_dist) = stack[len];
+            if box_dist >= best_dist {
+                continue;
+            }
+            match self.bvh[index].children {
+                Children::Leaf { start, end } => {
+                    for triangle in start..end {
+                        let (point, feature) = self.closest_on_triangle(triangle, pos);
+                        let dist = pos.distance_squared(point);
+                        if dist < best_dist {
+                            best_dist = dist;
+                            best = Some((dist, point, triangle, feature));
+                        }
+                    }
+                }
+                Children::Split { left, right } => {
+                    // Visit the closer child first so the other can often be skipped
+                    let left_dist = box_distance_squared(&self.bvh[left].bounds, pos);
+                    let right_dist = box_distance_squared(&self.bvh[right].bounds, pos);
+                    let (near, far) = if left_dist < right_dist {
+                        ((left, left_dist), (right, right_dist))
+                    } else {
+                        ((right, right_dist), (left, left_dist))
+                    };
+                    stack[len] = far;
+                    stack[len + 1] = near;
+                    len += 2;
+                }
+            }
+        }
+
+        let (dist, point, triangle, feature) = best?;
+        let normal = match feature {
+            Feature::Face => self.face_normals[triangle],
+            Feature::Edge(edge) => self.edge_normals[triangle][edge],
+            Feature::Vertex(corner) => {
+                self.vertex_normals[self.triangles[triangle][corner] as usize]
+            }
+        };
+        Some((dist, point, normal))
+    }
+
+    fn corners(&self, triangle: usize) -> [Vec3; 3] {
+        self.triangles[triangle].map(|i| self.vertices[i as usize])
+    }
+
+    /// Closest point on a triangle and the feature it lies on, from Real-Time Collision Detection
+    fn closest_on_triangle(&self, triangle: usize, p: Vec3) -> (Vec3, Feature) {
+        let [a, b, c] = self.corners(triangle);
+        let ab = b - a;
+        let ac = c - a;
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return (a, Feature::Vertex(0));
+        }
+
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return (b, Feature::Vertex(1));
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            let v = d1 / (d1 - d3);
+            return (a + v * ab, Feature::Edge(0));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return (c, Feature::Vertex(2));
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            let w = d2 / (d2 - d6);
+            return (a + w * ac, Feature::Edge(2));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+            return (b + w * (c - b), Feature::Edge(1));
+        }
+
+        let denom = 1.0 / (va + vb + vc);
+        let v = vb * denom;
+        let w = vc * denom;
+        (a + ab * v + ac * w, Feature::Face)
+    }
+
+    fn compute_normals(&mut self) {
+        self.face_normals = (0..self.triangles.len())
+            .map(|triangle| {
+                let [a, b, c] = self.corners(triangle);
+                (b - a).cross(c - a).normalize()
+            })
+            .collect();
+
+        self.vertex_normals = vec![Vec3::ZERO; self.vertices.len()];
+        let mut edge_sums = HashMap::new();
+        for (triangle, indices) in self.triangles.iter().enumerate() {
+            let corners = self.corners(triangle);
+            let normal = self.face_normals[triangle];
+            for corner in 0..3 {
+                let to_next = corners[(corner + 1) % 3] - corners[corner];
+                let to_prev = corners[(corner + 2) % 3] - corners[corner];
+                let angle = to_next.angle_between(to_prev);
+                self.vertex_normals[indices[corner] as usize] += angle * normal;
+
+                let edge = edge_key(indices[corner], indices[(corner + 1) % 3]);
+                *edge_sums.entry(edge).or_insert(Vec3::ZERO) += normal;
+            }
+        }
+
+        self.edge_normals = self
+            .triangles
+            .iter()
+            .map(|indices| {
+                [0, 1, 2]
+                    .map(|corner| edge_sums[&edge_key(indices[corner], indices[(corner + 1) % 3])])
+            })
+            .collect();
+    }
+
+    fn build_bvh(&mut self) {
+        if self.triangles.is_empty() {
+            return;
+        }
+        let centers = (0..self.triangles.len())
+            .map(|triangle| self.corners(triangle).iter().sum::<Vec3>() / 3.0)
+            .collect::<Vec<_>>();
+        let mut order = (0..self.triangles.len()).collect::<Vec<_>>();
+        self.build_node(&mut order, 0, &centers);
+
+        // Leaves refer to ranges, so the triangles are stored in the order of the tree
+        self.triangles = order.iter().map(|&i| self.triangles[i]).collect();
+        self.face_normals = order.iter().map(|&i| self.face_normals[i]).collect();
+        self.edge_normals = order.iter().map(|&i| self.edge_normals[i]).collect();
+    }
+
+    /// Adds the node for order[start..] and its children, returns its index
+    fn build_node(&mut self, order: &mut [usize], start: usize, centers: &[Vec3]) -> usize {
+        let bounds = Aabb::from_points(order.iter().flat_map(|&triangle| self.corners(triangle)));
+        let index = self.bvh.len();
+        self.bvh.push(BvhNode {
+            bounds,
+            children: Children::Leaf {
+                start,
+                end: start + order.len(),
+            },
+        });
+        if order.len() <= BVH_LEAF_SIZE {
+            return index;
+        }
+
+        // Median split along the longest side of the box around the centers
+        let center_bounds = Aabb::from_points(order.iter().map(|&i| centers[i]));
+        let size = center_bounds.size();
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        let middle = order.len() / 2;
+        order.select_nth_unstable_by(middle, |&a, &b| {
+            centers[a][axis].total_cmp(&centers[b][axis])
+        });
+
+        let (left_order, right_order) = order.split_at_mut(middle);
+        let left = self.build_node(left_order, start, centers);
+        let right = self.build_node(right_order, start + middle, centers);
+        self.bvh[index].children = Children::Split { left, right };
+        index
+    }
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+fn box_distance_squared(bounds: &Aabb, pos: Vec3) -> f32 {
+    let outside = (bounds.min - pos).max(pos - bounds.max).max(Vec3::ZERO);
+    outside.length_squared()
+}
+
+//
+// Bounding volume hierarchy
+//
+
+struct BvhNode {
+    bounds: Aabb,
+    children: Children,
+}
+
+enum Children {
+    /// Range of triangles
+    Leaf { start: usize, end: usize },
+    /// Indices of the child nodes
+    Split { left: usize, right: usize },
+}
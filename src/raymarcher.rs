@@ -1,42 +1,163 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    fs,
+    io::{self, ErrorKind},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::{Instant, SystemTime},
+};
+
 use glam::{vec3, vec4, Mat3, Vec2, Vec3, Vec4Swizzles};
+use image::{ImageResult, RgbaImage};
 use pixelated::{
-    canvas, input::{self, KeyCode}, media, window, Callbacks, Context
+    canvas, input::{self, MouseButton}, media, window, Callbacks, Context
+};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+
+use crate::{
+    camera_path::{CameraPath, CameraPose},
+    expr::{Env, Value},
+    input_map::{Action, InputMap},
+    materials::{BLUE, GREEN, RED, YELLOW},
+    scene::Scene,
+    scene_file::SceneFile,
+    stats::{draw_overlay, RenderStats, StatsLog},
+    surfaces::{interpolate_vec3, Aabb, Surface, SurfaceList},
 };
 
-use crate::surfaces::{Surface, SurfaceList};
-
-enum Shadows {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shadows {
     None,
     Hard,
+    /// Penumbra sharpness, higher is sharper
     Soft(f32),
 }
 
-enum Threading {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threading {
     Single,
+    /// One task per pixel
     ChunkMut(),
+    /// One task per chunk of the given number of pixels
     LineChunkMut(u32),
 }
 
-enum Antialiasing {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Antialiasing {
     None,
     AAx4,
 }
 
+/// Resolution and quality settings, the defaults come from the consts below
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub shadows: Shadows,
+    pub anti_aliasing: Antialiasing,
+    pub threading: Threading,
+    /// Threads used to render, 0 uses one per core
+    pub threads: usize,
+    /// Measures the march, shading and shadow times of RenderStats, reads the clock a few
+    /// times per ray
+    pub timings: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: WIDTH,
+            height: HEIGHT,
+            shadows: SHADOWS,
+            anti_aliasing: ANTI_ALIASING,
+            threading: THREADING,
+            threads: 0,
+            timings: false,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            ..Self::default()
+        }
+    }
+
+    /// Distance from the camera to the screen in pixels, gives a 90 degree vertical fov
+    pub fn focal_length(&self) -> f32 {
+        self.height as f32 / 2.0
+    }
+}
+
+/// Debug views that replace the shaded image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// Regular shaded output
+    None,
+    /// Heat map of the steps used out of MAX_STEPS
+    Steps,
+    /// Shaded output with rays that ran out of steps before converging highlighted
+    StepLimit,
+    /// Normal of the closest surface
+    Normals,
+    /// Distance along the ray, white is close and black is far
+    Depth,
+    /// Signed distance on a plane cutting through the scene
+    Slice { normal: Vec3, distance: f32 },
+}
+
+/// Camera controller, switched between with the toggle camera key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    /// Moves freely with the move keys and turns with the mouse
+    Fly,
+    /// Rotates around a target point
+    Orbit,
+}
+
+/// Orientation of the plane used by the slice debug view
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SliceAxis {
+    X,
+    Y,
+    Z,
+    /// Faces the camera and follows it around
+    Camera,
+}
+
 const WIDTH: u32 = 512;
 const HEIGHT: u32 = 512;
 // const WIDTH: u32 = 1920;
 // const HEIGHT: u32 = 1080;
 
-const FOCAL_LENGTH: f32 = HEIGHT as f32 / 2.0;
-
 const MAX_STEPS: u32 = 1000;
 const MAX_DISTANCE: f32 = 100.0;
 const SURFACE_DISTANCE: f32 = 0.0001;
 const EPSILON: f32 = SURFACE_DISTANCE * 0.1; // should be smaller than surface distance
 const SHADOW_STEP_DISTANCE: f32 = 0.005;
+/// Smallest lipschitz a surface is stepped with, a surface scaled by 0 would divide 0 by 0
+const MIN_LIPSCHITZ: f32 = 0.001;
+
+const SLICE_BAND_SPACING: f32 = 0.25;
+const SLICE_LINE_WIDTH: f32 = 0.01;
+const SLICE_GEOMETRY_OPACITY: f32 = 0.3;
+
+const SELECTION_TINT: Vec3 = YELLOW;
+const SELECTION_TINT_STRENGTH: f32 = 0.4;
 
-const CAMERA_MOVE_SPEED: f32 = 2.0;
-const CAMERA_ROTATE_SPEED: f32 = 0.001;
+const ORBIT_ROTATE_SPEED: f32 = 0.005;
+const ORBIT_ZOOM_SPEED: f32 = 1.1;
+const ORBIT_DEFAULT_DISTANCE: f32 = 5.0;
+
+const GRAVITY: f32 = 9.81;
+const GROUND_SNAP_DISTANCE: f32 = 0.05;
+const COLLISION_ITERATIONS: u32 = 4;
 
 // const ANTI_ALIASING: Antialiasing = Antialiasing::AAx4;
 const ANTI_ALIASING: Antialiasing = Antialiasing::None;
@@ -49,48 +170,66 @@ const THREADING: Threading = Threading::LineChunkMut(512);
 // const SHADOWS: Shadows = Shadows::Hard;
 const SHADOWS: Shadows = Shadows::Soft(16.0);
 
-const SHOW_FRAMERATE: bool = true;
+const STATS_FILE: &str = "outputs/stats.csv";
+/// Frames kept in the stats log, older frames are dropped
+const STATS_LOG_LENGTH: usize = 3600;
+
+const CAMERA_PATH_FILE: &str = "outputs/camera_path.txt";
+const INPUT_MAP_FILE: &str = "controls.txt";
+const SCENE_RELOAD_INTERVAL: f32 = 0.25;
 
 pub struct Raymarcher {
-    surfaces: SurfaceList,
+    scene: Scene,
     camera_pos: Vec3,
     camera_yaw: f32,
     light_pos: Vec3,
-    total_dt: f32,
-    total_frames: u32,
-    skips: i32,
-    paused: bool
+    paused: bool,
+    view: DebugView,
+    slice_axis: SliceAxis,
+    slice_offset: f32,
+    selection: Vec<usize>,
+    collision_radius: Option<f32>,
+    walk_height: Option<f32>,
+    fall_speed: f32,
+    camera_mode: CameraMode,
+    camera_pitch: f32,
+    orbit_target: Vec3,
+    orbit_distance: f32,
+    /// Path being recorded and the time since recording started
+    recording: Option<(CameraPath, f32)>,
+    /// Path being played and the time since playback started
+    playback: Option<(CameraPath, f32)>,
+    input_map: InputMap,
+    /// Current move speed, starts at the speed of the input map and changes with the scroll wheel
+    move_speed: f32,
+    /// Scene file to reload when it changes and the time it was last modified
+    scene_file: Option<(String, SystemTime)>,
+    /// Time since the scene file was last checked
+    reload_timer: f32,
+    settings: RenderSettings,
+    /// Pool used to render when settings.threads isn't 0
+    thread_pool: Option<ThreadPool>,
+    /// Lines read from stdin and the definitions made in the console so far
+    console: Option<(Receiver<String>, Env)>,
+    /// Stats of the last frame
+    stats: RenderStats,
+    stats_log: StatsLog,
+    show_stats: bool,
 }
 
 impl Callbacks for Raymarcher {
     fn init(&self, ctx: &mut Context) {
-        canvas::resize(ctx, WIDTH, HEIGHT);
+        canvas::resize(ctx, self.settings.width, self.settings.height);
         window::set_cursor_enabled(ctx, false);
     }
 
     fn update(&mut self, ctx: &mut Context, dt: f32) -> bool {
+        self.reload_scene(dt);
+        self.run_console();
         self.input(ctx, dt);
+        self.update_camera_path(dt);
         self.draw(ctx);
 
-
-        if SHOW_FRAMERATE {
-            self.skips -= 1;
-            if self.skips <= 0 {
-                self.total_dt += dt;
-                self.total_frames += 1;
-            }
-
-            if self.total_frames == 100 || self.total_frames == 50 || self.total_frames == 25 {
-                println!(
-                    "avg dt for {}: {}",
-                    self.total_frames,
-                    self.total_dt / self.total_frames as f32
-                );
-            }
-
-            println!("dt: {}", dt);
-        }
-
         false
     }
 }
@@ -98,29 +237,375 @@ impl Callbacks for Raymarcher {
 impl Raymarcher {
     pub fn new(surfaces: SurfaceList, camera_pos: Vec3, light_pos: Vec3) -> Self {
         Self {
-            surfaces,
+            scene: Scene::new(surfaces),
             camera_pos,
             camera_yaw: 0.0,
             light_pos,
-            total_dt: 0.0,
-            total_frames: 0,
-            skips: 10,
             paused: false,
+            view: DebugView::None,
+            slice_axis: SliceAxis::Camera,
+            slice_offset: 2.0,
+            selection: Vec::new(),
+            collision_radius: None,
+            walk_height: None,
+            fall_speed: 0.0,
+            camera_mode: CameraMode::Fly,
+            camera_pitch: 0.0,
+            orbit_target: Vec3::ZERO,
+            orbit_distance: ORBIT_DEFAULT_DISTANCE,
+            recording: None,
+            playback: None,
+            input_map: InputMap::new(),
+            move_speed: 0.0,
+            scene_file: None,
+            reload_timer: 0.0,
+            settings: RenderSettings::default(),
+            thread_pool: None,
+            console: None,
+            stats: RenderStats::new(),
+            stats_log: StatsLog::new().with_limit(STATS_LOG_LENGTH),
+            show_stats: false,
+        }
+        .with_input_map(load_input_map())
+    }
+
+    /// Loads a scene file with its camera and light, see SceneFile for the format
+    ///
+    /// The surfaces are reloaded whenever the file changes
+    pub fn load(path: &str) -> io::Result<Self> {
+        let modified = fs::metadata(path)?.modified()?;
+        let scene = SceneFile::load(path)?;
+        let mut raymarcher = Self::new(scene.build_surfaces(path)?, Vec3::ZERO, scene.light);
+        raymarcher.set_camera_pose(scene.camera_pose());
+        raymarcher.scene_file = Some((path.to_string(), modified));
+        Ok(raymarcher)
+    }
+
+    /// Rebuilds the surfaces when the scene file has changed, keeping the camera and light
+    ///
+    /// If the file can't be loaded the error is printed and the previous surfaces stay
+    fn reload_scene(&mut self, dt: f32) {
+        let Some((path, modified)) = &mut self.scene_file else {
+            return;
+        };
+        self.reload_timer += dt;
+        if self.reload_timer < SCENE_RELOAD_INTERVAL {
+            return;
+        }
+        self.reload_timer = 0.0;
+
+        // Editors can briefly remove the file while saving
+        let Ok(new_modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            return;
+        };
+        if new_modified == *modified {
+            return;
+        }
+        *modified = new_modified;
+
+        match SceneFile::load(path).and_then(|scene| scene.build_surfaces(path)) {
+            Ok(surfaces) => {
+                self.scene = Scene::new(surfaces);
+                // Indices of the old surfaces mean nothing in the new scene
+                self.selection.clear();
+                println!("reloaded {}", path);
+            }
+            Err(err) => println!("could not reload scene, keeping the previous one: {}", err),
+        }
+    }
+
+    /// Reads expressions from stdin while the viewer runs, see expr::Env for the syntax
+    ///
+    /// A line that evaluates to a surface replaces the scene, other values are printed.
+    /// Definitions are kept for the following lines
+    pub fn with_console(mut self) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        self.console = Some((receiver, Env::new()));
+        self
+    }
+
+    /// Runs the lines typed into the console since the last frame
+    fn run_console(&mut self) {
+        let Some((receiver, env)) = &mut self.console else {
+            return;
+        };
+        while let Ok(line) = receiver.try_recv() {
+            match env.eval(&line, "console") {
+                Ok(Some(Value::Surface(surface))) => {
+                    self.scene = Scene::new(Arc::new(vec![surface]));
+                    self.selection.clear();
+                }
+                Ok(Some(value)) => println!("{}", value),
+                Ok(None) => {}
+                Err(err) => println!("{}", err),
+            }
+        }
+    }
+
+    /// Saves the scene with the current camera and light, see SceneFile::new
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let scene = SceneFile::new(self.scene.surfaces(), self.camera_pose(), self.light_pos)?;
+        scene.save(path)
+    }
+
+    /// Replaces the key bindings and resets the move speed to the speed of input_map
+    pub fn with_input_map(mut self, input_map: InputMap) -> Self {
+        self.move_speed = input_map.move_speed;
+        self.input_map = input_map;
+        self
+    }
+
+    /// Replaces the resolution and quality settings
+    ///
+    /// Should be called before the window is created, the canvas is sized in init
+    pub fn with_settings(mut self, settings: RenderSettings) -> Self {
+        self.thread_pool = (settings.threads > 0).then(|| {
+            ThreadPoolBuilder::new()
+                .num_threads(settings.threads)
+                .build()
+                .expect("could not create the render threads")
+        });
+        self.settings = settings;
+        if self.camera_mode == CameraMode::Orbit {
+            self.frame_scene();
+        }
+        self
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// Starts with the orbit camera framing the scene instead of the fly camera
+    pub fn with_orbit(mut self) -> Self {
+        self.camera_mode = CameraMode::Orbit;
+        self.frame_scene();
+        self
+    }
+
+    /// Stops the camera from moving into surfaces
+    ///
+    /// The camera is treated as a sphere with the given radius and slides along surfaces it hits
+    ///
+    /// Panics if radius isn't positive
+    pub fn with_collision(mut self, radius: f32) -> Self {
+        assert!(
+            radius > 0.0,
+            "collision radius must be positive, got {radius}"
+        );
+        self.collision_radius = Some(radius);
+        self
+    }
+
+    /// Adds gravity and keeps the camera at height above the ground
+    ///
+    /// Moving up and down is disabled while walking
+    ///
+    /// Panics if height isn't positive
+    pub fn with_walking(mut self, height: f32) -> Self {
+        assert!(height > 0.0, "walk height must be positive, got {height}");
+        self.walk_height = Some(height);
+        self
+    }
+
+    /// Moves the camera, resolving collisions and gravity if enabled
+    fn move_camera(&mut self, mut movement: Vec3, dt: f32) {
+        if let Some(height) = self.walk_height {
+            self.fall_speed += GRAVITY * dt;
+            movement.y = -self.fall_speed * dt;
+
+            // Step up or snap down to the ground
+            let pos = self.camera_pos + movement;
+            let max_dist = height + GROUND_SNAP_DISTANCE;
+            if let Some(hit) = self.scene.cast_ray(pos, -Vec3::Y, max_dist) {
+                movement.y = hit.position.y + height - self.camera_pos.y;
+                self.fall_speed = 0.0;
+            }
+        }
+
+        let Some(radius) = self.collision_radius else {
+            self.camera_pos += movement;
+            return;
+        };
+
+        // Move in steps shorter than the radius to avoid passing through thin surfaces
+        let steps = (movement.length() / radius).ceil().max(1.0);
+        let step = movement / steps;
+        for _ in 0..steps as u32 {
+            let mut pos = self.camera_pos + step;
+            // Push out along the normal, which keeps the movement along the surface
+            for _ in 0..COLLISION_ITERATIONS {
+                let dist = self.scene.distance(pos);
+                if dist >= radius {
+                    break;
+                }
+                pos += self.scene.normal(pos) * (radius - dist);
+            }
+            self.camera_pos = pos;
         }
     }
 
     fn input(&mut self, ctx: &mut Context, dt: f32) {
         // Pause
-        if input::key_just_pressed(ctx, KeyCode::Escape) {
+        if self.input_map.just_pressed(ctx, Action::Pause) {
             self.paused = !self.paused;
             window::set_cursor_enabled(ctx, self.paused);
         }
 
+        // Picking, pause to get the cursor back
+        if input::mouse_button_just_pressed(ctx, MouseButton::Left) {
+            let (x, y) = input::mouse_pos_pixel(ctx);
+            self.pick(
+                x.min(self.settings.width - 1),
+                y.min(self.settings.height - 1),
+            );
+        }
+
         if self.paused {
             return;
         }
 
+        // Debug views
+        for (action, view) in [
+            (Action::ViewShaded, DebugView::None),
+            (Action::ViewSteps, DebugView::Steps),
+            (Action::ViewStepLimit, DebugView::StepLimit),
+            (Action::ViewNormals, DebugView::Normals),
+            (Action::ViewDepth, DebugView::Depth),
+        ] {
+            if self.input_map.just_pressed(ctx, action) && self.view != view {
+                self.view = view;
+                println!("debug view: {:?}", view);
+            }
+        }
+        // Slice view, pressing again cycles through the axes
+        if self.input_map.just_pressed(ctx, Action::ViewSlice) {
+            if matches!(self.view, DebugView::Slice { .. }) {
+                self.slice_axis = match self.slice_axis {
+                    SliceAxis::X => SliceAxis::Y,
+                    SliceAxis::Y => SliceAxis::Z,
+                    SliceAxis::Z => SliceAxis::Camera,
+                    SliceAxis::Camera => SliceAxis::X,
+                };
+            }
+            // Placeholder plane, the real one is set after the camera has moved
+            self.view = DebugView::Slice {
+                normal: Vec3::Y,
+                distance: 0.0,
+            };
+            println!("debug view: Slice {:?}", self.slice_axis);
+        }
+        let speed = self.current_move_speed(ctx);
+        if self.input_map.pressed(ctx, Action::SliceForward) {
+            self.slice_offset += speed * dt;
+        }
+        if self.input_map.pressed(ctx, Action::SliceBack) {
+            self.slice_offset -= speed * dt;
+        }
+
         // Camera
+        if self.input_map.just_pressed(ctx, Action::ToggleCamera) {
+            self.camera_mode = match self.camera_mode {
+                CameraMode::Fly => {
+                    self.frame_scene();
+                    CameraMode::Orbit
+                }
+                CameraMode::Orbit => {
+                    self.camera_pitch = 0.0;
+                    CameraMode::Fly
+                }
+            };
+            println!("camera: {:?}", self.camera_mode);
+        }
+        if self.input_map.just_pressed(ctx, Action::FrameScene)
+            && self.camera_mode == CameraMode::Orbit
+        {
+            self.frame_scene();
+        }
+        match self.camera_mode {
+            CameraMode::Fly => self.fly(ctx, dt),
+            CameraMode::Orbit => self.orbit(ctx),
+        }
+
+        // Light
+        for (action, direction) in [
+            (Action::LightForward, Vec3::Z),
+            (Action::LightBack, -Vec3::Z),
+            (Action::LightRight, Vec3::X),
+            (Action::LightLeft, -Vec3::X),
+            (Action::LightUp, Vec3::Y),
+            (Action::LightDown, -Vec3::Y),
+        ] {
+            if self.input_map.pressed(ctx, action) {
+                self.light_pos += direction * speed * dt;
+            }
+        }
+
+        // Slice plane
+        if let DebugView::Slice { .. } = self.view {
+            let (normal, distance) = match self.slice_axis {
+                SliceAxis::X => (Vec3::X, self.slice_offset),
+                SliceAxis::Y => (Vec3::Y, self.slice_offset),
+                SliceAxis::Z => (Vec3::Z, self.slice_offset),
+                SliceAxis::Camera => {
+                    let forward = self.camera_rotation() * Vec3::Z;
+                    (forward, forward.dot(self.camera_pos) + self.slice_offset)
+                }
+            };
+            self.view = DebugView::Slice { normal, distance };
+        }
+
+        // Camera path
+        if self.input_map.just_pressed(ctx, Action::ToggleRecording) {
+            self.toggle_recording();
+        }
+        if self.input_map.just_pressed(ctx, Action::TogglePlayback) {
+            self.toggle_playback();
+        }
+
+        // Stats
+        if self.input_map.just_pressed(ctx, Action::ToggleStats) {
+            self.show_stats = !self.show_stats;
+            self.settings.timings = self.show_stats;
+        }
+        if self.input_map.just_pressed(ctx, Action::SaveStats) {
+            match self.stats_log.save(STATS_FILE) {
+                Ok(()) => println!(
+                    "saved stats of {} frames to {}",
+                    self.stats_log.len(),
+                    STATS_FILE
+                ),
+                Err(err) => println!("could not save stats: {}", err),
+            }
+        }
+
+        if self.input_map.just_pressed(ctx, Action::Screenshot) {
+            let path = "outputs/32.png";
+            media::export_screenshot(ctx, path).unwrap();
+            println!("exported screenshot to {}", path);
+        }
+    }
+
+    /// Moves the camera with the move keys and turns it with the mouse
+    ///
+    /// The scroll wheel changes the move speed
+    fn fly(&mut self, ctx: &Context, dt: f32) {
+        let scroll = input::scroll_delta(ctx).1;
+        if scroll != 0.0 {
+            self.move_speed *= self.input_map.scroll_multiplier.powf(scroll);
+            println!("move speed: {}", self.move_speed);
+        }
+
         let rot_mat = Mat3::from_rotation_y(self.camera_yaw);
         let rot_mat = rot_mat.to_cols_array_2d();
         let right = vec3(rot_mat[0][0], rot_mat[0][1], rot_mat[0][2]).normalize();
@@ -128,213 +613,478 @@ impl Raymarcher {
         let forward = vec3(rot_mat[2][0], rot_mat[2][1], rot_mat[2][2]).normalize();
 
         // Movement
-        if input::key_pressed(ctx, KeyCode::W) {
-            self.camera_pos += forward * CAMERA_MOVE_SPEED * dt;
-        }
-        if input::key_pressed(ctx, KeyCode::S) {
-            self.camera_pos -= forward * CAMERA_MOVE_SPEED * dt;
-        }
-        if input::key_pressed(ctx, KeyCode::A) {
-            self.camera_pos -= right * CAMERA_MOVE_SPEED * dt;
-        }
-        if input::key_pressed(ctx, KeyCode::D) {
-            self.camera_pos += right * CAMERA_MOVE_SPEED * dt;
-        }
-        if input::key_pressed(ctx, KeyCode::X) {
-            self.camera_pos += up * CAMERA_MOVE_SPEED * dt;
-        }
-        if input::key_pressed(ctx, KeyCode::Z) {
-            self.camera_pos -= up * CAMERA_MOVE_SPEED * dt;
+        let speed = self.current_move_speed(ctx);
+        let mut movement = Vec3::ZERO;
+        for (action, direction) in [
+            (Action::MoveForward, forward),
+            (Action::MoveBack, -forward),
+            (Action::MoveLeft, -right),
+            (Action::MoveRight, right),
+            (Action::MoveUp, up),
+            (Action::MoveDown, -up),
+        ] {
+            if self.input_map.pressed(ctx, action) {
+                movement += direction * speed * dt;
+            }
         }
+        self.move_camera(movement, dt);
         // Rotation
-        self.camera_yaw += CAMERA_ROTATE_SPEED * input::mouse_delta(ctx).0;
+        self.camera_yaw += self.input_map.rotate_speed * input::mouse_delta(ctx).0;
         // if keyboard::key_pressed(ctx, KeyCode::Q) {
         //     self.camera_yaw -= CAMERA_ROTATE_SPEED * dt;
         // }
         // if keyboard::key_pressed(ctx, KeyCode::E) {
         //     self.camera_yaw += CAMERA_ROTATE_SPEED * dt;
         // }
+    }
 
-        // Light
-        if input::key_pressed(ctx, KeyCode::Up) {
-            self.light_pos.z += CAMERA_MOVE_SPEED * dt;
-        }
-        if input::key_pressed(ctx, KeyCode::Down) {
-            self.light_pos.z -= CAMERA_MOVE_SPEED * dt;
+    /// Rotates the camera around orbit_target with the mouse, pans while holding the pan key
+    /// and zooms with the scroll wheel
+    fn orbit(&mut self, ctx: &Context) {
+        let (dx, dy) = input::mouse_delta(ctx);
+        if self.input_map.pressed(ctx, Action::Pan) {
+            // Scaled so the target follows the cursor
+            let rot_mat = self.camera_rotation();
+            let scale = self.orbit_distance / self.settings.focal_length();
+            self.orbit_target += (rot_mat * Vec3::Y * dy - rot_mat * Vec3::X * dx) * scale;
+        } else {
+            let max_pitch = FRAC_PI_2 - 0.01;
+            self.camera_yaw += ORBIT_ROTATE_SPEED * dx;
+            self.camera_pitch =
+                (self.camera_pitch + ORBIT_ROTATE_SPEED * dy).clamp(-max_pitch, max_pitch);
         }
-        if input::key_pressed(ctx, KeyCode::Right) {
-            self.light_pos.x += CAMERA_MOVE_SPEED * dt;
+        self.orbit_distance *= ORBIT_ZOOM_SPEED.powf(-input::scroll_delta(ctx).1);
+
+        let forward = self.camera_rotation() * Vec3::Z;
+        self.camera_pos = self.orbit_target - forward * self.orbit_distance;
+    }
+
+    /// Points the orbit camera at the combined bounding box of the scene
+    ///
+    /// Keeps the current view direction. Falls back to a point in front of the camera if no
+    /// surface is bounded
+    fn frame_scene(&mut self) {
+        let forward = self.camera_rotation() * Vec3::Z;
+        match self.scene.bounds() {
+            Some(bounds) => {
+                let radius = bounds.size().length() / 2.0;
+                let half_fov =
+                    (self.settings.height as f32 / 2.0 / self.settings.focal_length()).atan();
+                self.orbit_target = bounds.center();
+                self.orbit_distance = radius / half_fov.sin();
+            }
+            None => {
+                self.orbit_target = self.camera_pos + forward * ORBIT_DEFAULT_DISTANCE;
+                self.orbit_distance = ORBIT_DEFAULT_DISTANCE;
+            }
         }
-        if input::key_pressed(ctx, KeyCode::Left) {
-            self.light_pos.x -= CAMERA_MOVE_SPEED * dt;
+        self.camera_pos = self.orbit_target - forward * self.orbit_distance;
+    }
+
+    /// Move speed including the speed modifier
+    fn current_move_speed(&self, ctx: &Context) -> f32 {
+        if self.input_map.pressed(ctx, Action::MoveFast) {
+            self.move_speed * self.input_map.fast_multiplier
+        } else {
+            self.move_speed
         }
+    }
 
-        if input::key_just_pressed(ctx, KeyCode::Space) {
-            let path = "outputs/32.png";
-            media::export_screenshot(ctx, path).unwrap();
-            println!("exported screenshot to {}", path);
+    /// Rotation from camera space to world space
+    fn camera_rotation(&self) -> Mat3 {
+        Mat3::from_rotation_y(self.camera_yaw) * Mat3::from_rotation_x(self.camera_pitch)
+    }
+
+    /// Selects the surface under canvas pixel (x, y)
+    fn pick(&mut self, x: u32, y: u32) {
+        // The canvas starts at the top while the screen starts at the bottom
+        let screen_pos =
+            get_screen_pos(x, self.settings.height - 1 - y, Vec2::ZERO, &self.settings);
+        let dir = self.camera_rotation() * screen_pos;
+
+        match self.scene.cast_ray(self.camera_pos, dir, MAX_DISTANCE) {
+            Some(hit) => {
+                self.selection = self.scene.surface_path(hit.position);
+                println!(
+                    "selected surface {:?} at {} with normal {}",
+                    self.selection, hit.position, hit.normal
+                );
+            }
+            None => {
+                self.selection.clear();
+                println!("cleared selection");
+            }
         }
     }
 
-    #[rustfmt::skip]
     fn draw(&mut self, ctx: &mut Context) {
         canvas::clear_screen(ctx);
-        let camera_pos = self.camera_pos;
-        let light_pos = self.light_pos;
-        let rot_mat = Mat3::from_rotation_y(self.camera_yaw);
+        let pixels = canvas::pixels_ref(ctx);
+        self.stats = self.render(pixels);
+        self.stats_log.push(self.stats);
+        if self.show_stats {
+            draw_overlay(pixels, self.settings.width, &self.stats);
+        }
+    }
+
+    /// Stats of the last frame drawn in the viewer
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
+
+    /// Stats of the frames drawn in the viewer, up to STATS_LOG_LENGTH
+    pub fn stats_log(&self) -> &StatsLog {
+        &self.stats_log
+    }
+
+    /// Renders the current view into pixels without needing a window
+    ///
+    /// pixels is RGBA with width * height pixels of the settings, starting at the top left like
+    /// the canvas. Returns what it took to render the frame
+    pub fn render(&self, pixels: &mut [u8]) -> RenderStats {
+        let start = Instant::now();
+        let frame = Frame {
+            camera_pos: self.camera_pos,
+            rot_mat: self.camera_rotation(),
+            light_pos: self.light_pos,
+            surfaces: self.scene.surfaces(),
+            stepping: self.scene.stepping(),
+            view: self.view,
+            selection: &self.selection,
+            settings: self.settings,
+        };
+
+        let mut stats = match &self.thread_pool {
+            Some(pool) => pool.install(|| draw(pixels, &frame)),
+            None => draw(pixels, &frame),
+        };
+        stats.frame_time = start.elapsed();
+        stats
+    }
+
+    /// Renders path at a fixed frame rate without a window and saves each frame as a png in dir
+    ///
+    /// Returns the stats of every frame rendered
+    pub fn render_camera_path(
+        &mut self,
+        path: &CameraPath,
+        fps: f32,
+        dir: &str,
+    ) -> ImageResult<StatsLog> {
+        fs::create_dir_all(dir)?;
+        let frames = (path.duration() * fps).floor() as u32 + 1;
+        let (width, height) = (self.settings.width, self.settings.height);
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        let mut log = StatsLog::new();
+        for i in 0..frames {
+            let Some(pose) = path.sample(i as f32 / fps) else {
+                break;
+            };
+            self.set_camera_pose(pose);
+            log.push(self.render(&mut pixels));
+
+            let image = RgbaImage::from_raw(width, height, pixels.clone()).unwrap();
+            image.save(format!("{}/{:05}.png", dir, i))?;
+        }
+        Ok(log)
+    }
+
+    pub fn camera_pose(&self) -> CameraPose {
+        CameraPose::new(self.camera_pos, self.camera_yaw, self.camera_pitch)
+    }
+
+    pub fn set_camera_pose(&mut self, pose: CameraPose) {
+        self.camera_pos = pose.position;
+        self.camera_yaw = pose.yaw;
+        self.camera_pitch = pose.pitch;
+    }
+
+    /// Starts or stops recording, the path is saved when stopping
+    fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            Some((path, _)) => match path.save(CAMERA_PATH_FILE) {
+                Ok(()) => println!("saved {} camera poses to {}", path.len(), CAMERA_PATH_FILE),
+                Err(err) => println!("could not save camera path: {}", err),
+            },
+            None => {
+                self.recording = Some((CameraPath::new(), 0.0));
+                println!("recording camera path");
+            }
+        }
+    }
+
+    /// Starts or stops playing the last saved path
+    fn toggle_playback(&mut self) {
+        if self.playback.take().is_some() {
+            println!("stopped playback");
+            return;
+        }
+        match CameraPath::load(CAMERA_PATH_FILE) {
+            Ok(path) if !path.is_empty() => {
+                println!(
+                    "playing {} camera poses from {}",
+                    path.len(),
+                    CAMERA_PATH_FILE
+                );
+                self.playback = Some((path, 0.0));
+            }
+            Ok(_) => println!("camera path {} is empty", CAMERA_PATH_FILE),
+            Err(err) => println!("could not load camera path: {}", err),
+        }
+    }
+
+    /// Records or plays back the camera pose for this frame
+    fn update_camera_path(&mut self, dt: f32) {
+        if let Some((path, time)) = &mut self.playback {
+            *time += dt;
+            let pose = path.sample(*time);
+            let done = *time >= path.duration();
+            if let Some(pose) = pose {
+                self.set_camera_pose(pose);
+            }
+            if done {
+                self.playback = None;
+                println!("finished playback");
+            }
+        }
 
-        match THREADING {
-            Threading::Single =>  draw_single_threaded(ctx, camera_pos, light_pos, rot_mat, self.surfaces.clone()), 
-            Threading::ChunkMut() => draw_multi_threaded_chunkmut( ctx, camera_pos, light_pos, rot_mat, self.surfaces.clone(),),
-            Threading::LineChunkMut(size) => draw_custom_multi_line_chunkmut( ctx, camera_pos, light_pos, rot_mat, size, &self.surfaces,),
+        let pose = self.camera_pose();
+        if let Some((path, time)) = &mut self.recording {
+            path.push(*time, pose);
+            *time += dt;
         }
     }
 }
 
-fn draw_single_threaded(
-    ctx: &mut Context,
+/// Loads the key bindings from INPUT_MAP_FILE, falling back to the defaults
+fn load_input_map() -> InputMap {
+    match InputMap::load(INPUT_MAP_FILE) {
+        Ok(input_map) => input_map,
+        Err(err) if err.kind() == ErrorKind::NotFound => InputMap::new(),
+        Err(err) => {
+            println!("could not load key bindings, using the defaults: {}", err);
+            InputMap::new()
+        }
+    }
+}
+
+/// Everything needed to draw a pixel
+struct Frame<'a> {
     camera_pos: Vec3,
-    light_pos: Vec3,
     rot_mat: Mat3,
-    surfaces: SurfaceList,
-) {
-    let pixels = 0..(WIDTH * HEIGHT);
-    pixels.into_iter().for_each(|i| {
-        let (x, y) = (i % WIDTH, i / WIDTH);
-        let color = match ANTI_ALIASING {
-            Antialiasing::None => {
-                draw_pixel_simple(x, y, camera_pos, rot_mat, light_pos, &surfaces)
-            }
-            Antialiasing::AAx4 => draw_pixel_aax4(x, y, camera_pos, rot_mat, light_pos, &surfaces),
-        };
-        canvas::write_pixel_rgb_f32(ctx, x, y, &color.to_array());
+    light_pos: Vec3,
+    surfaces: &'a [Surface],
+    /// One per surface, see Stepping
+    stepping: &'a [Stepping],
+    view: DebugView,
+    /// Path of the selected surface, see Scene::surface_path
+    selection: &'a [usize],
+    settings: RenderSettings,
+}
+
+// The draw functions place pixel (0, 0) in the bottom left corner of the canvas
+
+fn draw(pixels: &mut [u8], frame: &Frame) -> RenderStats {
+    match frame.settings.threading {
+        Threading::Single => draw_single_threaded(pixels, frame),
+        Threading::ChunkMut() => draw_multi_threaded_chunkmut(pixels, frame),
+        Threading::LineChunkMut(size) => draw_custom_multi_line_chunkmut(pixels, size, frame),
+    }
+}
+
+fn draw_single_threaded(pixels: &mut [u8], frame: &Frame) -> RenderStats {
+    let (width, height) = (frame.settings.width, frame.settings.height);
+    let mut stats = RenderStats::new();
+    (0..(width * height)).for_each(|i| {
+        let (x, y) = (i % width, i / width);
+        let color = draw_pixel(x, y, frame, &mut stats);
+        let index = ((height - 1 - y) * width + x) as usize * 4;
+        write_color(&mut pixels[index..index + 4], color);
     });
+    stats
 }
 
-fn draw_multi_threaded_chunkmut(
-    ctx: &mut Context,
-    camera_pos: Vec3,
-    light_pos: Vec3,
-    rot_mat: Mat3,
-    surfaces: SurfaceList,
-) {
-    let pixels = canvas::pixels_ref(ctx);
+fn draw_multi_threaded_chunkmut(pixels: &mut [u8], frame: &Frame) -> RenderStats {
+    let width = frame.settings.width;
+    let mut stats = RenderStats::new();
     pixels.rchunks_mut(4).enumerate().for_each(|(i, rgba)| {
-        let (x, y) = (i as u32 % WIDTH, i as u32 / WIDTH);
-        let color = match ANTI_ALIASING {
-            Antialiasing::None => {
-                draw_pixel_simple(x, y, camera_pos, rot_mat, light_pos, &surfaces)
-            }
-            Antialiasing::AAx4 => draw_pixel_aax4(x, y, camera_pos, rot_mat, light_pos, &surfaces),
-        }
-        .clamp(Vec3::ZERO, Vec3::ONE);
-        rgba[0] = (color.x * 255.0) as u8;
-        rgba[1] = (color.y * 255.0) as u8;
-        rgba[2] = (color.z * 255.0) as u8;
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        write_color(rgba, draw_pixel(x, y, frame, &mut stats));
     });
+    stats
 }
 
-fn draw_custom_multi_line_chunkmut(
-    ctx: &mut Context,
-    camera_pos: Vec3,
-    light_pos: Vec3,
-    rot_mat: Mat3,
-    size: u32,
-    surfaces: &SurfaceList,
-) {
-    // let size = 32;
-    let pixels = canvas::pixels_ref(ctx);
+fn draw_custom_multi_line_chunkmut(pixels: &mut [u8], size: u32, frame: &Frame) -> RenderStats {
+    let (width, height) = (frame.settings.width, frame.settings.height);
+    let len = (width * height) as usize;
     pixels
-        .rchunks_mut(size as usize * 4)
+        .par_rchunks_mut(size as usize * 4)
         .enumerate()
-        .for_each(|(i, line)| {
+        .map(|(i, line)| {
+            let mut stats = RenderStats::new();
+            // The last chunk is shorter when size doesn't divide the pixel count
+            let start = len.saturating_sub((i + 1) * size as usize);
             for (j, rgba) in line.chunks_mut(4).enumerate() {
-                let index = i as u32 * size + j as u32;
-                let (x, y) = (index % WIDTH, index / WIDTH);
-                let color = match ANTI_ALIASING {
-                    Antialiasing::None => {
-                        draw_pixel_simple(x, y, camera_pos, rot_mat, light_pos, surfaces)
-                    }
-                    Antialiasing::AAx4 => {
-                        draw_pixel_aax4(x, y, camera_pos, rot_mat, light_pos, surfaces)
-                    }
-                }
-                .clamp(Vec3::ZERO, Vec3::ONE);
-                rgba[0] = (color.x * 255.0) as u8;
-                rgba[1] = (color.y * 255.0) as u8;
-                rgba[2] = (color.z * 255.0) as u8;
-                // println!("({x}, {y}) color: {:?}, rgba; {:?}", color, rgba);
+                let index = (start + j) as u32;
+                let (x, y) = (index % width, height - 1 - index / width);
+                write_color(rgba, draw_pixel(x, y, frame, &mut stats));
             }
-        });
+            stats
+        })
+        .reduce(RenderStats::new, RenderStats::merge)
 }
 
-fn draw_pixel_simple(
-    x: u32,
-    y: u32,
-    camera_pos: Vec3,
-    rot_mat: Mat3,
-    light_pos: Vec3,
-    surfaces: &[Surface],
-) -> Vec3 {
-    let screen_pos = get_screen_pos(x, y, Vec2::ZERO);
-    let dir = (rot_mat * screen_pos).normalize();
-    raymarch_color(camera_pos, dir, light_pos, surfaces)
+fn draw_pixel(x: u32, y: u32, frame: &Frame, stats: &mut RenderStats) -> Vec3 {
+    match frame.settings.anti_aliasing {
+        Antialiasing::None => draw_pixel_simple(x, y, frame, stats),
+        Antialiasing::AAx4 => draw_pixel_aax4(x, y, frame, stats),
+    }
 }
 
-fn draw_pixel_aax4(
-    x: u32,
-    y: u32,
-    camera_pos: Vec3,
-    rot_mat: Mat3,
-    light_pos: Vec3,
-    surfaces: &[Surface],
-) -> Vec3 {
+/// Writes color as an opaque RGBA pixel
+fn write_color(rgba: &mut [u8], color: Vec3) {
+    let color = color.clamp(Vec3::ZERO, Vec3::ONE);
+    rgba[0] = (color.x * 255.0) as u8;
+    rgba[1] = (color.y * 255.0) as u8;
+    rgba[2] = (color.z * 255.0) as u8;
+    rgba[3] = 255;
+}
+
+fn draw_pixel_simple(x: u32, y: u32, frame: &Frame, stats: &mut RenderStats) -> Vec3 {
+    let screen_pos = get_screen_pos(x, y, Vec2::ZERO, &frame.settings);
+    let dir = (frame.rot_mat * screen_pos).normalize();
+    raymarch_color(frame.camera_pos, dir, frame, stats)
+}
+
+fn draw_pixel_aax4(x: u32, y: u32, frame: &Frame, stats: &mut RenderStats) -> Vec3 {
     let mut color = Vec3::ZERO;
 
     let e = vec4(0.125, -0.125, 0.375, -0.375);
     for offset in [e.xz(), e.yw(), e.wx(), e.zy()] {
-        let screen_pos = get_screen_pos(x, y, offset);
-        let dir = (rot_mat * screen_pos).normalize();
-        color += raymarch_color(camera_pos, dir, light_pos, surfaces);
+        let screen_pos = get_screen_pos(x, y, offset, &frame.settings);
+        let dir = (frame.rot_mat * screen_pos).normalize();
+        color += raymarch_color(frame.camera_pos, dir, frame, stats);
     }
     color / 4.0
 }
 
-fn get_screen_pos(x: u32, y: u32, offset: Vec2) -> Vec3 {
+fn get_screen_pos(x: u32, y: u32, offset: Vec2, settings: &RenderSettings) -> Vec3 {
     vec3(
-        x as f32 - WIDTH as f32 / 2.0 + offset.x,
-        y as f32 - HEIGHT as f32 / 2.0 + offset.y,
-        FOCAL_LENGTH,
+        x as f32 - settings.width as f32 / 2.0 + offset.x,
+        y as f32 - settings.height as f32 / 2.0 + offset.y,
+        settings.focal_length(),
     )
 }
 
-fn raymarch_color(ro: Vec3, rd: Vec3, light_pos: Vec3, surfaces: &[Surface]) -> Vec3 {
-    let dist = raymarch(ro, rd, surfaces);
-    if dist < MAX_DISTANCE {
-        let pos = ro + rd * dist;
-        hit(pos, rd, light_pos, ro, surfaces)
+fn raymarch_color(ro: Vec3, rd: Vec3, frame: &Frame, stats: &mut RenderStats) -> Vec3 {
+    let surfaces = frame.surfaces;
+    let start = frame.settings.timings.then(Instant::now);
+    let (dist, steps, converged) = raymarch(ro, rd, MAX_DISTANCE, surfaces, frame.stepping);
+    // Rays that neither converged nor reached the render distance ran out of steps
+    let step_limit = !converged && dist < MAX_DISTANCE;
+    if let Some(start) = start {
+        stats.march_time += start.elapsed();
+    }
+    stats.primary_rays += 1;
+    stats.primary_steps += steps as u64;
+    stats.max_steps = stats.max_steps.max(steps);
+    stats.step_limit_rays += step_limit as u64;
+    stats.estimated_sdf_evaluations += steps as u64 * surfaces.len() as u64;
+
+    let pos = ro + rd * dist;
+    let mut shaded = || {
+        if dist >= MAX_DISTANCE {
+            miss()
+        } else if is_selected(pos, surfaces, frame.selection) {
+            let color = hit(pos, rd, ro, frame, stats);
+            interpolate_vec3(color, SELECTION_TINT, SELECTION_TINT_STRENGTH)
+        } else {
+            hit(pos, rd, ro, frame, stats)
+        }
+    };
+
+    match frame.view {
+        DebugView::None => shaded(),
+        DebugView::Steps => heat_color(steps as f32 / MAX_STEPS as f32),
+        DebugView::StepLimit if step_limit => RED,
+        DebugView::StepLimit => shaded(),
+        DebugView::Normals if dist < MAX_DISTANCE => normal(pos, surfaces) * 0.5 + 0.5,
+        DebugView::Depth if dist < MAX_DISTANCE => Vec3::splat(1.0 - dist / MAX_DISTANCE),
+        DebugView::Normals | DebugView::Depth => miss(),
+        DebugView::Slice { normal, distance } => {
+            // Ray plane intersection, geometry in front of the plane is drawn see through
+            let t = (distance - ro.dot(normal)) / rd.dot(normal);
+            if t <= 0.0 || !t.is_finite() || t >= MAX_DISTANCE {
+                shaded()
+            } else if dist < t {
+                let slice = slice_color(closest_sdf(ro + rd * t, surfaces));
+                interpolate_vec3(slice, shaded(), SLICE_GEOMETRY_OPACITY)
+            } else {
+                slice_color(closest_sdf(ro + rd * t, surfaces))
+            }
+        }
+    }
+}
+
+/// Colors a signed distance with an orange tint outside and a blue tint inside
+///
+/// Bands are drawn every SLICE_BAND_SPACING and the surface itself as a white line
+fn slice_color(dist: f32) -> Vec3 {
+    let tint = if dist > 0.0 {
+        vec3(0.9, 0.6, 0.3)
+    } else {
+        vec3(0.65, 0.85, 1.0)
+    };
+    let falloff = 1.0 - (-6.0 * dist.abs()).exp();
+    let bands = 0.8 + 0.2 * (std::f32::consts::TAU * dist / SLICE_BAND_SPACING).cos();
+    let color = tint * falloff * bands;
+
+    if dist.abs() < SLICE_LINE_WIDTH {
+        Vec3::ONE
     } else {
-        miss()
+        color
     }
 }
 
-fn raymarch(ro: Vec3, rd: Vec3, surfaces: &[Surface]) -> f32 {
+/// Maps p in range [0,1] to a blue-green-yellow-red gradient
+///
+/// Uses the square root of p to spread out the low step counts most rays have
+fn heat_color(p: f32) -> Vec3 {
+    let gradient = [BLUE, GREEN, YELLOW, RED];
+    let p = p.clamp(0.0, 1.0).sqrt() * (gradient.len() - 1) as f32;
+    let i = (p as usize).min(gradient.len() - 2);
+    interpolate_vec3(gradient[i], gradient[i + 1], p - i as f32)
+}
+
+/// Returns the distance travelled along the ray, the number of steps used
+/// and whether the ray converged on a surface
+///
+/// Stops early once the distance travelled reaches max_dist. Rays that run out of
+/// steps or reach max_dist have not converged
+pub(crate) fn raymarch(
+    ro: Vec3,
+    rd: Vec3,
+    max_dist: f32,
+    surfaces: &[Surface],
+    stepping: &[Stepping],
+) -> (f32, u32, bool) {
     let mut t = 0.0;
+    let mut steps = 0;
     for _ in 0..MAX_STEPS {
+        steps += 1;
         let pos = ro + rd * t;
-        let dist = closest_dist(pos, surfaces);
+        let dist = closest_dist(pos, surfaces, stepping);
 
         if dist.abs() < SURFACE_DISTANCE && dist.is_sign_positive() {
-            break;
+            return (t, steps, true);
         }
 
         t += dist;
-        if t >= MAX_DISTANCE {
+        if t >= max_dist {
             break;
         }
     }
-    // println!("DISTANCE: MAX STEPS REACHED");
-    t
+    (t, steps, false)
 }
 
 fn closest_color(
@@ -344,24 +1094,92 @@ fn closest_color(
     light_pos: Vec3,
     surfaces: &[Surface],
 ) -> Vec3 {
+    if let Some(index) = closest_surface(pos, surfaces) {
+        surfaces[index].color(ray, pos, normal, light_pos)
+    } else {
+        vec3(0.0, 0.0, 0.0)
+    }
+}
+
+/// Returns the index of the surface closest to pos, if any is within MAX_DISTANCE
+pub(crate) fn closest_surface(pos: Vec3, surfaces: &[Surface]) -> Option<usize> {
     let mut closest = MAX_DISTANCE;
-    let mut closest_surf: Option<&Surface> = None;
-    for surface in surfaces.iter() {
+    let mut closest_index = None;
+    for (i, surface) in surfaces.iter().enumerate() {
         let res = surface.sdf(pos);
         if res < closest {
             closest = res;
-            closest_surf = Some(surface);
+            closest_index = Some(i);
         }
     }
-    if let Some(closest_surf) = closest_surf {
-        closest_surf.color(ray, pos, normal, light_pos)
-    } else {
-        vec3(0.0, 0.0, 0.0)
+    closest_index
+}
+
+/// Returns the path of indices from the closest top level surface down to the surface at pos
+///
+/// Surfaces made of children, like Union, add the index of the child closest to pos
+pub(crate) fn surface_path(pos: Vec3, surfaces: &[Surface]) -> Vec<usize> {
+    let Some(index) = closest_surface(pos, surfaces) else {
+        return Vec::new();
+    };
+    let mut path = vec![index];
+    let mut surface = &surfaces[index];
+    let mut pos = pos;
+    while let Some((index, child, child_pos)) = surface.closest_child(pos) {
+        path.push(index);
+        surface = child;
+        pos = child_pos;
+    }
+    path
+}
+
+/// Returns true if the surface at pos is the selected surface or one of its children
+fn is_selected(pos: Vec3, surfaces: &[Surface], selection: &[usize]) -> bool {
+    let Some((&first, rest)) = selection.split_first() else {
+        return false;
+    };
+    if closest_surface(pos, surfaces) != Some(first) {
+        return false;
     }
+    let mut surface = &surfaces[first];
+    let mut pos = pos;
+    for &index in rest {
+        match surface.closest_child(pos) {
+            Some((i, child, child_pos)) if i == index => {
+                surface = child;
+                pos = child_pos;
+            }
+            _ => return false,
+        }
+    }
+    true
 }
 
-fn closest_dist(pos: Vec3, surfaces: &[Surface]) -> f32 {
+/// Distance a ray at pos can safely step, every surface's distance is divided by its lipschitz
+///
+/// A surface that overestimates its distance only slows down the rays near it, and not at all
+/// while they are further from its march bounds
+pub(crate) fn closest_dist(pos: Vec3, surfaces: &[Surface], stepping: &[Stepping]) -> f32 {
     // return 0.0;
+    let mut closest = MAX_DISTANCE;
+    for (surface, stepping) in surfaces.iter().zip(stepping) {
+        let mut res = surface.sdf(pos) / stepping.lipschitz;
+        if let Some(bounds) = stepping.bounds {
+            // The surface is at least as far as its bounds
+            let outside = bounds.distance(pos);
+            if outside > 0.0 {
+                res = res.max(outside);
+            }
+        }
+        if res < closest {
+            closest = res;
+        }
+    }
+    closest
+}
+
+/// Signed distance to the closest surface, as the surfaces return it
+pub(crate) fn closest_sdf(pos: Vec3, surfaces: &[Surface]) -> f32 {
     let mut closest = MAX_DISTANCE;
     for surface in surfaces.iter() {
         let res = surface.sdf(pos);
@@ -372,7 +1190,29 @@ fn closest_dist(pos: Vec3, surfaces: &[Surface]) -> f32 {
     closest
 }
 
-fn hit(pos: Vec3, rd: Vec3, light_pos: Vec3, camera_pos: Vec3, surfaces: &[Surface]) -> Vec3 {
+/// What the marcher needs of a surface besides its sdf, worked out once per scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Stepping {
+    /// SurfaceTrait::lipschitz, at least MIN_LIPSCHITZ
+    lipschitz: f32,
+    bounds: Option<Aabb>,
+}
+
+impl Stepping {
+    pub(crate) fn new(surfaces: &[Surface]) -> Vec<Stepping> {
+        surfaces
+            .iter()
+            .map(|surface| Stepping {
+                lipschitz: surface.lipschitz().max(MIN_LIPSCHITZ),
+                bounds: surface.march_bounds(),
+            })
+            .collect()
+    }
+}
+
+fn hit(pos: Vec3, rd: Vec3, camera_pos: Vec3, frame: &Frame, stats: &mut RenderStats) -> Vec3 {
+    let (light_pos, shadows, surfaces) = (frame.light_pos, frame.settings.shadows, frame.surfaces);
+    let start = frame.settings.timings.then(Instant::now);
     let normal = normal(pos, surfaces);
     let light_dir = (light_pos - pos).normalize();
     let relfeced_dir = reflect(-light_dir, normal);
@@ -389,12 +1229,14 @@ fn hit(pos: Vec3, rd: Vec3, light_pos: Vec3, camera_pos: Vec3, surfaces: &[Surfa
     let fog = 1.0 - distance_surface / MAX_DISTANCE;
 
     // Shadows
+    let shadow_start = frame.settings.timings.then(Instant::now);
     #[rustfmt::skip]
-    let shadow = match SHADOWS {
-        Shadows::Hard => hard_shadow(pos, light_pos, surfaces),
-        Shadows::Soft(k) => soft_shadow(pos,light_pos, k, surfaces),
-        Shadows::None => 1.0,
+    let (shadow, shadow_steps) = match shadows {
+        Shadows::Hard => hard_shadow(pos, light_pos, surfaces, frame.stepping),
+        Shadows::Soft(k) => soft_shadow(pos,light_pos, k, surfaces, frame.stepping),
+        Shadows::None => (1.0, 0),
     };
+    let shadow_time = shadow_start.map(|start| start.elapsed());
 
     // Combine
     let mut color = closest_color(rd, pos, normal, light_pos, surfaces);
@@ -404,6 +1246,15 @@ fn hit(pos: Vec3, rd: Vec3, light_pos: Vec3, camera_pos: Vec3, surfaces: &[Surfa
     // Gamma correction
     color = color.powf(0.4545);
 
+    // Normal takes 4 evaluations and the color 1
+    let n = surfaces.len() as u64;
+    stats.shadow_rays += (shadows != Shadows::None) as u64;
+    stats.estimated_sdf_evaluations += (shadow_steps as u64 + 5) * n;
+    if let (Some(start), Some(shadow_time)) = (start, shadow_time) {
+        stats.shadow_time += shadow_time;
+        stats.shading_time += start.elapsed() - shadow_time;
+    }
+
     color
 }
 
@@ -411,12 +1262,12 @@ fn miss() -> Vec3 {
     vec3(0.0, 0.0, 0.0)
 }
 
-fn normal(pos: Vec3, surfaces: &[Surface]) -> Vec3 {
-    let center = closest_dist(pos, surfaces);
+pub(crate) fn normal(pos: Vec3, surfaces: &[Surface]) -> Vec3 {
+    let center = closest_sdf(pos, surfaces);
     let diff = vec3(
-        closest_dist(pos + vec3(EPSILON, 0.0, 0.0), surfaces) - center,
-        closest_dist(pos + vec3(0.0, EPSILON, 0.0), surfaces) - center,
-        closest_dist(pos + vec3(0.0, 0.0, EPSILON), surfaces) - center,
+        closest_sdf(pos + vec3(EPSILON, 0.0, 0.0), surfaces) - center,
+        closest_sdf(pos + vec3(0.0, EPSILON, 0.0), surfaces) - center,
+        closest_sdf(pos + vec3(0.0, 0.0, EPSILON), surfaces) - center,
     );
     diff.normalize()
 }
@@ -425,38 +1276,51 @@ fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
     incident - 2.0 * normal.dot(incident) * normal
 }
 
-fn hard_shadow(surface_pos: Vec3, light_pos: Vec3, surfaces: &[Surface]) -> f32 {
+/// Returns the shadow and the steps it took
+fn hard_shadow(
+    surface_pos: Vec3,
+    light_pos: Vec3,
+    surfaces: &[Surface],
+    stepping: &[Stepping],
+) -> (f32, u32) {
     let light_dir = (light_pos - surface_pos).normalize();
     let light_dist = light_pos.distance(surface_pos);
     let start_pos = surface_pos + light_dir * SHADOW_STEP_DISTANCE; // start a little outside
 
-    let dist = raymarch(start_pos, light_dir, surfaces);
+    let (dist, steps, _) = raymarch(start_pos, light_dir, MAX_DISTANCE, surfaces, stepping);
 
     if dist < light_dist {
-        0.0
+        (0.0, steps)
     } else {
-        1.0
+        (1.0, steps)
     }
 }
 
-fn soft_shadow(surface_pos: Vec3, light_pos: Vec3, k: f32, surfaces: &[Surface]) -> f32 {
+/// Returns the shadow and the steps it took
+fn soft_shadow(
+    surface_pos: Vec3,
+    light_pos: Vec3,
+    k: f32,
+    surfaces: &[Surface],
+    stepping: &[Stepping],
+) -> (f32, u32) {
     let light_dir = (light_pos - surface_pos).normalize();
     let light_dist = light_pos.distance(surface_pos);
 
     let mut t = SHADOW_STEP_DISTANCE; // start a little outside
     let mut shadow: f32 = 1.0;
-    for _ in 0..MAX_STEPS {
+    for step in 0..MAX_STEPS {
         // If we pass the light return white
         if t >= light_dist {
-            return shadow;
+            return (shadow, step);
         }
 
         let pos = surface_pos + light_dir * t;
-        let dist = closest_dist(pos, surfaces);
+        let dist = closest_dist(pos, surfaces, stepping);
 
         // If we hit something before reaching the light return black
         if dist.abs() < SURFACE_DISTANCE {
-            return 0.0;
+            return (0.0, step + 1);
         }
 
         // Calculate shadow and t
@@ -464,7 +1328,7 @@ fn soft_shadow(surface_pos: Vec3, light_pos: Vec3, k: f32, surfaces: &[Surface])
         t += dist;
     }
     // println!("SOFT SHADOW: REACHED MAX STEPS");
-    1.0
+    (1.0, MAX_STEPS)
 }
 
     // closest.0
@@ -503,3 +1367,100 @@ fn soft_shadow(surface_pos: Vec3, light_pos: Vec3, k: f32, surfaces: &[Surface])
     // let y = closest_dist(pos + vec3(0.0, EPSILON, 0.0), surfaces);
     // let z = closest_dist(pos + vec3(0.0, 0.0, EPSILON), surfaces);
     // (vec3(x, y, z) - center) / EPSILON
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use glam::{vec3, Vec3};
+
+    use super::Raymarcher;
+    use crate::{
+        materials::{Unlit, RED},
+        surfaces::{exact_box, plane, sphere, translation, Material},
+    };
+
+    const FRAME: f32 = 1.0 / 60.0;
+    const TOLERANCE: f32 = 0.01;
+
+    fn red() -> Material {
+        Arc::new(Unlit::new(RED))
+    }
+
+    #[test]
+    fn collision_slides_along_walls() {
+        // Wall at x = 2 facing -x
+        let surfaces = Arc::new(vec![plane(vec3(-1.0, 0.0, 0.0), -2.0, red())]);
+        let mut raymarcher = Raymarcher::new(surfaces, Vec3::ZERO, Vec3::ONE).with_collision(0.5);
+        raymarcher.move_camera(vec3(3.0, 0.0, 1.0), FRAME);
+
+        let pos = raymarcher.camera_pos;
+        assert!((pos.x - 1.5).abs() < TOLERANCE, "{pos}");
+        assert!((pos.z - 1.0).abs() < TOLERANCE, "{pos}");
+        assert!(pos.y.abs() < TOLERANCE, "{pos}");
+    }
+
+    #[test]
+    fn walking_settles_at_height() {
+        let surfaces = Arc::new(vec![plane(Vec3::Y, 0.0, red())]);
+        let mut raymarcher =
+            Raymarcher::new(surfaces, vec3(0.0, 3.0, 0.0), Vec3::ONE).with_walking(1.0);
+        // Falls until the ground is in reach, then stays on it while walking
+        for _ in 0..120 {
+            raymarcher.move_camera(Vec3::ZERO, FRAME);
+        }
+        for _ in 0..60 {
+            raymarcher.move_camera(vec3(0.05, 0.0, 0.0), FRAME);
+        }
+
+        let pos = raymarcher.camera_pos;
+        assert!((pos.y - 1.0).abs() < TOLERANCE, "{pos}");
+        assert!((pos.x - 3.0).abs() < TOLERANCE, "{pos}");
+        assert_eq!(raymarcher.fall_speed, 0.0);
+    }
+
+    #[test]
+    fn walking_steps_up() {
+        // Step 0.3 high starting at x = 1
+        let surfaces = Arc::new(vec![
+            plane(Vec3::Y, 0.0, red()),
+            translation(vec3(3.0, 0.15, 0.0), exact_box(vec3(2.0, 0.15, 2.0), red())),
+        ]);
+        let mut raymarcher =
+            Raymarcher::new(surfaces, vec3(0.0, 1.0, 0.0), Vec3::ONE).with_walking(1.0);
+        for _ in 0..40 {
+            raymarcher.move_camera(vec3(0.05, 0.0, 0.0), FRAME);
+        }
+
+        let pos = raymarcher.camera_pos;
+        assert!((pos.y - 1.3).abs() < TOLERANCE, "{pos}");
+    }
+
+    #[test]
+    #[should_panic(expected = "collision radius must be positive")]
+    fn collision_radius_must_be_positive() {
+        let surfaces = Arc::new(vec![sphere(1.0, red())]);
+        let _ = Raymarcher::new(surfaces, vec3(0.0, 0.0, -5.0), Vec3::ONE).with_collision(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "collision radius must be positive")]
+    fn collision_radius_rejects_nan() {
+        let surfaces = Arc::new(vec![sphere(1.0, red())]);
+        let _ = Raymarcher::new(surfaces, vec3(0.0, 0.0, -5.0), Vec3::ONE).with_collision(f32::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "walk height must be positive")]
+    fn walk_height_must_be_positive() {
+        let surfaces = Arc::new(vec![sphere(1.0, red())]);
+        let _ = Raymarcher::new(surfaces, vec3(0.0, 0.0, -5.0), Vec3::ONE).with_walking(-1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "walk height must be positive")]
+    fn walk_height_rejects_nan() {
+        let surfaces = Arc::new(vec![sphere(1.0, red())]);
+        let _ = Raymarcher::new(surfaces, vec3(0.0, 0.0, -5.0), Vec3::ONE).with_walking(f32::NAN);
+    }
+}
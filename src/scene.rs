@@ -0,0 +1,130 @@
+use glam::{vec3, Vec3};
+
+use crate::{
+    raymarcher::{closest_sdf, closest_surface, raymarch, surface_path, Stepping},
+    surfaces::{Aabb, SurfaceList},
+};
+
+const GRADIENT_EPSILON: f32 = 0.001;
+const CLOSEST_POINT_ITERATIONS: u32 = 16;
+const CLOSEST_POINT_TOLERANCE: f32 = 0.0001;
+
+/// Result of a ray cast against a scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    /// Distance along the ray
+    pub t: f32,
+    pub position: Vec3,
+    pub normal: Vec3,
+    /// Index of the top level surface that was hit
+    pub surface_index: usize,
+    /// Number of steps the ray marcher used
+    pub steps: u32,
+}
+
+/// Queries against a list of surfaces
+///
+/// Uses the same ray marching as the renderer, so results match what is drawn.
+/// Distances are capped at the render distance of the renderer
+#[derive(Clone)]
+pub struct Scene {
+    surfaces: SurfaceList,
+    /// How every surface is stepped, worked out once instead of for every ray
+    stepping: Vec<Stepping>,
+}
+
+impl Scene {
+    pub fn new(surfaces: SurfaceList) -> Self {
+        let stepping = Stepping::new(&surfaces);
+        Self { surfaces, stepping }
+    }
+
+    pub fn surfaces(&self) -> &SurfaceList {
+        &self.surfaces
+    }
+
+    pub(crate) fn stepping(&self) -> &[Stepping] {
+        &self.stepping
+    }
+
+    /// Marches a ray from origin along dir
+    ///
+    /// Returns None if nothing is hit within max_dist, if the ray runs out of steps
+    /// before reaching a surface or if origin is inside a surface
+    pub fn cast_ray(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<Hit> {
+        let dir = dir.normalize();
+        let (t, steps, converged) =
+            raymarch(origin, dir, max_dist, &self.surfaces, &self.stepping);
+        if !converged || t < 0.0 {
+            return None;
+        }
+
+        let position = origin + dir * t;
+        Some(Hit {
+            t,
+            position,
+            normal: self.normal(position),
+            surface_index: closest_surface(position, &self.surfaces)?,
+            steps,
+        })
+    }
+
+    /// Path of indices to the surface at pos, starting with the index of the top level surface
+    ///
+    /// Surfaces made of children, like Union, add the index of the child closest to pos.
+    /// Empty if no surface is within render distance
+    pub fn surface_path(&self, pos: Vec3) -> Vec<usize> {
+        surface_path(pos, &self.surfaces)
+    }
+
+    /// Combined bounds of all bounded surfaces, unbounded surfaces like planes are skipped
+    ///
+    /// None if no surface is bounded
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.surfaces
+            .iter()
+            .filter_map(|surface| surface.bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Signed distance from pos to the closest surface, negative inside
+    pub fn distance(&self, pos: Vec3) -> f32 {
+        closest_sdf(pos, &self.surfaces)
+    }
+
+    /// Gradient of the distance field at pos using central differences
+    ///
+    /// Has length 1 for exact distance fields
+    pub fn gradient(&self, pos: Vec3) -> Vec3 {
+        let e = GRADIENT_EPSILON;
+        let dx = self.distance(pos + vec3(e, 0.0, 0.0)) - self.distance(pos - vec3(e, 0.0, 0.0));
+        let dy = self.distance(pos + vec3(0.0, e, 0.0)) - self.distance(pos - vec3(0.0, e, 0.0));
+        let dz = self.distance(pos + vec3(0.0, 0.0, e)) - self.distance(pos - vec3(0.0, 0.0, e));
+        vec3(dx, dy, dz) / (2.0 * e)
+    }
+
+    /// Surface normal at pos, the normalized gradient
+    pub fn normal(&self, pos: Vec3) -> Vec3 {
+        self.gradient(pos).normalize_or_zero()
+    }
+
+    /// Projects pos onto the closest surface
+    ///
+    /// Steps along the gradient until the distance is zero, exact distance fields only need one step
+    pub fn closest_point(&self, pos: Vec3) -> Vec3 {
+        let mut p = pos;
+        for _ in 0..CLOSEST_POINT_ITERATIONS {
+            let dist = self.distance(p);
+            if dist.abs() < CLOSEST_POINT_TOLERANCE {
+                break;
+            }
+            let gradient = self.gradient(p);
+            let length_squared = gradient.length_squared();
+            if length_squared == 0.0 {
+                break;
+            }
+            p -= gradient * dist / length_squared;
+        }
+        p
+    }
+}
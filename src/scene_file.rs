@@ -0,0 +1,758 @@
+use std::{
+    collections::BTreeMap,
+    f32::consts::PI,
+    fs,
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use glam::{vec3, EulerRot, Quat, Vec2, Vec3};
+use ron::ser::PrettyConfig;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    camera_path::CameraPose,
+    expr::Env,
+    fbm::FbmSettings,
+    materials::{Normal, Textured, TrapGradient, Unlit},
+    surfaces::{
+        box_frame, capped_cone, capped_cylinder, capped_torus, capsule, cylinder, displace,
+        ellipsoid, exact_box, fbm_terrain, hexagonal_prism, infinite_repetition, intersection,
+        link, octahedron, perlin_sphere, pertrubed_sphere, plane, pyramid, rotation, round_cone,
+        rounded_box, scale, smooth_intersection, smooth_subtraction, smooth_union, sphere,
+        subtraction, torus, translation, translation_rotation_scale, triangular_prism, union,
+        Heightfield, Mandelbox, Mandelbulb, Material, MengerSponge, MeshSurface,
+        SierpinskiTetrahedron, Surface, SurfaceList, VoxelSdf, MANDELBOX_FOLD_LIMIT,
+        MANDELBOX_ITERATIONS, MANDELBOX_MIN_RADIUS, MANDELBULB_BAILOUT, MANDELBULB_ITERATIONS,
+        MENGER_ITERATIONS, SIERPINSKI_ITERATIONS,
+    },
+    voxels::Interpolation,
+};
+
+/// A scene stored as RON text
+///
+/// ```text
+/// Scene(
+///     camera: (position: (0, 1, -5)),
+///     light: (-2, 1, -2),
+///     materials: {
+///         "red": Unlit((1, 0, 0)),
+///     },
+///     surfaces: [
+///         Plane(normal: (0, 1, 0), distance: -2, material: Textured(path: "assets/dirt.jpeg")),
+///         Translation(offset: (0, 0.5, 0), surface: Sphere(radius: 1, material: Named("red"))),
+///     ],
+/// )
+/// ```
+///
+/// Angles are in degrees and texture paths are relative to the working directory
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "Scene")]
+pub struct SceneFile {
+    pub camera: CameraDesc,
+    /// Position of the point light
+    pub light: Vec3,
+    /// Materials that surfaces can share with MaterialDesc::Named
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    pub surfaces: Vec<SurfaceDesc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraDesc {
+    pub position: Vec3,
+    /// Degrees to the right
+    #[serde(default)]
+    pub yaw: f32,
+    /// Degrees down
+    #[serde(default)]
+    pub pitch: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MaterialDesc {
+    /// Flat color with components in range [0,1]
+    Unlit(Vec3),
+    Normal,
+    Textured {
+        path: String,
+        #[serde(default = "one")]
+        scale: f32,
+        #[serde(default = "one")]
+        blend_sharpness: f32,
+    },
+    /// Blends between two colors on fractals, see materials::TrapGradient
+    TrapGradient {
+        near: Vec3,
+        far: Vec3,
+    },
+    /// Material from the materials of the scene
+    Named(String),
+}
+
+/// Mirrors the surface constructors in surfaces
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SurfaceDesc {
+    Sphere {
+        radius: f32,
+        material: MaterialDesc,
+    },
+    /// BoxExact, half_size is the distance from the center to the faces
+    Box {
+        half_size: Vec3,
+        material: MaterialDesc,
+    },
+    Plane {
+        normal: Vec3,
+        distance: f32,
+        material: MaterialDesc,
+    },
+    PerlinSphere {
+        radius: f32,
+        intensity: f32,
+        material: MaterialDesc,
+    },
+    PertrubedSphere {
+        radius: f32,
+        intensity: f32,
+        phase_shift: f32,
+        material: MaterialDesc,
+    },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        material: MaterialDesc,
+    },
+    /// Arc of a torus, angle is half the opening
+    CappedTorus {
+        angle: f32,
+        major_radius: f32,
+        minor_radius: f32,
+        material: MaterialDesc,
+    },
+    Link {
+        half_length: f32,
+        major_radius: f32,
+        minor_radius: f32,
+        material: MaterialDesc,
+    },
+    Capsule {
+        start: Vec3,
+        end: Vec3,
+        radius: f32,
+        material: MaterialDesc,
+    },
+    /// Cylinder along the y axis
+    Cylinder {
+        half_height: f32,
+        radius: f32,
+        material: MaterialDesc,
+    },
+    /// Cylinder from start to end
+    CappedCylinder {
+        start: Vec3,
+        end: Vec3,
+        radius: f32,
+        material: MaterialDesc,
+    },
+    CappedCone {
+        half_height: f32,
+        bottom_radius: f32,
+        top_radius: f32,
+        material: MaterialDesc,
+    },
+    RoundCone {
+        bottom_radius: f32,
+        top_radius: f32,
+        height: f32,
+        material: MaterialDesc,
+    },
+    /// half_size includes the rounding
+    RoundedBox {
+        half_size: Vec3,
+        radius: f32,
+        material: MaterialDesc,
+    },
+    BoxFrame {
+        half_size: Vec3,
+        thickness: f32,
+        material: MaterialDesc,
+    },
+    Ellipsoid {
+        radii: Vec3,
+        material: MaterialDesc,
+    },
+    Octahedron {
+        size: f32,
+        material: MaterialDesc,
+    },
+    Pyramid {
+        half_base: f32,
+        height: f32,
+        material: MaterialDesc,
+    },
+    /// Prism along the z axis, radius is the distance from the center to the sides
+    HexagonalPrism {
+        radius: f32,
+        half_length: f32,
+        material: MaterialDesc,
+    },
+    /// Prism along the z axis, radius is the distance from the center to the sides
+    TriangularPrism {
+        radius: f32,
+        half_length: f32,
+        material: MaterialDesc,
+    },
+    Mandelbulb {
+        power: f32,
+        #[serde(default = "mandelbulb_iterations")]
+        iterations: u32,
+        #[serde(default = "mandelbulb_bailout")]
+        bailout: f32,
+        material: MaterialDesc,
+    },
+    /// Sponge inside a cube with half side size
+    MengerSponge {
+        size: f32,
+        #[serde(default = "menger_iterations")]
+        iterations: u32,
+        material: MaterialDesc,
+    },
+    /// Tetrahedron with corners at distance size from the center along each axis
+    SierpinskiTetrahedron {
+        size: f32,
+        #[serde(default = "sierpinski_iterations")]
+        iterations: u32,
+        material: MaterialDesc,
+    },
+    /// Triangle mesh loaded from an OBJ or STL file
+    Mesh {
+        path: String,
+        material: MaterialDesc,
+    },
+    /// Terrain from the brightness of an image, size is the extent along x and z
+    Heightfield {
+        path: String,
+        size: Vec2,
+        height: f32,
+        material: MaterialDesc,
+    },
+    /// Endless ground raised by fBm noise
+    FbmTerrain {
+        #[serde(default)]
+        noise: FbmSettings,
+        material: MaterialDesc,
+    },
+    /// Distances sampled on a grid, saved with voxels::VoxelGrid::save
+    Voxels {
+        path: String,
+        #[serde(default)]
+        interpolation: Interpolation,
+        material: MaterialDesc,
+    },
+    Mandelbox {
+        scale: f32,
+        #[serde(default = "mandelbox_min_radius")]
+        min_radius: f32,
+        #[serde(default = "mandelbox_fold_limit")]
+        fold_limit: f32,
+        #[serde(default = "mandelbox_iterations")]
+        iterations: u32,
+        material: MaterialDesc,
+    },
+    #[serde(deserialize_with = "non_empty")]
+    Union(Vec<SurfaceDesc>),
+    #[serde(deserialize_with = "non_empty")]
+    Intersection(Vec<SurfaceDesc>),
+    /// Subtracts cut from base
+    Subtraction {
+        base: Box<SurfaceDesc>,
+        cut: Box<SurfaceDesc>,
+    },
+    SmoothUnion {
+        #[serde(deserialize_with = "non_empty")]
+        surfaces: Vec<SurfaceDesc>,
+        blend: f32,
+    },
+    SmoothIntersection {
+        #[serde(deserialize_with = "non_empty")]
+        surfaces: Vec<SurfaceDesc>,
+        blend: f32,
+    },
+    SmoothSubtraction {
+        base: Box<SurfaceDesc>,
+        cut: Box<SurfaceDesc>,
+        blend: f32,
+    },
+    Translation {
+        offset: Vec3,
+        surface: Box<SurfaceDesc>,
+    },
+    Rotation {
+        rotation: RotationDesc,
+        surface: Box<SurfaceDesc>,
+    },
+    Scale {
+        factor: f32,
+        surface: Box<SurfaceDesc>,
+    },
+    /// TranslationRotationScale, every part is optional
+    Transform {
+        #[serde(default)]
+        translation: Vec3,
+        #[serde(default)]
+        rotation: RotationDesc,
+        #[serde(default = "one")]
+        scale: f32,
+        surface: Box<SurfaceDesc>,
+    },
+    /// InfiniteRepetition
+    Repetition {
+        period: Vec3,
+        surface: Box<SurfaceDesc>,
+    },
+    /// Adds fBm noise to the distance of surface
+    Displace {
+        #[serde(default)]
+        noise: FbmSettings,
+        surface: Box<SurfaceDesc>,
+    },
+    /// Program in the expression language, see expr::Env
+    Expr(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RotationDesc {
+    /// Euler angles in degrees, applied in XYZ order
+    Degrees(Vec3),
+    /// Quaternion as (x, y, z, w), used when saving rotations that degrees can't represent exactly
+    Quat(Quat),
+}
+
+impl Default for RotationDesc {
+    fn default() -> Self {
+        RotationDesc::Degrees(Vec3::ZERO)
+    }
+}
+
+impl RotationDesc {
+    /// Degrees if they convert back to exactly the same quaternion, rounded if possible
+    pub fn from_quat(rotation: Quat) -> Self {
+        let (x, y, z) = rotation.to_euler(EulerRot::XYZ);
+        let degrees = vec3(x, y, z) * 180.0 / PI;
+        let rounded = (degrees * 1000.0).round() / 1000.0;
+        [rounded, degrees]
+            .map(RotationDesc::Degrees)
+            .into_iter()
+            .find(|desc| desc.to_quat() == rotation)
+            .unwrap_or(RotationDesc::Quat(rotation))
+    }
+
+    pub fn to_quat(&self) -> Quat {
+        match self {
+            RotationDesc::Degrees(degrees) => {
+                let radians = *degrees * PI / 180.0;
+                Quat::from_euler(EulerRot::XYZ, radians.x, radians.y, radians.z)
+            }
+            RotationDesc::Quat(rotation) => *rotation,
+        }
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn mandelbulb_iterations() -> u32 {
+    MANDELBULB_ITERATIONS
+}
+
+fn mandelbulb_bailout() -> f32 {
+    MANDELBULB_BAILOUT
+}
+
+fn menger_iterations() -> u32 {
+    MENGER_ITERATIONS
+}
+
+fn sierpinski_iterations() -> u32 {
+    SIERPINSKI_ITERATIONS
+}
+
+fn mandelbox_min_radius() -> f32 {
+    MANDELBOX_MIN_RADIUS
+}
+
+fn mandelbox_fold_limit() -> f32 {
+    MANDELBOX_FOLD_LIMIT
+}
+
+fn mandelbox_iterations() -> u32 {
+    MANDELBOX_ITERATIONS
+}
+
+fn non_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SurfaceDesc>, D::Error> {
+    let surfaces = Vec::<SurfaceDesc>::deserialize(deserializer)?;
+    if surfaces.is_empty() {
+        return Err(D::Error::custom("expected at least one surface"));
+    }
+    Ok(surfaces)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+impl SceneFile {
+    /// Describes surfaces built in code, see SurfaceTrait::describe
+    ///
+    /// Fails if any surface or material can't be described
+    pub fn new(surfaces: &[Surface], camera: CameraPose, light: Vec3) -> io::Result<Self> {
+        let surfaces = surfaces
+            .iter()
+            .enumerate()
+            .map(|(i, surface)| {
+                surface.describe().ok_or_else(|| {
+                    invalid_data(format!("surface {} can't be described as a scene file", i))
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self {
+            camera: CameraDesc {
+                position: camera.position,
+                yaw: camera.yaw.to_degrees(),
+                pitch: camera.pitch.to_degrees(),
+            },
+            light,
+            materials: BTreeMap::new(),
+            surfaces,
+        })
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text, path)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_ron())
+    }
+
+    /// The scene as text that parse reads back
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, PrettyConfig::new()).unwrap()
+    }
+
+    /// Parses text, path is only used in error messages
+    pub fn parse(text: &str, path: &str) -> io::Result<Self> {
+        ron::from_str(text).map_err(|err| {
+            invalid_data(format!(
+                "{}:{}:{}: {}",
+                path, err.position.line, err.position.col, err.code
+            ))
+        })
+    }
+
+    /// Builds the surfaces, loading any textures
+    ///
+    /// path is only used in error messages, which also name the material or surface that failed
+    pub fn build_surfaces(&self, path: &str) -> io::Result<SurfaceList> {
+        let mut materials = BTreeMap::new();
+        for (name, material) in self.materials.iter() {
+            if let MaterialDesc::Named(_) = material {
+                return Err(invalid_data(format!(
+                    "{}: material \"{}\" can't refer to another named material",
+                    path, name
+                )));
+            }
+            let built = material.build(&BTreeMap::new()).map_err(|err| {
+                invalid_data(format!("{}: materials[\"{}\"]: {}", path, name, err))
+            })?;
+            materials.insert(name.clone(), built);
+        }
+
+        let surfaces = self
+            .surfaces
+            .iter()
+            .enumerate()
+            .map(|(i, surface)| {
+                surface
+                    .build(&materials)
+                    .map_err(|err| invalid_data(format!("{}: surfaces[{}]: {}", path, i, err)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Arc::new(surfaces))
+    }
+
+    pub fn camera_pose(&self) -> CameraPose {
+        CameraPose::new(
+            self.camera.position,
+            self.camera.yaw.to_radians(),
+            self.camera.pitch.to_radians(),
+        )
+    }
+}
+
+impl MaterialDesc {
+    /// named holds the built materials of the scene
+    pub fn build(&self, named: &BTreeMap<String, Material>) -> io::Result<Material> {
+        Ok(match self {
+            MaterialDesc::Unlit(color) => Arc::new(Unlit::new(*color)),
+            MaterialDesc::Normal => Arc::new(Normal),
+            MaterialDesc::TrapGradient { near, far } => Arc::new(TrapGradient::new(*near, *far)),
+            MaterialDesc::Textured {
+                path,
+                scale,
+                blend_sharpness,
+            } => {
+                let textured = Textured::load(path).map_err(|err| {
+                    invalid_data(format!("could not load texture \"{}\": {}", path, err))
+                })?;
+                Arc::new(
+                    textured
+                        .with_scale(*scale)
+                        .with_blend_sharpness(*blend_sharpness),
+                )
+            }
+            MaterialDesc::Named(name) => named
+                .get(name)
+                .cloned()
+                .ok_or_else(|| invalid_data(format!("unknown material \"{}\"", name)))?,
+        })
+    }
+}
+
+impl SurfaceDesc {
+    /// named holds the built materials of the scene
+    pub fn build(&self, named: &BTreeMap<String, Material>) -> io::Result<Surface> {
+        let build = |surface: &SurfaceDesc| surface.build(named);
+
+        Ok(match self {
+            SurfaceDesc::Sphere { radius, material } => sphere(*radius, material.build(named)?),
+            SurfaceDesc::Box {
+                half_size,
+                material,
+            } => exact_box(*half_size, material.build(named)?),
+            SurfaceDesc::Plane {
+                normal,
+                distance,
+                material,
+            } => plane(*normal, *distance, material.build(named)?),
+            SurfaceDesc::PerlinSphere {
+                radius,
+                intensity,
+                material,
+            } => perlin_sphere(*radius, *intensity, material.build(named)?),
+            SurfaceDesc::PertrubedSphere {
+                radius,
+                intensity,
+                phase_shift,
+                material,
+            } => pertrubed_sphere(*radius, *intensity, *phase_shift, material.build(named)?),
+            SurfaceDesc::Torus {
+                major_radius,
+                minor_radius,
+                material,
+            } => torus(*major_radius, *minor_radius, material.build(named)?),
+            SurfaceDesc::CappedTorus {
+                angle,
+                major_radius,
+                minor_radius,
+                material,
+            } => capped_torus(
+                angle.to_radians(),
+                *major_radius,
+                *minor_radius,
+                material.build(named)?,
+            ),
+            SurfaceDesc::Link {
+                half_length,
+                major_radius,
+                minor_radius,
+                material,
+            } => link(
+                *half_length,
+                *major_radius,
+                *minor_radius,
+                material.build(named)?,
+            ),
+            SurfaceDesc::Capsule {
+                start,
+                end,
+                radius,
+                material,
+            } => capsule(*start, *end, *radius, material.build(named)?),
+            SurfaceDesc::Cylinder {
+                half_height,
+                radius,
+                material,
+            } => cylinder(*half_height, *radius, material.build(named)?),
+            SurfaceDesc::CappedCylinder {
+                start,
+                end,
+                radius,
+                material,
+            } => capped_cylinder(*start, *end, *radius, material.build(named)?),
+            SurfaceDesc::CappedCone {
+                half_height,
+                bottom_radius,
+                top_radius,
+                material,
+            } => capped_cone(
+                *half_height,
+                *bottom_radius,
+                *top_radius,
+                material.build(named)?,
+            ),
+            SurfaceDesc::RoundCone {
+                bottom_radius,
+                top_radius,
+                height,
+                material,
+            } => round_cone(*bottom_radius, *top_radius, *height, material.build(named)?),
+            SurfaceDesc::RoundedBox {
+                half_size,
+                radius,
+                material,
+            } => rounded_box(*half_size, *radius, material.build(named)?),
+            SurfaceDesc::BoxFrame {
+                half_size,
+                thickness,
+                material,
+            } => box_frame(*half_size, *thickness, material.build(named)?),
+            SurfaceDesc::Ellipsoid { radii, material } => ellipsoid(*radii, material.build(named)?),
+            SurfaceDesc::Octahedron { size, material } => octahedron(*size, material.build(named)?),
+            SurfaceDesc::Pyramid {
+                half_base,
+                height,
+                material,
+            } => pyramid(*half_base, *height, material.build(named)?),
+            SurfaceDesc::HexagonalPrism {
+                radius,
+                half_length,
+                material,
+            } => hexagonal_prism(*radius, *half_length, material.build(named)?),
+            SurfaceDesc::TriangularPrism {
+                radius,
+                half_length,
+                material,
+            } => triangular_prism(*radius, *half_length, material.build(named)?),
+            SurfaceDesc::Union(surfaces) => fold(surfaces, named, &union)?,
+            SurfaceDesc::Intersection(surfaces) => fold(surfaces, named, &intersection)?,
+            SurfaceDesc::Subtraction { base, cut } => subtraction(build(base)?, build(cut)?),
+            SurfaceDesc::SmoothUnion { surfaces, blend } => {
+                fold(surfaces, named, &|a, b| smooth_union(a, b, *blend))?
+            }
+            SurfaceDesc::SmoothIntersection { surfaces, blend } => {
+                fold(surfaces, named, &|a, b| smooth_intersection(a, b, *blend))?
+            }
+            SurfaceDesc::SmoothSubtraction { base, cut, blend } => {
+                smooth_subtraction(build(base)?, build(cut)?, *blend)
+            }
+            SurfaceDesc::Translation { offset, surface } => translation(*offset, build(surface)?),
+            SurfaceDesc::Rotation {
+                rotation: rotation_desc,
+                surface,
+            } => rotation(rotation_desc.to_quat(), build(surface)?),
+            SurfaceDesc::Scale { factor, surface } => scale(*factor, build(surface)?),
+            SurfaceDesc::Transform {
+                translation,
+                rotation,
+                scale,
+                surface,
+            } => translation_rotation_scale(
+                *translation,
+                rotation.to_quat(),
+                *scale,
+                build(surface)?,
+            ),
+            SurfaceDesc::Mandelbulb {
+                power,
+                iterations,
+                bailout,
+                material,
+            } => Arc::new(
+                Mandelbulb::new(*power, material.build(named)?)
+                    .with_iterations(*iterations)
+                    .with_bailout(*bailout),
+            ),
+            SurfaceDesc::Mesh { path, material } => Arc::new(
+                MeshSurface::load(path, material.build(named)?).map_err(|err| {
+                    invalid_data(format!("could not load mesh \"{}\": {}", path, err))
+                })?,
+            ),
+            SurfaceDesc::Heightfield {
+                path,
+                size,
+                height,
+                material,
+            } => Arc::new(
+                Heightfield::load(path, *size, *height, material.build(named)?).map_err(|err| {
+                    invalid_data(format!("could not load heightmap \"{}\": {}", path, err))
+                })?,
+            ),
+            SurfaceDesc::FbmTerrain { noise, material } => {
+                fbm_terrain(*noise, material.build(named)?)
+            }
+            SurfaceDesc::Voxels {
+                path,
+                interpolation,
+                material,
+            } => Arc::new(
+                VoxelSdf::load(path, material.build(named)?)
+                    .map_err(|err| {
+                        invalid_data(format!("could not load voxels \"{}\": {}", path, err))
+                    })?
+                    .with_interpolation(*interpolation),
+            ),
+            SurfaceDesc::MengerSponge {
+                size,
+                iterations,
+                material,
+            } => Arc::new(
+                MengerSponge::new(*size, material.build(named)?).with_iterations(*iterations),
+            ),
+            SurfaceDesc::SierpinskiTetrahedron {
+                size,
+                iterations,
+                material,
+            } => Arc::new(
+                SierpinskiTetrahedron::new(*size, material.build(named)?)
+                    .with_iterations(*iterations),
+            ),
+            SurfaceDesc::Mandelbox {
+                scale,
+                min_radius,
+                fold_limit,
+                iterations,
+                material,
+            } => Arc::new(
+                Mandelbox::new(*scale, material.build(named)?)
+                    .with_min_radius(*min_radius)
+                    .with_fold_limit(*fold_limit)
+                    .with_iterations(*iterations),
+            ),
+            SurfaceDesc::Repetition { period, surface } => {
+                infinite_repetition(*period, build(surface)?)
+            }
+            SurfaceDesc::Displace { noise, surface } => displace(*noise, build(surface)?),
+            SurfaceDesc::Expr(text) => Env::new().eval_surface(text, "expression")?,
+        })
+    }
+}
+
+/// Builds the surfaces and combines them from left to right
+fn fold(
+    surfaces: &[SurfaceDesc],
+    named: &BTreeMap<String, Material>,
+    combine: &dyn Fn(Surface, Surface) -> Surface,
+) -> io::Result<Surface> {
+    let (first, rest) = surfaces
+        .split_first()
+        .ok_or_else(|| invalid_data("expected at least one surface".to_string()))?;
+    let mut combined = first.build(named)?;
+    for surface in rest {
+        combined = combine(combined, surface.build(named)?);
+    }
+    Ok(combined)
+}
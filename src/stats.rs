@@ -0,0 +1,347 @@
+use std::{collections::VecDeque, fs, path::Path, time::Duration};
+
+/// Counters and timings of one rendered frame
+///
+/// Times other than frame_time are summed over all render threads, so they can add up to more
+/// than frame_time. They stay zero unless RenderSettings::timings is on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    /// Rays from the camera, 4 per pixel with anti aliasing
+    pub primary_rays: u64,
+    /// Rays towards the light, one per primary ray that hit something while shadows are on
+    pub shadow_rays: u64,
+    /// Calls to the sdf of the top level surfaces, estimated as one per surface for every step
+    /// and five more to shade a hit. Debug views and the selection tint aren't counted
+    pub estimated_sdf_evaluations: u64,
+    /// Steps summed over all primary rays
+    pub primary_steps: u64,
+    /// Most steps a single primary ray took
+    pub max_steps: u32,
+    /// Primary rays that used every step without converging on a surface
+    pub step_limit_rays: u64,
+    pub march_time: Duration,
+    /// Time spent coloring hits, not counting shadows
+    pub shading_time: Duration,
+    pub shadow_time: Duration,
+    /// Wall clock time of the whole frame
+    pub frame_time: Duration,
+}
+
+impl RenderStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Combines the stats of two parts of a frame
+    pub fn merge(self, other: RenderStats) -> RenderStats {
+        RenderStats {
+            primary_rays: self.primary_rays + other.primary_rays,
+            shadow_rays: self.shadow_rays + other.shadow_rays,
+            estimated_sdf_evaluations: self.estimated_sdf_evaluations
+                + other.estimated_sdf_evaluations,
+            primary_steps: self.primary_steps + other.primary_steps,
+            max_steps: self.max_steps.max(other.max_steps),
+            step_limit_rays: self.step_limit_rays + other.step_limit_rays,
+            march_time: self.march_time + other.march_time,
+            shading_time: self.shading_time + other.shading_time,
+            shadow_time: self.shadow_time + other.shadow_time,
+            frame_time: self.frame_time + other.frame_time,
+        }
+    }
+
+    pub fn average_steps(&self) -> f32 {
+        if self.primary_rays == 0 {
+            return 0.0;
+        }
+        self.primary_steps as f32 / self.primary_rays as f32
+    }
+
+    /// Human readable summary, one line per group of values
+    pub fn lines(&self) -> Vec<String> {
+        let total = (self.march_time + self.shading_time + self.shadow_time).as_secs_f32();
+        let percent = |time: Duration| {
+            if total > 0.0 {
+                100.0 * time.as_secs_f32() / total
+            } else {
+                0.0
+            }
+        };
+        let mut lines = vec![
+            format!("frame {:.1} ms", self.frame_time.as_secs_f32() * 1000.0),
+            format!(
+                "rays {} primary {} shadow",
+                self.primary_rays, self.shadow_rays
+            ),
+            format!(
+                "steps avg {:.1} max {} limit {}",
+                self.average_steps(),
+                self.max_steps,
+                self.step_limit_rays
+            ),
+            format!(
+                "sdf {:.2}m evaluations estimated",
+                self.estimated_sdf_evaluations as f32 / 1e6
+            ),
+        ];
+        // Without timings there is nothing to split
+        if total > 0.0 {
+            lines.push(format!(
+                "march {:.0}% shade {:.0}% shadow {:.0}%",
+                percent(self.march_time),
+                percent(self.shading_time),
+                percent(self.shadow_time)
+            ));
+        }
+        lines
+    }
+}
+
+//
+// Log
+//
+
+/// Column names of the csv output, also the keys of the json output
+const COLUMNS: [&str; 10] = [
+    "primary_rays",
+    "shadow_rays",
+    "estimated_sdf_evaluations",
+    "primary_steps",
+    "max_steps",
+    "step_limit_rays",
+    "march_ms",
+    "shading_ms",
+    "shadow_ms",
+    "frame_ms",
+];
+
+/// Stats of consecutive frames, keeps the most recent frames if given a limit
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsLog {
+    frames: VecDeque<RenderStats>,
+    limit: Option<usize>,
+}
+
+impl StatsLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops the oldest frames when there are more than limit
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn push(&mut self, stats: RenderStats) {
+        self.frames.push_back(stats);
+        if let Some(limit) = self.limit {
+            while self.frames.len() > limit {
+                self.frames.pop_front();
+            }
+        }
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &RenderStats> {
+        self.frames.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Stats of all frames merged
+    pub fn total(&self) -> RenderStats {
+        self.frames
+            .iter()
+            .fold(RenderStats::new(), |total, stats| total.merge(*stats))
+    }
+
+    /// Mean wall clock time of a frame, zero without frames
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frames.is_empty() {
+            return Duration::ZERO;
+        }
+        self.total().frame_time / self.frames.len() as u32
+    }
+
+    /// One header line and one line per frame
+    pub fn to_csv(&self) -> String {
+        let mut text = format!("frame,{}\n", COLUMNS.join(","));
+        for (i, stats) in self.frames.iter().enumerate() {
+            let values = values(stats);
+            text += &format!("{},{}\n", i, values.join(","));
+        }
+        text
+    }
+
+    /// Array with one object per frame
+    pub fn to_json(&self) -> String {
+        let frames = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, stats)| {
+                let fields = COLUMNS
+                    .iter()
+                    .zip(values(stats))
+                    .map(|(column, value)| format!("\"{}\": {}", column, value))
+                    .collect::<Vec<_>>();
+                format!("  {{\"frame\": {}, {}}}", i, fields.join(", "))
+            })
+            .collect::<Vec<_>>();
+        format!("[\n{}\n]\n", frames.join(",\n"))
+    }
+
+    /// Saves as json if path ends in .json and as csv otherwise, creating missing directories
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = match Path::new(path).extension() {
+            Some(extension) if extension == "json" => self.to_json(),
+            _ => self.to_csv(),
+        };
+        fs::write(path, text)
+    }
+}
+
+/// Values in the order of COLUMNS
+fn values(stats: &RenderStats) -> Vec<String> {
+    let ms = |time: Duration| format!("{:.3}", time.as_secs_f64() * 1000.0);
+    vec![
+        stats.primary_rays.to_string(),
+        stats.shadow_rays.to_string(),
+        stats.estimated_sdf_evaluations.to_string(),
+        stats.primary_steps.to_string(),
+        stats.max_steps.to_string(),
+        stats.step_limit_rays.to_string(),
+        ms(stats.march_time),
+        ms(stats.shading_time),
+        ms(stats.shadow_time),
+        ms(stats.frame_time),
+    ]
+}
+
+//
+// Overlay
+//
+
+const OVERLAY_SCALE: u32 = 2;
+const OVERLAY_MARGIN: u32 = 4;
+const OVERLAY_LINE_SPACING: u32 = 2;
+const OVERLAY_BACKGROUND: [u8; 3] = [0, 0, 0];
+const OVERLAY_TEXT: [u8; 3] = [255, 255, 255];
+
+/// Draws the lines of stats in the top left corner
+///
+/// pixels is RGBA with width pixels per row, starting at the top left like the canvas
+pub fn draw_overlay(pixels: &mut [u8], width: u32, stats: &RenderStats) {
+    let height = pixels.len() as u32 / 4 / width;
+    let lines = stats.lines();
+    let glyph_width = (GLYPH_WIDTH + 1) * OVERLAY_SCALE;
+    let line_height = (GLYPH_HEIGHT + OVERLAY_LINE_SPACING) * OVERLAY_SCALE;
+
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u32;
+    let box_width = columns * glyph_width + 2 * OVERLAY_MARGIN;
+    let box_height = lines.len() as u32 * line_height + 2 * OVERLAY_MARGIN;
+    for y in 0..box_height.min(height) {
+        for x in 0..box_width.min(width) {
+            set_pixel(pixels, width, x, y, OVERLAY_BACKGROUND);
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = OVERLAY_MARGIN + row as u32 * line_height;
+        for (column, c) in line.chars().enumerate() {
+            let left = OVERLAY_MARGIN + column as u32 * glyph_width;
+            draw_glyph(pixels, width, height, left, top, glyph(c));
+        }
+    }
+}
+
+fn draw_glyph(pixels: &mut [u8], width: u32, height: u32, left: u32, top: u32, glyph: u16) {
+    for gy in 0..GLYPH_HEIGHT {
+        for gx in 0..GLYPH_WIDTH {
+            let bit = (GLYPH_HEIGHT - 1 - gy) * GLYPH_WIDTH + (GLYPH_WIDTH - 1 - gx);
+            if glyph & (1 << bit) == 0 {
+                continue;
+            }
+            for sy in 0..OVERLAY_SCALE {
+                for sx in 0..OVERLAY_SCALE {
+                    let x = left + gx * OVERLAY_SCALE + sx;
+                    let y = top + gy * OVERLAY_SCALE + sy;
+                    if x < width && y < height {
+                        set_pixel(pixels, width, x, y, OVERLAY_TEXT);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn set_pixel(pixels: &mut [u8], width: u32, x: u32, y: u32, color: [u8; 3]) {
+    let index = ((y * width + x) * 4) as usize;
+    pixels[index..index + 3].copy_from_slice(&color);
+    pixels[index + 3] = 255;
+}
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// 3x5 bitmap of c, one row of 3 bits per line starting at the top. Unknown characters are blank
+#[rustfmt::skip]
+fn glyph(c: char) -> u16 {
+    let rows: [u16; 5] = match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    };
+    rows.iter().fold(0, |bits, row| bits << GLYPH_WIDTH | row)
+}
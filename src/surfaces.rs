@@ -1,9 +1,19 @@
-use std::sync::Arc;
+use std::{io, sync::Arc};
 
-use glam::{Quat, Vec3};
+use glam::{vec2, vec3, Quat, Vec2, Vec2Swizzles, Vec3, Vec3Swizzles};
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    DynamicImage, ImageError, ImageResult,
+};
 use noise::{NoiseFn, Perlin};
 
-use crate::materials::MaterialTrait;
+use crate::{
+    fbm::{Fbm, FbmSettings},
+    materials::{MaterialTrait, OrbitTrap},
+    mesh::Mesh,
+    scene_file::{RotationDesc, SurfaceDesc},
+    voxels::{Interpolation, VoxelGrid},
+};
 
 //
 // Type definitions
@@ -16,6 +26,124 @@ pub type Material = Arc<dyn MaterialTrait + Sync + Send>;
 pub trait SurfaceTrait: Sync + Send {
     fn sdf(&self, pos: Vec3) -> f32;
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3;
+
+    /// For surfaces made of other surfaces, returns the index of the child that decides the
+    /// color at pos together with the child and pos in the space of the child
+    fn closest_child(&self, _pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        None
+    }
+
+    /// Box containing the surface, None if the surface is unbounded
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// Upper bound on how much sdf changes per unit moved, 1 for exact distances
+    ///
+    /// The marcher divides its steps by this, so surfaces whose sdf overestimates the distance
+    /// aren't stepped through
+    fn lipschitz(&self) -> f32 {
+        1.0
+    }
+
+    /// Box the marcher can step up to without dividing by lipschitz, defaults to the bounds
+    ///
+    /// Unlike bounds it may be endless along some axes, like the layer a terrain stays within
+    fn march_bounds(&self) -> Option<Aabb> {
+        self.bounds()
+    }
+
+    /// Description for the scene file format, None for surfaces that can't be saved
+    fn describe(&self) -> Option<SurfaceDesc> {
+        None
+    }
+}
+
+//
+// Bounding box
+//
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest box containing all points
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+        for point in points {
+            min = min.min(point);
+            max = max.max(point);
+        }
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            vec3(a.x, a.y, a.z),
+            vec3(b.x, a.y, a.z),
+            vec3(a.x, b.y, a.z),
+            vec3(b.x, b.y, a.z),
+            vec3(a.x, a.y, b.z),
+            vec3(b.x, a.y, b.z),
+            vec3(a.x, b.y, b.z),
+            vec3(b.x, b.y, b.z),
+        ]
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.max(other.min), self.max.min(other.max))
+    }
+
+    /// Grows the box by amount in every direction
+    pub fn expand(&self, amount: f32) -> Aabb {
+        Aabb::new(
+            self.min - Vec3::splat(amount),
+            self.max + Vec3::splat(amount),
+        )
+    }
+
+    /// Distance from pos to the box, 0 inside
+    pub fn distance(&self, pos: Vec3) -> f32 {
+        (self.min - pos)
+            .max(pos - self.max)
+            .max(Vec3::ZERO)
+            .length()
+    }
+}
+
+/// Bounds of the union of two optional bounds, None if either is unbounded
+fn union_bounds(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    Some(a?.union(&b?))
+}
+
+/// Bounds of the intersection of two optional bounds, None if both are unbounded
+fn intersection_bounds(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b)),
+        (a, b) => a.or(b),
+    }
 }
 
 //
@@ -42,6 +170,20 @@ impl SurfaceTrait for Sphere {
         // vec3(1.0, 1.0, 1.0)
         self.material.color(ray, pos, normal, light_pos)
     }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            Vec3::splat(-self.radius),
+            Vec3::splat(self.radius),
+        ))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Sphere {
+            radius: self.radius,
+            material: self.material.describe()?,
+        })
+    }
 }
 pub fn sphere(radius: f32, material: Material) -> Surface {
     Arc::new(Sphere::new(radius, material))
@@ -71,6 +213,17 @@ impl SurfaceTrait for BoxExact {
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
         self.material.color(ray, pos, normal, light_pos)
     }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.b, self.b))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Box {
+            half_size: self.b,
+            material: self.material.describe()?,
+        })
+    }
 }
 pub fn exact_box(b: Vec3, material: Material) -> Surface {
     Arc::new(BoxExact::new(b, material))
@@ -105,512 +258,2655 @@ impl SurfaceTrait for Plane {
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
         self.material.color(ray, pos, normal, light_pos)
     }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Plane {
+            normal: self.normal,
+            distance: self.distance_along_normal,
+            material: self.material.describe()?,
+        })
+    }
 }
 pub fn plane(normal: Vec3, distance_along_normal: f32, material: Material) -> Surface {
     Arc::new(Plane::new(normal, distance_along_normal, material))
 }
 
 //
-// Union
+// Torus
 //
 
-pub struct Union {
-    surface1: Arc<dyn SurfaceTrait>,
-    surface2: Arc<dyn SurfaceTrait>,
+/// Ring in the xz plane around the y axis
+pub struct Torus {
+    major_radius: f32,
+    minor_radius: f32,
+    material: Material,
 }
 
-impl Union {
-    pub fn new(surface1: Arc<dyn SurfaceTrait>, surface2: Arc<dyn SurfaceTrait>) -> Self {
-        Self { surface1, surface2 }
+impl Torus {
+    /// major_radius is the distance from the center to the middle of the tube, minor_radius the
+    /// radius of the tube
+    pub fn new(major_radius: f32, minor_radius: f32, material: Material) -> Self {
+        Self {
+            major_radius,
+            minor_radius,
+            material,
+        }
     }
 }
 
-impl SurfaceTrait for Union {
+impl SurfaceTrait for Torus {
     fn sdf(&self, pos: Vec3) -> f32 {
-        self.surface1.sdf(pos).min(self.surface2.sdf(pos))
+        let q = vec2(pos.xz().length() - self.major_radius, pos.y);
+        q.length() - self.minor_radius
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
+        self.material.color(ray, pos, normal, light_pos)
+    }
 
-        if dist2 < dist1 {
-            self.surface2.color(ray, pos, normal, light_pos)
-        } else {
-            self.surface1.color(ray, pos, normal, light_pos)
-        }
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let half_size = vec3(outer, self.minor_radius, outer);
+        Some(Aabb::new(-half_size, half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Torus {
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn union(surface1: Surface, surface2: Surface) -> Surface {
-    Arc::new(Union::new(surface1, surface2))
+pub fn torus(major_radius: f32, minor_radius: f32, material: Material) -> Surface {
+    Arc::new(Torus::new(major_radius, minor_radius, material))
 }
+
 //
-// Subtraction
+// Capped Torus
 //
 
-/// Subtracts surface2 from surface1
-pub struct Subtraction {
-    surface1: Surface,
-    surface2: Surface,
+/// Arc of a torus in the xy plane around the z axis, centered on the y axis
+pub struct CappedTorus {
+    angle: f32,
+    /// sin and cos of angle
+    sin_cos: Vec2,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Material,
 }
 
-impl Subtraction {
-    pub fn new(surface1: Surface, surface2: Surface) -> Self {
-        Self { surface1, surface2 }
+impl CappedTorus {
+    /// angle in radians is half the opening of the arc, PI gives a full torus
+    pub fn new(angle: f32, major_radius: f32, minor_radius: f32, material: Material) -> Self {
+        Self {
+            angle,
+            sin_cos: vec2(angle.sin(), angle.cos()),
+            major_radius,
+            minor_radius,
+            material,
+        }
     }
 }
 
-impl SurfaceTrait for Subtraction {
+impl SurfaceTrait for CappedTorus {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
-        (dist1).max(-dist2)
+        let pos = vec3(pos.x.abs(), pos.y, pos.z);
+        let sc = self.sin_cos;
+        let k = if sc.y * pos.x > sc.x * pos.y {
+            pos.xy().dot(sc)
+        } else {
+            pos.xy().length()
+        };
+        let ra = self.major_radius;
+        (pos.dot(pos) + ra * ra - 2.0 * ra * k).max(0.0).sqrt() - self.minor_radius
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
+        self.material.color(ray, pos, normal, light_pos)
+    }
 
-        if -dist2 > dist1 {
-            self.surface2.color(ray, pos, normal, light_pos)
-        } else {
-            self.surface1.color(ray, pos, normal, light_pos)
-        }
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let half_size = vec3(outer, outer, self.minor_radius);
+        Some(Aabb::new(-half_size, half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::CappedTorus {
+            angle: self.angle.to_degrees(),
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn subtraction(surface1: Surface, surface2: Surface) -> Surface {
-    Arc::new(Subtraction::new(surface1, surface2))
+pub fn capped_torus(
+    angle: f32,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Material,
+) -> Surface {
+    Arc::new(CappedTorus::new(
+        angle,
+        major_radius,
+        minor_radius,
+        material,
+    ))
 }
+
 //
-// Intersection
+// Link
 //
 
-pub struct Intersection {
-    surface1: Surface,
-    surface2: Surface,
+/// Chain link, a torus in the xy plane stretched along the y axis
+pub struct Link {
+    half_length: f32,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Material,
 }
 
-impl Intersection {
-    pub fn new(surface1: Surface, surface2: Surface) -> Self {
-        Self { surface1, surface2 }
+impl Link {
+    /// half_length is how far each half of the torus is moved from the center
+    pub fn new(half_length: f32, major_radius: f32, minor_radius: f32, material: Material) -> Self {
+        Self {
+            half_length,
+            major_radius,
+            minor_radius,
+            material,
+        }
     }
 }
 
-impl SurfaceTrait for Intersection {
+impl SurfaceTrait for Link {
     fn sdf(&self, pos: Vec3) -> f32 {
-        self.surface1.sdf(pos).max(self.surface2.sdf(pos))
+        let q = vec3(pos.x, (pos.y.abs() - self.half_length).max(0.0), pos.z);
+        vec2(q.xy().length() - self.major_radius, q.z).length() - self.minor_radius
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
+        self.material.color(ray, pos, normal, light_pos)
+    }
 
-        if dist2 > dist1 {
-            self.surface2.color(ray, pos, normal, light_pos)
-        } else {
-            self.surface1.color(ray, pos, normal, light_pos)
-        }
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let half_size = vec3(outer, self.half_length + outer, self.minor_radius);
+        Some(Aabb::new(-half_size, half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Link {
+            half_length: self.half_length,
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn intersection(surface1: Surface, surface2: Surface) -> Surface {
-    Arc::new(Intersection::new(surface1, surface2))
+pub fn link(half_length: f32, major_radius: f32, minor_radius: f32, material: Material) -> Surface {
+    Arc::new(Link::new(half_length, major_radius, minor_radius, material))
 }
 
 //
-// SmoothUnion
+// Capsule
 //
 
-pub struct SmoothUnion {
-    surface1: Arc<dyn SurfaceTrait>,
-    surface2: Arc<dyn SurfaceTrait>,
-    blend_factor: f32,
+/// Line segment from start to end with a radius, a radius of 0 gives the segment itself
+pub struct Capsule {
+    start: Vec3,
+    end: Vec3,
+    radius: f32,
+    material: Material,
 }
 
-impl SmoothUnion {
-    pub fn new(
-        surface1: Arc<dyn SurfaceTrait>,
-        surface2: Arc<dyn SurfaceTrait>,
-        blend_factor: f32,
-    ) -> Self {
+impl Capsule {
+    pub fn new(start: Vec3, end: Vec3, radius: f32, material: Material) -> Self {
         Self {
-            surface1,
-            surface2,
-            blend_factor,
+            start,
+            end,
+            radius,
+            material,
         }
     }
 }
 
-impl SurfaceTrait for SmoothUnion {
+impl SurfaceTrait for Capsule {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
-
-        let h = (0.5 + 0.5 * (dist2 - dist1) / self.blend_factor).clamp(0.0, 1.0);
-        interpolate_f32(dist2, dist1, h) - self.blend_factor * h * (1.0 - h)
+        let pa = pos - self.start;
+        let ba = self.end - self.start;
+        let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+        // A zero length segment is a sphere
+        let h = if h.is_nan() { 0.0 } else { h };
+        (pa - ba * h).length() - self.radius
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
-        let h = (0.5 + 0.5 * (dist1 - dist2) / self.blend_factor).clamp(0.0, 1.0);
+        self.material.color(ray, pos, normal, light_pos)
+    }
 
-        let color1 = self.surface1.color(ray, pos, normal, light_pos);
-        let color2 = self.surface2.color(ray, pos, normal, light_pos);
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points([self.start, self.end]).expand(self.radius))
+    }
 
-        interpolate_vec3(color1, color2, h)
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Capsule {
+            start: self.start,
+            end: self.end,
+            radius: self.radius,
+            material: self.material.describe()?,
+        })
     }
 }
-
-pub fn smooth_union(surface1: Surface, surface2: Surface, blend_factor: f32) -> Surface {
-    Arc::new(SmoothUnion::new(surface1, surface2, blend_factor))
+pub fn capsule(start: Vec3, end: Vec3, radius: f32, material: Material) -> Surface {
+    Arc::new(Capsule::new(start, end, radius, material))
 }
 
 //
-// Smooth Subtraction
+// Cylinder
 //
 
-/// Subtracts surface2 from surface1
-pub struct SmoothSubtraction {
-    surface1: Surface,
-    surface2: Surface,
-    blend_factor: f32,
+/// Cylinder along the y axis, centered on the origin
+pub struct Cylinder {
+    half_height: f32,
+    radius: f32,
+    material: Material,
 }
 
-impl SmoothSubtraction {
-    pub fn new(surface1: Surface, surface2: Surface, blend_factor: f32) -> Self {
+impl Cylinder {
+    pub fn new(half_height: f32, radius: f32, material: Material) -> Self {
         Self {
-            surface1,
-            surface2,
-            blend_factor,
+            half_height,
+            radius,
+            material,
         }
     }
 }
 
-impl SurfaceTrait for SmoothSubtraction {
+impl SurfaceTrait for Cylinder {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let dist2 = self.surface1.sdf(pos);
-        let dist1 = self.surface2.sdf(pos);
-
-        let h = (0.5 - 0.5 * (dist2 + dist1) / self.blend_factor).clamp(0.0, 1.0);
-
-        interpolate_f32(dist2, -dist1, h) + self.blend_factor * h * (1.0 - h)
+        let d = vec2(pos.xz().length(), pos.y).abs() - vec2(self.radius, self.half_height);
+        d.x.max(d.y).min(0.0) + d.max(Vec2::ZERO).length()
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
-
-        let h = (0.5 - 0.5 * (dist2 + dist1) / self.blend_factor).clamp(0.0, 1.0);
+        self.material.color(ray, pos, normal, light_pos)
+    }
 
-        let color1 = self.surface1.color(ray, pos, normal, light_pos);
-        let color2 = self.surface2.color(ray, pos, normal, light_pos);
+    fn bounds(&self) -> Option<Aabb> {
+        let half_size = vec3(self.radius, self.half_height, self.radius);
+        Some(Aabb::new(-half_size, half_size))
+    }
 
-        interpolate_vec3(color1, color2, h)
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Cylinder {
+            half_height: self.half_height,
+            radius: self.radius,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn smooth_subtraction(surface1: Surface, surface2: Surface, blend_factor: f32) -> Surface {
-    Arc::new(SmoothSubtraction::new(surface1, surface2, blend_factor))
+pub fn cylinder(half_height: f32, radius: f32, material: Material) -> Surface {
+    Arc::new(Cylinder::new(half_height, radius, material))
 }
 
 //
-// Smooth Intersection
+// Capped Cylinder
 //
 
-pub struct SmoothIntersection {
-    surface1: Surface,
-    surface2: Surface,
-    blend_factor: f32,
+/// Cylinder with flat caps at start and end
+pub struct CappedCylinder {
+    start: Vec3,
+    end: Vec3,
+    radius: f32,
+    material: Material,
 }
 
-impl SmoothIntersection {
-    pub fn new(surface1: Surface, surface2: Surface, blend_factor: f32) -> Self {
+impl CappedCylinder {
+    pub fn new(start: Vec3, end: Vec3, radius: f32, material: Material) -> Self {
         Self {
-            surface1,
-            surface2,
-            blend_factor,
+            start,
+            end,
+            radius,
+            material,
         }
     }
 }
 
-impl SurfaceTrait for SmoothIntersection {
+impl SurfaceTrait for CappedCylinder {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
-
-        // Distance
-        let h = (0.5 - 0.5 * (dist2 - dist1) / self.blend_factor).clamp(0.0, 1.0);
-        interpolate_f32(dist2, dist1, h) + self.blend_factor * h * (1.0 - h)
+        let ba = self.end - self.start;
+        let pa = pos - self.start;
+        let baba = ba.dot(ba);
+        let paba = pa.dot(ba);
+
+        // Distances to the side and the caps, scaled by baba to avoid a square root
+        let x = (pa * baba - ba * paba).length() - self.radius * baba;
+        let y = (paba - baba * 0.5).abs() - baba * 0.5;
+        let x2 = x * x;
+        let y2 = y * y * baba;
+        let d = if x.max(y) < 0.0 {
+            -x2.min(y2)
+        } else {
+            (if x > 0.0 { x2 } else { 0.0 }) + (if y > 0.0 { y2 } else { 0.0 })
+        };
+        d.signum() * d.abs().sqrt() / baba
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let dist1 = self.surface1.sdf(pos);
-        let dist2 = self.surface2.sdf(pos);
-
-        let h = (0.5 - 0.5 * (dist2 - dist1) / self.blend_factor).clamp(0.0, 1.0);
+        self.material.color(ray, pos, normal, light_pos)
+    }
 
-        let color1 = self.surface1.color(ray, pos, normal, light_pos);
-        let color2 = self.surface2.color(ray, pos, normal, light_pos);
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points([self.start, self.end]).expand(self.radius))
+    }
 
-        interpolate_vec3(color2, color1, h)
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::CappedCylinder {
+            start: self.start,
+            end: self.end,
+            radius: self.radius,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn smooth_intersection(surface1: Surface, surface2: Surface, blend_factor: f32) -> Surface {
-    Arc::new(SmoothIntersection::new(surface1, surface2, blend_factor))
+pub fn capped_cylinder(start: Vec3, end: Vec3, radius: f32, material: Material) -> Surface {
+    Arc::new(CappedCylinder::new(start, end, radius, material))
 }
 
 //
-// Translation
+// Capped Cone
 //
 
-pub struct Translation {
-    translation: Vec3,
-    surface: Surface,
+/// Cone along the y axis cut flat at both ends, centered on the origin
+pub struct CappedCone {
+    half_height: f32,
+    bottom_radius: f32,
+    top_radius: f32,
+    material: Material,
 }
 
-impl Translation {
-    pub fn new(translation: Vec3, surface: Surface) -> Self {
+impl CappedCone {
+    pub fn new(half_height: f32, bottom_radius: f32, top_radius: f32, material: Material) -> Self {
         Self {
-            surface,
-            translation,
+            half_height,
+            bottom_radius,
+            top_radius,
+            material,
         }
     }
 }
 
-impl SurfaceTrait for Translation {
+impl SurfaceTrait for CappedCone {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let new_pos = pos - self.translation;
-        self.surface.sdf(new_pos)
+        let (h, r1, r2) = (self.half_height, self.bottom_radius, self.top_radius);
+        let q = vec2(pos.xz().length(), pos.y);
+        let k1 = vec2(r2, h);
+        let k2 = vec2(r2 - r1, 2.0 * h);
+
+        // Closest points on the caps and on the side
+        let cap_radius = if q.y < 0.0 { r1 } else { r2 };
+        let ca = vec2(q.x - q.x.min(cap_radius), q.y.abs() - h);
+        let cb = q - k1 + k2 * ((k1 - q).dot(k2) / k2.dot(k2)).clamp(0.0, 1.0);
+        let sign = if cb.x < 0.0 && ca.y < 0.0 { -1.0 } else { 1.0 };
+        sign * ca.dot(ca).min(cb.dot(cb)).sqrt()
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        let new_pos = pos - self.translation;
-        self.surface.color(ray, new_pos, normal, light_pos)
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = self.bottom_radius.max(self.top_radius);
+        let half_size = vec3(radius, self.half_height, radius);
+        Some(Aabb::new(-half_size, half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::CappedCone {
+            half_height: self.half_height,
+            bottom_radius: self.bottom_radius,
+            top_radius: self.top_radius,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn capped_cone(
+    half_height: f32,
+    bottom_radius: f32,
+    top_radius: f32,
+    material: Material,
+) -> Surface {
+    Arc::new(CappedCone::new(
+        half_height,
+        bottom_radius,
+        top_radius,
+        material,
+    ))
+}
+
+//
+// Round Cone
+//
+
+/// Sphere at the origin and a sphere height above it on the y axis, joined by a cone
+///
+/// The difference of the radii has to be smaller than height
+pub struct RoundCone {
+    bottom_radius: f32,
+    top_radius: f32,
+    height: f32,
+    material: Material,
+}
+
+impl RoundCone {
+    pub fn new(bottom_radius: f32, top_radius: f32, height: f32, material: Material) -> Self {
+        Self {
+            bottom_radius,
+            top_radius,
+            height,
+            material,
+        }
+    }
+}
+
+impl SurfaceTrait for RoundCone {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let (r1, r2, h) = (self.bottom_radius, self.top_radius, self.height);
+        let b = (r1 - r2) / h;
+        let a = (1.0 - b * b).sqrt();
+        let q = vec2(pos.xz().length(), pos.y);
+
+        // Which of the two spheres or the cone between them is closest
+        let k = q.dot(vec2(-b, a));
+        if k < 0.0 {
+            q.length() - r1
+        } else if k > a * h {
+            (q - vec2(0.0, h)).length() - r2
+        } else {
+            q.dot(vec2(a, b)) - r1
+        }
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = self.bottom_radius.max(self.top_radius);
+        Some(Aabb::new(
+            vec3(-radius, -self.bottom_radius, -radius),
+            vec3(radius, self.height + self.top_radius, radius),
+        ))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::RoundCone {
+            bottom_radius: self.bottom_radius,
+            top_radius: self.top_radius,
+            height: self.height,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn round_cone(bottom_radius: f32, top_radius: f32, height: f32, material: Material) -> Surface {
+    Arc::new(RoundCone::new(bottom_radius, top_radius, height, material))
+}
+
+//
+// Rounded Box
+//
+
+pub struct RoundedBox {
+    half_size: Vec3,
+    radius: f32,
+    material: Material,
+}
+
+impl RoundedBox {
+    /// half_size includes the rounding, radius is the radius of the edges and corners
+    pub fn new(half_size: Vec3, radius: f32, material: Material) -> Self {
+        Self {
+            half_size,
+            radius,
+            material,
+        }
+    }
+}
+
+impl SurfaceTrait for RoundedBox {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let q = pos.abs() - self.half_size + self.radius;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0) - self.radius
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.half_size, self.half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::RoundedBox {
+            half_size: self.half_size,
+            radius: self.radius,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn rounded_box(half_size: Vec3, radius: f32, material: Material) -> Surface {
+    Arc::new(RoundedBox::new(half_size, radius, material))
+}
+
+//
+// Box Frame
+//
+
+/// Edges of a box
+pub struct BoxFrame {
+    half_size: Vec3,
+    thickness: f32,
+    material: Material,
+}
+
+impl BoxFrame {
+    /// thickness is half the width of the edges, which are inside half_size
+    pub fn new(half_size: Vec3, thickness: f32, material: Material) -> Self {
+        Self {
+            half_size,
+            thickness,
+            material,
+        }
+    }
+}
+
+impl SurfaceTrait for BoxFrame {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let e = Vec3::splat(self.thickness);
+        let p = pos.abs() - self.half_size;
+        let q = (p + e).abs() - e;
+
+        // Closest of the edges along x, y and z
+        let edge = |v: Vec3| v.max(Vec3::ZERO).length() + v.max_element().min(0.0);
+        edge(vec3(p.x, q.y, q.z))
+            .min(edge(vec3(q.x, p.y, q.z)))
+            .min(edge(vec3(q.x, q.y, p.z)))
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.half_size, self.half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::BoxFrame {
+            half_size: self.half_size,
+            thickness: self.thickness,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn box_frame(half_size: Vec3, thickness: f32, material: Material) -> Surface {
+    Arc::new(BoxFrame::new(half_size, thickness, material))
+}
+
+//
+// Ellipsoid
+//
+
+/// Sphere stretched by radii along the axes
+///
+/// There is no closed form for the distance, sdf is an approximation that is never larger than
+/// the distance so the marcher can't overstep
+pub struct Ellipsoid {
+    radii: Vec3,
+    material: Material,
+}
+
+impl Ellipsoid {
+    pub fn new(radii: Vec3, material: Material) -> Self {
+        Self { radii, material }
+    }
+}
+
+impl SurfaceTrait for Ellipsoid {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let k0 = (pos / self.radii).length();
+        let k1 = (pos / (self.radii * self.radii)).length();
+        if k1 == 0.0 {
+            return -self.radii.min_element();
+        }
+        k0 * (k0 - 1.0) / k1
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.radii, self.radii))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Ellipsoid {
+            radii: self.radii,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn ellipsoid(radii: Vec3, material: Material) -> Surface {
+    Arc::new(Ellipsoid::new(radii, material))
+}
+
+//
+// Octahedron
+//
+
+pub struct Octahedron {
+    size: f32,
+    material: Material,
+}
+
+impl Octahedron {
+    /// size is the distance from the center to the corners
+    pub fn new(size: f32, material: Material) -> Self {
+        Self { size, material }
+    }
+}
+
+impl SurfaceTrait for Octahedron {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let s = self.size;
+        let p = pos.abs();
+        let m = p.x + p.y + p.z - s;
+
+        // Rotate the closest face to the same place, or return the distance to the face plane
+        let q = if 3.0 * p.x < m {
+            p
+        } else if 3.0 * p.y < m {
+            p.yzx()
+        } else if 3.0 * p.z < m {
+            p.zxy()
+        } else {
+            return m * 0.57735027;
+        };
+        let k = (0.5 * (q.z - q.y + s)).clamp(0.0, s);
+        vec3(q.x, q.y - s + k, q.z - k).length()
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(Vec3::splat(-self.size), Vec3::splat(self.size)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Octahedron {
+            size: self.size,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn octahedron(size: f32, material: Material) -> Surface {
+    Arc::new(Octahedron::new(size, material))
+}
+
+//
+// Pyramid
+//
+
+/// Pyramid with a square base on the xz plane and its top on the y axis
+pub struct Pyramid {
+    half_base: f32,
+    height: f32,
+    material: Material,
+}
+
+impl Pyramid {
+    /// half_base is half the side of the base
+    pub fn new(half_base: f32, height: f32, material: Material) -> Self {
+        Self {
+            half_base,
+            height,
+            material,
+        }
+    }
+}
+
+impl SurfaceTrait for Pyramid {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        // Distance to a pyramid with a base of size 1, scaled to the base
+        let size = 2.0 * self.half_base;
+        let p = pos / size;
+        let h = self.height / size;
+        let m2 = h * h + 0.25;
+
+        let xz = p.xz().abs();
+        let xz = if xz.y > xz.x { xz.yx() } else { xz } - 0.5;
+        let p = vec3(xz.x, p.y, xz.y);
+        let q = vec3(p.z, h * p.y - 0.5 * p.x, h * p.x + 0.5 * p.y);
+
+        let s = (-q.x).max(0.0);
+        let t = ((q.y - 0.5 * p.z) / (m2 + 0.25)).clamp(0.0, 1.0);
+        let a = m2 * (q.x + s) * (q.x + s) + q.y * q.y;
+        let b = m2 * (q.x + 0.5 * t) * (q.x + 0.5 * t) + (q.y - m2 * t) * (q.y - m2 * t);
+        let d2 = if q.y.min(-q.x * m2 - q.y * 0.5) > 0.0 {
+            0.0
+        } else {
+            a.min(b)
+        };
+        let sides = ((d2 + q.z * q.z) / m2).sqrt();
+
+        // Distance to the sides, or the base if it is closer
+        let dist = if q.z.max(-p.y) < 0.0 {
+            -sides.min(p.y)
+        } else {
+            let base = vec3(p.x.max(0.0), p.y, p.z.max(0.0)).length();
+            sides.min(base)
+        };
+        dist * size
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            vec3(-self.half_base, 0.0, -self.half_base),
+            vec3(self.half_base, self.height, self.half_base),
+        ))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Pyramid {
+            half_base: self.half_base,
+            height: self.height,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn pyramid(half_base: f32, height: f32, material: Material) -> Surface {
+    Arc::new(Pyramid::new(half_base, height, material))
+}
+
+//
+// Hexagonal Prism
+//
+
+/// Prism along the z axis with a hexagon with two sides parallel to the x axis
+pub struct HexagonalPrism {
+    radius: f32,
+    half_length: f32,
+    material: Material,
+}
+
+impl HexagonalPrism {
+    /// radius is the distance from the center to the sides
+    pub fn new(radius: f32, half_length: f32, material: Material) -> Self {
+        Self {
+            radius,
+            half_length,
+            material,
+        }
+    }
+}
+
+impl SurfaceTrait for HexagonalPrism {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let k = vec3(-0.8660254, 0.5, 0.57735);
+        let p = pos.abs();
+
+        // Mirror onto the side facing up
+        let xy = p.xy() - 2.0 * k.xy().dot(p.xy()).min(0.0) * k.xy();
+        let r = self.radius;
+        let edge = vec2(xy.x.clamp(-k.z * r, k.z * r), r);
+        let d = vec2(
+            (xy - edge).length() * (xy.y - r).signum(),
+            p.z - self.half_length,
+        );
+        d.x.max(d.y).min(0.0) + d.max(Vec2::ZERO).length()
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // The corners are further out than the sides
+        let corner = self.radius * 2.0 / 3f32.sqrt();
+        let half_size = vec3(corner, self.radius, self.half_length);
+        Some(Aabb::new(-half_size, half_size))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::HexagonalPrism {
+            radius: self.radius,
+            half_length: self.half_length,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn hexagonal_prism(radius: f32, half_length: f32, material: Material) -> Surface {
+    Arc::new(HexagonalPrism::new(radius, half_length, material))
+}
+
+//
+// Triangular Prism
+//
+
+/// Prism along the z axis with an equilateral triangle pointing up the y axis
+pub struct TriangularPrism {
+    radius: f32,
+    half_length: f32,
+    material: Material,
+}
+
+impl TriangularPrism {
+    /// radius is the distance from the center to the sides
+    pub fn new(radius: f32, half_length: f32, material: Material) -> Self {
+        Self {
+            radius,
+            half_length,
+            material,
+        }
+    }
+}
+
+impl SurfaceTrait for TriangularPrism {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let k = 3f32.sqrt();
+        let r = self.radius;
+
+        // Move the bottom right corner to the origin, then mirror onto the bottom side
+        let mut p = vec2(pos.x.abs() - r * k, pos.y + r);
+        if p.x + k * p.y > 0.0 {
+            p = vec2(p.x - k * p.y, -k * p.x - p.y) / 2.0;
+        }
+        p.x -= p.x.clamp(-2.0 * r * k, 0.0);
+        let triangle = -p.length() * p.y.signum();
+
+        let d = vec2(triangle, pos.z.abs() - self.half_length);
+        d.x.max(d.y).min(0.0) + d.max(Vec2::ZERO).length()
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // The top corner is twice as far as the sides
+        let r = self.radius;
+        let half_width = r * 3f32.sqrt();
+        Some(Aabb::new(
+            vec3(-half_width, -r, -self.half_length),
+            vec3(half_width, 2.0 * r, self.half_length),
+        ))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::TriangularPrism {
+            radius: self.radius,
+            half_length: self.half_length,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn triangular_prism(radius: f32, half_length: f32, material: Material) -> Surface {
+    Arc::new(TriangularPrism::new(radius, half_length, material))
+}
+
+//
+// Union
+//
+
+pub struct Union {
+    surface1: Arc<dyn SurfaceTrait>,
+    surface2: Arc<dyn SurfaceTrait>,
+}
+
+impl Union {
+    pub fn new(surface1: Arc<dyn SurfaceTrait>, surface2: Arc<dyn SurfaceTrait>) -> Self {
+        Self { surface1, surface2 }
+    }
+}
+
+impl SurfaceTrait for Union {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        self.surface1.sdf(pos).min(self.surface2.sdf(pos))
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        if dist2 < dist1 {
+            self.surface2.color(ray, pos, normal, light_pos)
+        } else {
+            self.surface1.color(ray, pos, normal, light_pos)
+        }
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        if self.surface2.sdf(pos) < self.surface1.sdf(pos) {
+            Some((1, &self.surface2, pos))
+        } else {
+            Some((0, &self.surface1, pos))
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        union_bounds(self.surface1.bounds(), self.surface2.bounds())
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface1.lipschitz().max(self.surface2.lipschitz())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Union(vec![
+            self.surface1.describe()?,
+            self.surface2.describe()?,
+        ]))
+    }
+}
+pub fn union(surface1: Surface, surface2: Surface) -> Surface {
+    Arc::new(Union::new(surface1, surface2))
+}
+//
+// Subtraction
+//
+
+/// Subtracts surface2 from surface1
+pub struct Subtraction {
+    surface1: Surface,
+    surface2: Surface,
+}
+
+impl Subtraction {
+    pub fn new(surface1: Surface, surface2: Surface) -> Self {
+        Self { surface1, surface2 }
+    }
+}
+
+impl SurfaceTrait for Subtraction {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+        (dist1).max(-dist2)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        if -dist2 > dist1 {
+            self.surface2.color(ray, pos, normal, light_pos)
+        } else {
+            self.surface1.color(ray, pos, normal, light_pos)
+        }
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        if -self.surface2.sdf(pos) > self.surface1.sdf(pos) {
+            Some((1, &self.surface2, pos))
+        } else {
+            Some((0, &self.surface1, pos))
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.surface1.bounds()
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface1.lipschitz().max(self.surface2.lipschitz())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Subtraction {
+            base: Box::new(self.surface1.describe()?),
+            cut: Box::new(self.surface2.describe()?),
+        })
+    }
+}
+pub fn subtraction(surface1: Surface, surface2: Surface) -> Surface {
+    Arc::new(Subtraction::new(surface1, surface2))
+}
+//
+// Intersection
+//
+
+pub struct Intersection {
+    surface1: Surface,
+    surface2: Surface,
+}
+
+impl Intersection {
+    pub fn new(surface1: Surface, surface2: Surface) -> Self {
+        Self { surface1, surface2 }
+    }
+}
+
+impl SurfaceTrait for Intersection {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        self.surface1.sdf(pos).max(self.surface2.sdf(pos))
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        if dist2 > dist1 {
+            self.surface2.color(ray, pos, normal, light_pos)
+        } else {
+            self.surface1.color(ray, pos, normal, light_pos)
+        }
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        if self.surface2.sdf(pos) > self.surface1.sdf(pos) {
+            Some((1, &self.surface2, pos))
+        } else {
+            Some((0, &self.surface1, pos))
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        intersection_bounds(self.surface1.bounds(), self.surface2.bounds())
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface1.lipschitz().max(self.surface2.lipschitz())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Intersection(vec![
+            self.surface1.describe()?,
+            self.surface2.describe()?,
+        ]))
+    }
+}
+pub fn intersection(surface1: Surface, surface2: Surface) -> Surface {
+    Arc::new(Intersection::new(surface1, surface2))
+}
+
+//
+// SmoothUnion
+//
+
+pub struct SmoothUnion {
+    surface1: Arc<dyn SurfaceTrait>,
+    surface2: Arc<dyn SurfaceTrait>,
+    blend_factor: f32,
+}
+
+impl SmoothUnion {
+    pub fn new(
+        surface1: Arc<dyn SurfaceTrait>,
+        surface2: Arc<dyn SurfaceTrait>,
+        blend_factor: f32,
+    ) -> Self {
+        Self {
+            surface1,
+            surface2,
+            blend_factor,
+        }
+    }
+}
+
+impl SurfaceTrait for SmoothUnion {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        let h = (0.5 + 0.5 * (dist2 - dist1) / self.blend_factor).clamp(0.0, 1.0);
+        interpolate_f32(dist2, dist1, h) - self.blend_factor * h * (1.0 - h)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+        let h = (0.5 + 0.5 * (dist1 - dist2) / self.blend_factor).clamp(0.0, 1.0);
+
+        let color1 = self.surface1.color(ray, pos, normal, light_pos);
+        let color2 = self.surface2.color(ray, pos, normal, light_pos);
+
+        interpolate_vec3(color1, color2, h)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        if self.surface2.sdf(pos) < self.surface1.sdf(pos) {
+            Some((1, &self.surface2, pos))
+        } else {
+            Some((0, &self.surface1, pos))
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // The blend adds at most a quarter of the blend factor
+        let bounds = union_bounds(self.surface1.bounds(), self.surface2.bounds())?;
+        Some(bounds.expand(self.blend_factor * 0.25))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface1.lipschitz().max(self.surface2.lipschitz())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::SmoothUnion {
+            surfaces: vec![self.surface1.describe()?, self.surface2.describe()?],
+            blend: self.blend_factor,
+        })
+    }
+}
+
+pub fn smooth_union(surface1: Surface, surface2: Surface, blend_factor: f32) -> Surface {
+    Arc::new(SmoothUnion::new(surface1, surface2, blend_factor))
+}
+
+//
+// Smooth Subtraction
+//
+
+/// Subtracts surface2 from surface1
+pub struct SmoothSubtraction {
+    surface1: Surface,
+    surface2: Surface,
+    blend_factor: f32,
+}
+
+impl SmoothSubtraction {
+    pub fn new(surface1: Surface, surface2: Surface, blend_factor: f32) -> Self {
+        Self {
+            surface1,
+            surface2,
+            blend_factor,
+        }
+    }
+}
+
+impl SurfaceTrait for SmoothSubtraction {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        let h = (0.5 - 0.5 * (dist1 + dist2) / self.blend_factor).clamp(0.0, 1.0);
+
+        interpolate_f32(dist1, -dist2, h) + self.blend_factor * h * (1.0 - h)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        let h = (0.5 - 0.5 * (dist1 + dist2) / self.blend_factor).clamp(0.0, 1.0);
+
+        let color1 = self.surface1.color(ray, pos, normal, light_pos);
+        let color2 = self.surface2.color(ray, pos, normal, light_pos);
+
+        interpolate_vec3(color1, color2, h)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        if -self.surface2.sdf(pos) > self.surface1.sdf(pos) {
+            Some((1, &self.surface2, pos))
+        } else {
+            Some((0, &self.surface1, pos))
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.surface1.bounds()
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface1.lipschitz().max(self.surface2.lipschitz())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::SmoothSubtraction {
+            base: Box::new(self.surface1.describe()?),
+            cut: Box::new(self.surface2.describe()?),
+            blend: self.blend_factor,
+        })
+    }
+}
+pub fn smooth_subtraction(surface1: Surface, surface2: Surface, blend_factor: f32) -> Surface {
+    Arc::new(SmoothSubtraction::new(surface1, surface2, blend_factor))
+}
+
+//
+// Smooth Intersection
+//
+
+pub struct SmoothIntersection {
+    surface1: Surface,
+    surface2: Surface,
+    blend_factor: f32,
+}
+
+impl SmoothIntersection {
+    pub fn new(surface1: Surface, surface2: Surface, blend_factor: f32) -> Self {
+        Self {
+            surface1,
+            surface2,
+            blend_factor,
+        }
+    }
+}
+
+impl SurfaceTrait for SmoothIntersection {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        // Distance
+        let h = (0.5 - 0.5 * (dist2 - dist1) / self.blend_factor).clamp(0.0, 1.0);
+        interpolate_f32(dist2, dist1, h) + self.blend_factor * h * (1.0 - h)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let dist1 = self.surface1.sdf(pos);
+        let dist2 = self.surface2.sdf(pos);
+
+        let h = (0.5 - 0.5 * (dist2 - dist1) / self.blend_factor).clamp(0.0, 1.0);
+
+        let color1 = self.surface1.color(ray, pos, normal, light_pos);
+        let color2 = self.surface2.color(ray, pos, normal, light_pos);
+
+        interpolate_vec3(color2, color1, h)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        if self.surface2.sdf(pos) > self.surface1.sdf(pos) {
+            Some((1, &self.surface2, pos))
+        } else {
+            Some((0, &self.surface1, pos))
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        intersection_bounds(self.surface1.bounds(), self.surface2.bounds())
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface1.lipschitz().max(self.surface2.lipschitz())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::SmoothIntersection {
+            surfaces: vec![self.surface1.describe()?, self.surface2.describe()?],
+            blend: self.blend_factor,
+        })
+    }
+}
+pub fn smooth_intersection(surface1: Surface, surface2: Surface, blend_factor: f32) -> Surface {
+    Arc::new(SmoothIntersection::new(surface1, surface2, blend_factor))
+}
+
+//
+// Translation
+//
+
+pub struct Translation {
+    translation: Vec3,
+    surface: Surface,
+}
+
+impl Translation {
+    pub fn new(translation: Vec3, surface: Surface) -> Self {
+        Self {
+            surface,
+            translation,
+        }
+    }
+}
+
+impl SurfaceTrait for Translation {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let new_pos = pos - self.translation;
+        self.surface.sdf(new_pos)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let new_pos = pos - self.translation;
+        self.surface.color(ray, new_pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        Some((0, &self.surface, pos - self.translation))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.surface.bounds()?;
+        Some(Aabb::new(
+            bounds.min + self.translation,
+            bounds.max + self.translation,
+        ))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface.lipschitz()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Translation {
+            offset: self.translation,
+            surface: Box::new(self.surface.describe()?),
+        })
+    }
+}
+pub fn translation(translation: Vec3, surface: Surface) -> Surface {
+    Arc::new(Translation::new(translation, surface))
+}
+
+//
+// Rotation
+//
+
+pub struct Rotation {
+    rotation: Quat,
+    surface: Surface,
+}
+
+impl Rotation {
+    pub fn new(rotation: Quat, surface: Surface) -> Self {
+        Self { surface, rotation }
+    }
+}
+
+impl SurfaceTrait for Rotation {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let new_pos = self.rotation * pos;
+        self.surface.sdf(new_pos)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.surface.color(ray, pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        Some((0, &self.surface, self.rotation * pos))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.surface.bounds()?;
+        let inverse = self.rotation.inverse();
+        Some(Aabb::from_points(
+            bounds.corners().map(|corner| inverse * corner),
+        ))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface.lipschitz()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Rotation {
+            rotation: RotationDesc::from_quat(self.rotation),
+            surface: Box::new(self.surface.describe()?),
+        })
+    }
+}
+pub fn rotation(rotation: Quat, surface: Surface) -> Surface {
+    Arc::new(Rotation::new(rotation, surface))
+}
+
+//
+// Scale
+//
+
+pub struct Scale {
+    scale: f32,
+    surface: Surface,
+}
+
+impl Scale {
+    pub fn new(scale: f32, surface: Surface) -> Self {
+        Self { surface, scale }
+    }
+}
+
+impl SurfaceTrait for Scale {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        self.surface.sdf(pos / self.scale) * self.scale
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.surface.color(ray, pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        Some((0, &self.surface, pos / self.scale))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.surface.bounds()?;
+        Some(Aabb::new(bounds.min * self.scale, bounds.max * self.scale))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface.lipschitz()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Scale {
+            factor: self.scale,
+            surface: Box::new(self.surface.describe()?),
+        })
+    }
+}
+pub fn scale(scale: f32, surface: Surface) -> Surface {
+    Arc::new(Scale::new(scale, surface))
+}
+
+//
+// Translation Rotation Scale
+//
+
+pub struct TranslationRotationScale {
+    surface: Surface,
+    translation: Vec3,
+    rotation: Quat,
+    scale: f32,
+}
+
+impl TranslationRotationScale {
+    pub fn new(translation: Vec3, rotation: Quat, scale: f32, surface: Surface) -> Self {
+        Self {
+            surface,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+}
+
+impl SurfaceTrait for TranslationRotationScale {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let new_pos = self.rotation * (pos - self.translation);
+        self.surface.sdf(new_pos / self.scale) * self.scale
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.surface.color(ray, pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        let new_pos = self.rotation * (pos - self.translation);
+        Some((0, &self.surface, new_pos / self.scale))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.surface.bounds()?;
+        let inverse = self.rotation.inverse();
+        Some(Aabb::from_points(bounds.corners().map(|corner| {
+            inverse * (corner * self.scale) + self.translation
+        })))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface.lipschitz()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Transform {
+            translation: self.translation,
+            rotation: RotationDesc::from_quat(self.rotation),
+            scale: self.scale,
+            surface: Box::new(self.surface.describe()?),
+        })
+    }
+}
+pub fn translation_rotation_scale(
+    translation: Vec3,
+    rotation: Quat,
+    scale: f32,
+    surface: Surface,
+) -> Surface {
+    Arc::new(TranslationRotationScale::new(
+        translation,
+        rotation,
+        scale,
+        surface,
+    ))
+}
+
+//
+// Infinite Repetition
+//
+
+pub struct InfiniteRepetition {
+    period: Vec3,
+    surface: Surface,
+}
+
+impl InfiniteRepetition {
+    pub fn new(period: Vec3, surface: Surface) -> Self {
+        Self { surface, period }
+    }
+}
+
+fn modulo(x: Vec3, y: Vec3) -> Vec3 {
+    x - y * (x / y).floor()
+}
+
+impl SurfaceTrait for InfiniteRepetition {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let c = self.period;
+        let q = modulo(pos + 0.5 * c, c) - 0.5 * c;
+        self.surface.sdf(q)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.surface.color(ray, pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        let c = self.period;
+        Some((0, &self.surface, modulo(pos + 0.5 * c, c) - 0.5 * c))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.surface.lipschitz()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Repetition {
+            period: self.period,
+            surface: Box::new(self.surface.describe()?),
+        })
+    }
+}
+pub fn infinite_repetition(period: Vec3, surface: Surface) -> Surface {
+    Arc::new(InfiniteRepetition::new(period, surface))
+}
+
+//
+// Displace
+//
+
+/// How far outside the reach of the noise the surface is used without it
+const DISPLACE_MARGIN: f32 = 0.1;
+
+/// Adds fBm noise to a surface, raised where the noise is positive and sunk where it's negative
+///
+/// The bounds grow by the amplitude of the noise, which assumes the surface returns exact
+/// distances. Surfaces that underestimate, like Ellipsoid or the fractals, can have bumps
+/// outside the bounds that the marcher steps over
+pub struct Displace {
+    fbm: Fbm,
+    surface: Surface,
+}
+
+impl Displace {
+    pub fn new(settings: FbmSettings, surface: Surface) -> Self {
+        Self {
+            fbm: Fbm::new(settings),
+            surface,
+        }
+    }
+}
+
+impl SurfaceTrait for Displace {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let dist = self.surface.sdf(pos);
+        // The noise can't bring the surface closer than its amplitude, skip it when far away
+        if dist > self.fbm.max_value() + DISPLACE_MARGIN {
+            return dist - self.fbm.max_value();
+        }
+        dist - self.fbm.get(pos)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.surface.color(ray, pos, normal, light_pos)
+    }
+
+    fn closest_child(&self, pos: Vec3) -> Option<(usize, &Surface, Vec3)> {
+        Some((0, &self.surface, pos))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // Only holds for exact distances, an underestimate reaches the amplitude further out
+        Some(self.surface.bounds()?.expand(self.fbm.max_value()))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        // The gradients of the surface and the noise add up at worst
+        self.surface.lipschitz() + self.fbm.max_gradient()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Displace {
+            noise: self.fbm.settings(),
+            surface: Box::new(self.surface.describe()?),
+        })
+    }
+}
+pub fn displace(settings: FbmSettings, surface: Surface) -> Surface {
+    Arc::new(Displace::new(settings, surface))
+}
+
+//
+// Perlin Sphere
+//
+
+/// Sphere displaced by Perlin noise seeded with the radius, see Displace for noise on any surface
+pub struct PerlinSphere {
+    radius: f32,
+    material: Material,
+    perlin: Perlin,
+    intensity: f32,
+}
+
+impl PerlinSphere {
+    pub fn new(radius: f32, intensity: f32, material: Material) -> Self {
+        let perlin = Perlin::new(radius as u32);
+        Self {
+            radius,
+            material,
+            perlin,
+            intensity,
+        }
+    }
+}
+
+impl SurfaceTrait for PerlinSphere {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let offset = self.perlin.get([pos.x as f64, pos.y as f64, pos.z as f64]) as f32;
+        pos.length() - self.radius + offset * self.intensity
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // Perlin noise stays within [-1,1]
+        let radius = self.radius + self.intensity.abs();
+        Some(Aabb::new(Vec3::splat(-radius), Vec3::splat(radius)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::PerlinSphere {
+            radius: self.radius,
+            intensity: self.intensity,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn perlin_sphere(radius: f32, intensity: f32, material: Material) -> Surface {
+    Arc::new(PerlinSphere::new(radius, intensity, material))
+}
+
+//
+// Pertrubed Sphere
+//
+
+pub struct PertrubedSphere {
+    radius: f32,
+    intensity: f32,
+    phase_shift: f32,
+    material: Material,
+}
+
+impl PertrubedSphere {
+    pub fn new(radius: f32, intensity: f32, phase_shift: f32, material: Material) -> Self {
+        Self {
+            radius,
+            material,
+            intensity,
+            phase_shift,
+        }
+    }
+}
+
+impl SurfaceTrait for PertrubedSphere {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let c = self.intensity;
+        let q = self.phase_shift;
+        let offset = c * (q + pos.x).sin() * (q + pos.y).sin() * (q + pos.z).sin();
+        pos.length() - self.radius + offset
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = self.radius + self.intensity.abs();
+        Some(Aabb::new(Vec3::splat(-radius), Vec3::splat(radius)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::PertrubedSphere {
+            radius: self.radius,
+            intensity: self.intensity,
+            phase_shift: self.phase_shift,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn pertrubed_sphere(
+    radius: f32,
+    intensity: f32,
+    phase_shift: f32,
+    material: Material,
+) -> Surface {
+    Arc::new(PertrubedSphere::new(
+        radius,
+        intensity,
+        phase_shift,
+        material,
+    ))
+}
+
+//
+// Fractals
+//
+
+/// Beyond this distance from the bounds of a fractal the sdf is the distance to the bounds
+///
+/// Escape time estimates grow faster than the distance far away and would step past the fractal
+const FRACTAL_BOUND_MARGIN: f32 = 0.1;
+
+//
+// Mandelbulb
+//
+
+pub(crate) const MANDELBULB_ITERATIONS: u32 = 12;
+pub(crate) const MANDELBULB_BAILOUT: f32 = 2.0;
+
+/// Mandelbulb fractal around the origin, with y as the axis of the spherical coordinates
+///
+/// Materials get the orbit trap of the position they color through
+/// MaterialTrait::color_with_trap. Only translations keep that position in the space of the
+/// fractal, other transforms pass the world position
+pub struct Mandelbulb {
+    power: f32,
+    iterations: u32,
+    bailout: f32,
+    material: Material,
+}
+
+impl Mandelbulb {
+    /// power 8 gives the classic bulb
+    pub fn new(power: f32, material: Material) -> Self {
+        Self {
+            power,
+            iterations: MANDELBULB_ITERATIONS,
+            bailout: MANDELBULB_BAILOUT,
+            material,
+        }
+    }
+
+    /// More iterations add finer detail, but take longer
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Orbits further from the origin than bailout are treated as escaped
+    pub fn with_bailout(mut self, bailout: f32) -> Self {
+        self.bailout = bailout;
+        self
+    }
+
+    /// Iterates pos and returns the distance estimate together with the orbit trap
+    pub fn orbit(&self, pos: Vec3) -> (f32, OrbitTrap) {
+        let mut z = pos;
+        // Derivative of the length of z with respect to pos
+        let mut dr = 1.0;
+        let mut r = z.length();
+        let mut trap = OrbitTrap {
+            origin: r,
+            planes: z.abs(),
+            escape: 1.0,
+        };
+
+        for i in 0..self.iterations {
+            if r > self.bailout {
+                trap.escape = i as f32 / self.iterations as f32;
+                break;
+            }
+            if r == 0.0 {
+                break;
+            }
+
+            // Raise z to the power in spherical coordinates
+            let theta = (z.y / r).acos() * self.power;
+            let phi = z.z.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z =
+                zr * vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ) + pos;
+
+            r = z.length();
+            trap.origin = trap.origin.min(r);
+            trap.planes = trap.planes.min(z.abs());
+        }
+
+        if r == 0.0 {
+            return (0.0, trap);
+        }
+        (0.5 * r.ln() * r / dr, trap)
+    }
+
+    /// Radius of a sphere around the fractal, points further out than 2 escape for any power
+    /// of at least 2
+    fn radius(&self) -> f32 {
+        self.bailout.min(2.0)
+    }
+}
+
+impl SurfaceTrait for Mandelbulb {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let dist = pos.length() - self.radius();
+        if dist > FRACTAL_BOUND_MARGIN {
+            return dist;
+        }
+        self.orbit(pos).0
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        let (_, trap) = self.orbit(pos);
+        self.material
+            .color_with_trap(ray, pos, normal, light_pos, &trap)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = self.radius();
+        Some(Aabb::new(Vec3::splat(-radius), Vec3::splat(radius)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Mandelbulb {
+            power: self.power,
+            iterations: self.iterations,
+            bailout: self.bailout,
+            material: self.material.describe()?,
+        })
+    }
+}
+pub fn mandelbulb(power: f32, material: Material) -> Surface {
+    Arc::new(Mandelbulb::new(power, material))
+}
+
+//
+// Menger Sponge
+//
+
+pub(crate) const MENGER_ITERATIONS: u32 = 4;
+
+/// Cube with the middle of every face and the center removed, recursively
+pub struct MengerSponge {
+    size: f32,
+    iterations: u32,
+    material: Material,
+}
+
+impl MengerSponge {
+    /// size is half the side of the cube
+    pub fn new(size: f32, material: Material) -> Self {
+        Self {
+            size,
+            iterations: MENGER_ITERATIONS,
+            material,
+        }
+    }
+
+    /// Every iteration divides the holes by 3
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+}
+
+impl SurfaceTrait for MengerSponge {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        let p = pos / self.size;
+        let q = p.abs() - Vec3::ONE;
+        let mut dist = q.max(Vec3::ZERO).length() + q.max_element().min(0.0);
+
+        // Scale of the current level, the derivative of the folded position
+        let mut scale = 1.0;
+        for _ in 0..self.iterations {
+            let a = (p * scale).rem_euclid(Vec3::splat(2.0)) - Vec3::ONE;
+            scale *= 3.0;
+            let r = (Vec3::ONE - 3.0 * a.abs()).abs();
+            // Distance to the three crossing bars of holes at this level
+            let bars = r.max(r.yzx()).min_element();
+            dist = dist.max((bars - 1.0) / scale);
+        }
+        dist * self.size
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(Vec3::splat(-self.size), Vec3::splat(self.size)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::MengerSponge {
+            size: self.size,
+            iterations: self.iterations,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn translation(translation: Vec3, surface: Surface) -> Surface {
-    Arc::new(Translation::new(translation, surface))
-}
+pub fn menger_sponge(size: f32, material: Material) -> Surface {
+    Arc::new(MengerSponge::new(size, material))
+}
 
 //
-// Rotation
+// Sierpinski Tetrahedron
 //
 
-pub struct Rotation {
-    rotation: Quat,
-    surface: Surface,
+pub(crate) const SIERPINSKI_ITERATIONS: u32 = 8;
+
+/// Tetrahedron made of 4 tetrahedra of half the size, recursively
+pub struct SierpinskiTetrahedron {
+    size: f32,
+    iterations: u32,
+    material: Material,
 }
 
-impl Rotation {
-    pub fn new(rotation: Quat, surface: Surface) -> Self {
-        Self { surface, rotation }
+impl SierpinskiTetrahedron {
+    /// Corners are at (size, size, size), (-size, -size, size), (size, -size, -size) and
+    /// (-size, size, -size)
+    pub fn new(size: f32, material: Material) -> Self {
+        Self {
+            size,
+            iterations: SIERPINSKI_ITERATIONS,
+            material,
+        }
+    }
+
+    /// Every iteration halves the smallest tetrahedra
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
     }
 }
 
-impl SurfaceTrait for Rotation {
+impl SurfaceTrait for SierpinskiTetrahedron {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let new_pos = self.rotation * pos;
-        self.surface.sdf(new_pos)
+        let mut z = pos / self.size;
+        let mut scale = 1.0;
+        for _ in 0..self.iterations {
+            // Mirror the other corners onto (1, 1, 1), then grow its tetrahedron to full size
+            if z.x + z.y < 0.0 {
+                z = vec3(-z.y, -z.x, z.z);
+            }
+            if z.x + z.z < 0.0 {
+                z = vec3(-z.z, z.y, -z.x);
+            }
+            if z.y + z.z < 0.0 {
+                z = vec3(z.x, -z.z, -z.y);
+            }
+            z = z * 2.0 - Vec3::ONE;
+            scale *= 2.0;
+        }
+
+        // Distance to the planes of the faces, opposite to the corners
+        let faces = (-z.x - z.y - z.z)
+            .max(z.x + z.y - z.z)
+            .max(z.x - z.y + z.z)
+            .max(-z.x + z.y + z.z);
+        (faces - 1.0) * 0.57735027 / scale * self.size
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        self.surface.color(ray, pos, normal, light_pos)
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(Vec3::splat(-self.size), Vec3::splat(self.size)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::SierpinskiTetrahedron {
+            size: self.size,
+            iterations: self.iterations,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn rotation(rotation: Quat, surface: Surface) -> Surface {
-    Arc::new(Rotation::new(rotation, surface))
+pub fn sierpinski_tetrahedron(size: f32, material: Material) -> Surface {
+    Arc::new(SierpinskiTetrahedron::new(size, material))
 }
 
 //
-// Scale
+// Mandelbox
 //
 
-pub struct Scale {
+pub(crate) const MANDELBOX_ITERATIONS: u32 = 12;
+pub(crate) const MANDELBOX_MIN_RADIUS: f32 = 0.5;
+pub(crate) const MANDELBOX_FOLD_LIMIT: f32 = 1.0;
+/// Orbits further from the origin than this are treated as escaped
+const MANDELBOX_BAILOUT: f32 = 100.0;
+
+/// Mandelbox fractal around the origin
+///
+/// Colored through MaterialTrait::color_with_trap like the Mandelbulb
+pub struct Mandelbox {
     scale: f32,
-    surface: Surface,
+    min_radius: f32,
+    fold_limit: f32,
+    iterations: u32,
+    material: Material,
 }
 
-impl Scale {
-    pub fn new(scale: f32, surface: Surface) -> Self {
-        Self { surface, scale }
+impl Mandelbox {
+    /// scale 2 gives the classic box, negative scales give rounder shapes. Scales between -1
+    /// and 1 don't make a fractal
+    pub fn new(scale: f32, material: Material) -> Self {
+        Self {
+            scale,
+            min_radius: MANDELBOX_MIN_RADIUS,
+            fold_limit: MANDELBOX_FOLD_LIMIT,
+            iterations: MANDELBOX_ITERATIONS,
+            material,
+        }
+    }
+
+    /// Radius below which the sphere fold scales by a constant instead of inverting
+    pub fn with_min_radius(mut self, min_radius: f32) -> Self {
+        self.min_radius = min_radius;
+        self
+    }
+
+    /// Coordinates beyond the fold limit are reflected back towards the origin
+    pub fn with_fold_limit(mut self, fold_limit: f32) -> Self {
+        self.fold_limit = fold_limit;
+        self
+    }
+
+    /// More iterations add finer detail, but take longer
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Iterates pos and returns the distance estimate together with the orbit trap
+    pub fn orbit(&self, pos: Vec3) -> (f32, OrbitTrap) {
+        let min_radius2 = self.min_radius * self.min_radius;
+        let mut z = pos;
+        // Derivative of z with respect to pos
+        let mut dr = 1.0;
+        let mut trap = OrbitTrap {
+            origin: z.length(),
+            planes: z.abs(),
+            escape: 1.0,
+        };
+
+        for i in 0..self.iterations {
+            // Box fold
+            z = z.clamp(Vec3::splat(-self.fold_limit), Vec3::splat(self.fold_limit)) * 2.0 - z;
+
+            // Sphere fold, inverts the shell between the min radius and 1
+            let r2 = z.length_squared();
+            let factor = if r2 < min_radius2 {
+                1.0 / min_radius2
+            } else if r2 < 1.0 {
+                1.0 / r2
+            } else {
+                1.0
+            };
+            z = z * factor * self.scale + pos;
+            dr = dr * factor * self.scale.abs() + 1.0;
+
+            let r = z.length();
+            trap.origin = trap.origin.min(r);
+            trap.planes = trap.planes.min(z.abs());
+            if r > MANDELBOX_BAILOUT {
+                trap.escape = i as f32 / self.iterations as f32;
+                break;
+            }
+        }
+        (z.length() / dr, trap)
+    }
+
+    /// Half the side of a cube around the fractal
+    fn half_size(&self) -> f32 {
+        let scale = self.scale.abs();
+        2.0 * self.fold_limit.max(1.0) * (scale + 1.0) / (scale - 1.0)
     }
 }
 
-impl SurfaceTrait for Scale {
+impl SurfaceTrait for Mandelbox {
     fn sdf(&self, pos: Vec3) -> f32 {
-        self.surface.sdf(pos / self.scale) * self.scale
+        let outside = (pos.abs() - Vec3::splat(self.half_size())).max(Vec3::ZERO);
+        let dist = outside.length();
+        if dist > FRACTAL_BOUND_MARGIN {
+            return dist;
+        }
+        self.orbit(pos).0
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        self.surface.color(ray, pos, normal, light_pos)
+        let (_, trap) = self.orbit(pos);
+        self.material
+            .color_with_trap(ray, pos, normal, light_pos, &trap)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let half_size = self.half_size();
+        Some(Aabb::new(Vec3::splat(-half_size), Vec3::splat(half_size)))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Mandelbox {
+            scale: self.scale,
+            min_radius: self.min_radius,
+            fold_limit: self.fold_limit,
+            iterations: self.iterations,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn scale(scale: f32, surface: Surface) -> Surface {
-    Arc::new(Scale::new(scale, surface))
+pub fn mandelbox(scale: f32, material: Material) -> Surface {
+    Arc::new(Mandelbox::new(scale, material))
 }
 
 //
-// Translation Rotation Scale
+// Mesh
 //
 
-pub struct TranslationRotationScale {
-    surface: Surface,
-    translation: Vec3,
-    rotation: Quat,
-    scale: f32,
+/// Triangle mesh from an OBJ or STL file, see mesh::Mesh
+pub struct MeshSurface {
+    mesh: Arc<Mesh>,
+    /// Kept so the surface can be saved
+    path: Option<String>,
+    material: Material,
 }
 
-impl TranslationRotationScale {
-    pub fn new(translation: Vec3, rotation: Quat, scale: f32, surface: Surface) -> Self {
+impl MeshSurface {
+    /// Surfaces made from a mesh in memory can't be saved
+    pub fn new(mesh: Arc<Mesh>, material: Material) -> Self {
         Self {
-            surface,
-            translation,
-            rotation,
-            scale,
+            mesh,
+            path: None,
+            material,
         }
     }
+
+    pub fn load(path: &str, material: Material) -> io::Result<Self> {
+        Ok(Self {
+            mesh: Arc::new(Mesh::load(path)?),
+            path: Some(path.to_string()),
+            material,
+        })
+    }
+
+    pub fn mesh(&self) -> &Arc<Mesh> {
+        &self.mesh
+    }
 }
 
-impl SurfaceTrait for TranslationRotationScale {
+impl SurfaceTrait for MeshSurface {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let new_pos = self.rotation * (pos - self.translation);
-        self.surface.sdf(new_pos / self.scale) * self.scale
+        self.mesh.signed_distance(pos)
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        self.surface.color(ray, pos, normal, light_pos)
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.mesh.bounds()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Mesh {
+            path: self.path.clone()?,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn translation_rotation_scale(
-    translation: Vec3,
-    rotation: Quat,
-    scale: f32,
-    surface: Surface,
-) -> Surface {
-    Arc::new(TranslationRotationScale::new(
-        translation,
-        rotation,
-        scale,
-        surface,
-    ))
+/// Panics if the file can't be loaded, use MeshSurface::load to handle errors
+pub fn mesh(path: &str, material: Material) -> Surface {
+    Arc::new(MeshSurface::load(path, material).unwrap())
 }
 
 //
-// Infinite Repetition
+// Heightfield
 //
 
-pub struct InfiniteRepetition {
-    period: Vec3,
-    surface: Surface,
+/// Heights in [0,1], scaled by the height of the heightfield
+enum Heights {
+    /// Samples with rows along x, the first row at the far side in z like a map seen from above
+    Grid {
+        width: usize,
+        depth: usize,
+        values: Vec<f32>,
+    },
+    /// Takes the horizontal position in world space
+    Function(Box<dyn Fn(Vec2) -> f32 + Sync + Send>),
+}
+
+/// Terrain centered on the origin, solid from y = 0 up to the heights
+///
+/// Heights only change the distance vertically, so steps are divided by the steepest slope, see
+/// SurfaceTrait::lipschitz
+pub struct Heightfield {
+    heights: Heights,
+    /// Extent along x and z
+    size: Vec2,
+    height_scale: f32,
+    /// Most the height changes per unit moved horizontally, in world units
+    max_slope: f32,
+    /// Kept so the surface can be saved
+    path: Option<String>,
+    material: Material,
 }
 
-impl InfiniteRepetition {
-    pub fn new(period: Vec3, surface: Surface) -> Self {
-        Self { surface, period }
+impl Heightfield {
+    /// Heights from the brightness of an image, black at y = 0 and white at height_scale
+    ///
+    /// Errors if the image is smaller than 2 by 2 pixels
+    pub fn from_image(
+        image: &DynamicImage,
+        size: Vec2,
+        height_scale: f32,
+        material: Material,
+    ) -> ImageResult<Self> {
+        if image.width() < 2 || image.height() < 2 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "heightmap must be at least 2 by 2 pixels, got {} by {}",
+                    image.width(),
+                    image.height()
+                )),
+            )));
+        }
+        let gray = image.to_luma32f();
+        let (width, depth) = (gray.width() as usize, gray.height() as usize);
+        Ok(Self::from_grid(
+            width,
+            depth,
+            gray.into_raw(),
+            size,
+            height_scale,
+            material,
+        ))
     }
-}
 
-fn modulo(x: Vec3, y: Vec3) -> Vec3 {
-    x - y * (x / y).floor()
+    /// Heights in [0,1] in rows along x, width samples per row
+    ///
+    /// Panics if there aren't at least 2 by 2 values
+    pub fn from_grid(
+        width: usize,
+        depth: usize,
+        values: Vec<f32>,
+        size: Vec2,
+        height_scale: f32,
+        material: Material,
+    ) -> Self {
+        assert!(width >= 2 && depth >= 2 && values.len() == width * depth);
+        let cell = size / vec2(width as f32 - 1.0, depth as f32 - 1.0);
+        let at = |x: usize, z: usize| values[x + z * width];
+
+        // Gradients of bilinear interpolation are largest where both edges of a cell are
+        let mut max_slope: f32 = 0.0;
+        for z in 0..depth - 1 {
+            for x in 0..width - 1 {
+                let dx = (at(x + 1, z) - at(x, z))
+                    .abs()
+                    .max((at(x + 1, z + 1) - at(x, z + 1)).abs());
+                let dz = (at(x, z + 1) - at(x, z))
+                    .abs()
+                    .max((at(x + 1, z + 1) - at(x + 1, z)).abs());
+                max_slope = max_slope.max((vec2(dx, dz) / cell).length());
+            }
+        }
+
+        Self {
+            heights: Heights::Grid {
+                width,
+                depth,
+                values,
+            },
+            size,
+            height_scale,
+            max_slope: max_slope * height_scale.abs(),
+            path: None,
+            material,
+        }
+    }
+
+    /// Heights in [0,1] from a function of the horizontal position
+    ///
+    /// Assumes the function changes by at most 1 per unit moved, which is a slope of
+    /// height_scale in world units. Use with_max_slope for steeper functions
+    pub fn from_fn(
+        heights: impl Fn(Vec2) -> f32 + Sync + Send + 'static,
+        size: Vec2,
+        height_scale: f32,
+        material: Material,
+    ) -> Self {
+        Self {
+            heights: Heights::Function(Box::new(heights)),
+            size,
+            height_scale,
+            max_slope: height_scale.abs(),
+            path: None,
+            material,
+        }
+    }
+
+    pub fn load(
+        path: &str,
+        size: Vec2,
+        height_scale: f32,
+        material: Material,
+    ) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        Ok(Self {
+            path: Some(path.to_string()),
+            ..Self::from_image(&image, size, height_scale, material)?
+        })
+    }
+
+    /// Most the height changes per unit moved horizontally, in world units
+    pub fn with_max_slope(mut self, max_slope: f32) -> Self {
+        self.max_slope = max_slope;
+        self
+    }
+
+    pub fn max_slope(&self) -> f32 {
+        self.max_slope
+    }
+
+    /// Height of the terrain at x, z, positions outside take the height at the closest side
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        let half = self.size / 2.0;
+        let pos = vec2(x, z).clamp(-half, half);
+        let height = match &self.heights {
+            Heights::Grid {
+                width,
+                depth,
+                values,
+            } => {
+                // Position in samples, the first row is at +z
+                let grid_x = (pos.x + half.x) / self.size.x * (*width - 1) as f32;
+                let grid_z = (half.y - pos.y) / self.size.y * (*depth - 1) as f32;
+                let x0 = (grid_x.floor() as usize).min(width - 2);
+                let z0 = (grid_z.floor() as usize).min(depth - 2);
+                let (tx, tz) = (grid_x - x0 as f32, grid_z - z0 as f32);
+                let at = |x: usize, z: usize| values[x + z * width];
+                let near = interpolate_f32(at(x0, z0), at(x0 + 1, z0), tx);
+                let far = interpolate_f32(at(x0, z0 + 1), at(x0 + 1, z0 + 1), tx);
+                interpolate_f32(near, far, tz)
+            }
+            Heights::Function(heights) => heights(pos),
+        };
+        height * self.height_scale
+    }
 }
 
-impl SurfaceTrait for InfiniteRepetition {
+impl SurfaceTrait for Heightfield {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let c = self.period;
-        let q = modulo(pos + 0.5 * c, c) - 0.5 * c;
-        self.surface.sdf(q)
+        let above = pos.y - self.height(pos.x, pos.z);
+        let q = pos.xz().abs() - self.size / 2.0;
+        let sides = q.max(Vec2::ZERO).length() + q.max_element().min(0.0);
+        above.max(sides).max(-pos.y)
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-        self.surface.color(ray, pos, normal, light_pos)
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let half = self.size / 2.0;
+        Some(Aabb::new(
+            vec3(-half.x, 0.0, -half.y),
+            vec3(half.x, self.height_scale.max(0.0), half.y),
+        ))
+    }
+
+    fn lipschitz(&self) -> f32 {
+        (1.0 + self.max_slope * self.max_slope).sqrt()
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Heightfield {
+            path: self.path.clone()?,
+            size: self.size,
+            height: self.height_scale,
+            material: self.material.describe()?,
+        })
     }
 }
-pub fn infinite_repetition(period: Vec3, surface: Surface) -> Surface {
-    Arc::new(InfiniteRepetition::new(period, surface))
+/// Panics if the image can't be loaded, use Heightfield::load to handle errors
+pub fn heightfield(path: &str, size: Vec2, height_scale: f32, material: Material) -> Surface {
+    Arc::new(Heightfield::load(path, size, height_scale, material).unwrap())
 }
 
 //
-// Perlin Sphere
+// fBm Terrain
 //
 
-pub struct PerlinSphere {
-    radius: f32,
+/// How far above the highest peaks the distance to them is used instead of the terrain
+const TERRAIN_PEAK_MARGIN: f32 = 0.1;
+
+/// Endless ground at y = 0 raised and lowered by fBm noise of the horizontal position
+pub struct FbmTerrain {
+    fbm: Fbm,
     material: Material,
-    perlin: Perlin,
-    intensity: f32,
 }
 
-impl PerlinSphere {
-    pub fn new(radius: f32, intensity: f32, material: Material) -> Self {
-        let perlin = Perlin::new(radius as u32);
+impl FbmTerrain {
+    pub fn new(settings: FbmSettings, material: Material) -> Self {
         Self {
-            radius,
+            fbm: Fbm::new(settings),
             material,
-            perlin,
-            intensity,
         }
     }
+
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        self.fbm.get(vec3(x, 0.0, z))
+    }
 }
 
-impl SurfaceTrait for PerlinSphere {
+impl SurfaceTrait for FbmTerrain {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let offset = self.perlin.get([pos.x as f64, pos.y as f64, pos.z as f64]) as f32;
-        pos.length() - self.radius + offset * self.intensity
+        // Above the highest peaks the ground is at least as far as the peaks
+        let above_peaks = pos.y - self.fbm.max_value();
+        if above_peaks > TERRAIN_PEAK_MARGIN {
+            return above_peaks;
+        }
+        pos.y - self.height(pos.x, pos.z)
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
         self.material.color(ray, pos, normal, light_pos)
     }
+
+    fn lipschitz(&self) -> f32 {
+        let slope = self.fbm.max_gradient();
+        (1.0 + slope * slope).sqrt()
+    }
+
+    fn march_bounds(&self) -> Option<Aabb> {
+        let height = self.fbm.max_value();
+        Some(Aabb::new(
+            vec3(f32::NEG_INFINITY, -height, f32::NEG_INFINITY),
+            vec3(f32::INFINITY, height, f32::INFINITY),
+        ))
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::FbmTerrain {
+            noise: self.fbm.settings(),
+            material: self.material.describe()?,
+        })
+    }
 }
-pub fn perlin_sphere(radius: f32, intensity: f32, material: Material) -> Surface {
-    Arc::new(PerlinSphere::new(radius, intensity, material))
+pub fn fbm_terrain(settings: FbmSettings, material: Material) -> Surface {
+    Arc::new(FbmTerrain::new(settings, material))
 }
 
 //
-// Pertrubed Sphere
+// Voxel SDF
 //
 
-pub struct PertrubedSphere {
-    radius: f32,
-    intensity: f32,
-    phase_shift: f32,
+/// Distances looked up in a grid instead of computed, see voxels::VoxelGrid
+///
+/// Cheap to evaluate whatever surface the grid was baked from, but details smaller than the
+/// cells are lost
+pub struct VoxelSdf {
+    grid: Arc<VoxelGrid>,
+    interpolation: Interpolation,
+    /// Kept so the surface can be saved
+    path: Option<String>,
     material: Material,
 }
 
-impl PertrubedSphere {
-    pub fn new(radius: f32, intensity: f32, phase_shift: f32, material: Material) -> Self {
+impl VoxelSdf {
+    /// Surfaces made from a grid in memory can't be saved, save the grid and load it instead
+    pub fn new(grid: Arc<VoxelGrid>, material: Material) -> Self {
         Self {
-            radius,
+            grid,
+            interpolation: Interpolation::Trilinear,
+            path: None,
             material,
-            intensity,
-            phase_shift,
         }
     }
+
+    pub fn load(path: &str, material: Material) -> io::Result<Self> {
+        Ok(Self {
+            path: Some(path.to_string()),
+            ..Self::new(Arc::new(VoxelGrid::load(path)?), material)
+        })
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Colors hits with material instead of the current one
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+
+    pub fn grid(&self) -> &Arc<VoxelGrid> {
+        &self.grid
+    }
 }
 
-impl SurfaceTrait for PertrubedSphere {
+impl SurfaceTrait for VoxelSdf {
     fn sdf(&self, pos: Vec3) -> f32 {
-        let c = self.intensity;
-        let q = self.phase_shift;
-        let offset = c * (q + pos.x).sin() * (q + pos.y).sin() * (q + pos.z).sin();
-        pos.length() - self.radius + offset
+        self.grid.distance(pos, self.interpolation)
     }
 
     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
         self.material.color(ray, pos, normal, light_pos)
     }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.grid.bounds())
+    }
+
+    fn describe(&self) -> Option<SurfaceDesc> {
+        Some(SurfaceDesc::Voxels {
+            path: self.path.clone()?,
+            interpolation: self.interpolation,
+            material: self.material.describe()?,
+        })
+    }
 }
-pub fn pertrubed_sphere(
-    radius: f32,
-    intensity: f32,
-    phase_shift: f32,
+
+/// Colors with the colors of a surface, for baked surfaces
+struct SurfaceColor(Surface);
+
+impl MaterialTrait for SurfaceColor {
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.0.color(ray, pos, normal, light_pos)
+    }
+}
+
+/// Samples surface into a grid with resolution samples along the longest side of bounds
+///
+/// Hits keep the colors of surface. Bounds should leave a few cells of room around the
+/// surface
+pub fn bake(surface: &Surface, bounds: Aabb, resolution: u32) -> VoxelSdf {
+    let grid = VoxelGrid::bake(surface.as_ref(), bounds, resolution);
+    VoxelSdf::new(Arc::new(grid), Arc::new(SurfaceColor(surface.clone())))
+}
+
+//
+// Function
+//
+
+/// Surface with a distance function given as a closure
+pub struct FnSurface<F> {
+    sdf: F,
     material: Material,
-) -> Surface {
-    Arc::new(PertrubedSphere::new(
-        radius,
-        intensity,
-        phase_shift,
-        material,
-    ))
+    lipschitz: f32,
+    bounds: Option<Aabb>,
+}
+
+impl<F: Fn(Vec3) -> f32 + Sync + Send> FnSurface<F> {
+    pub fn new(sdf: F, material: Material) -> Self {
+        Self {
+            sdf,
+            material,
+            lipschitz: 1.0,
+            bounds: None,
+        }
+    }
+
+    /// Use when sdf isn't an exact distance, see SurfaceTrait::lipschitz
+    pub fn with_lipschitz(mut self, lipschitz: f32) -> Self {
+        self.lipschitz = lipschitz;
+        self
+    }
+
+    /// Lets the surface be framed and picked, see SurfaceTrait::bounds
+    pub fn with_bounds(mut self, bounds: Aabb) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+}
+
+impl<F: Fn(Vec3) -> f32 + Sync + Send> SurfaceTrait for FnSurface<F> {
+    fn sdf(&self, pos: Vec3) -> f32 {
+        (self.sdf)(pos)
+    }
+
+    fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
+        self.material.color(ray, pos, normal, light_pos)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn lipschitz(&self) -> f32 {
+        self.lipschitz
+    }
+}
+/// Surface with the distance function sdf, for example `from_fn(|p| p.length() - 1.0, material)`
+pub fn from_fn(sdf: impl Fn(Vec3) -> f32 + Sync + Send + 'static, material: Material) -> Surface {
+    Arc::new(FnSurface::new(sdf, material))
 }
 
 /// p should be in range [0,1]
@@ -622,63 +2918,3 @@ pub fn interpolate_vec3(a: Vec3, b: Vec3, p: f32) -> Vec3 {
 pub fn interpolate_f32(a: f32, b: f32, p: f32) -> f32 {
     a * (1.0 - p) + b * p
 }
-
-// pub struct Fractal {
-//     material: Material,
-//     power: f32,
-// }
-//
-// impl Fractal {
-//     pub fn new(power: f32, material: Material) -> Self {
-//         Self { power, material }
-//     }
-// }
-//
-// const STEPS: usize = 20;
-//
-// impl SurfaceTrait for Fractal {
-//     fn sdf(&self, pos: Vec3) -> f32 {
-//         let mut z = pos;
-//         let mut dr = 1.0;
-//         let mut r = 0.0;
-//         let mut iterations = 0;
-//
-//         for i in 0..STEPS {
-//             r = z.length();
-//             iterations = i;
-//
-//             if r > 4.0 {
-//                 // let mut dst = 0.5 * r.log2() * r / dr;
-//                 // dst /= iterations as f32 + 1.0;
-//                 // println!("{r} > 4.0 dst: {dst}");
-//                 break;
-//             }
-//
-//             let mut phi = (pos.y / pos.x).atan();
-//             let mut theta = (pos.z / r).acos();
-//             dr = r.powf(self.power - 1.0) * self.power + 1.0;
-//
-//             let zr = r.powf(self.power);
-//             theta *= self.power;
-//             phi *= self.power;
-//
-//             z = zr
-//                 * vec3(
-//                     theta.sin() * phi.cos(),
-//                     phi.sin() * theta.sin(),
-//                     theta.cos(),
-//                 );
-//
-//             z += pos;
-//         }
-//         let dst = 0.5 * r.log10() * r / dr;
-//         // if dst < 0.1 {
-//         //     println!("SMALLER")
-//         // }
-//         dst
-//     }
-//
-//     fn color(&self, ray: Vec3, pos: Vec3, normal: Vec3, light_pos: Vec3) -> Vec3 {
-//         self.material.color(ray, pos, normal, light_pos)
-//     }
-// }
@@ -0,0 +1,257 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use glam::{IVec3, UVec3, Vec3};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::surfaces::{interpolate_f32, Aabb, SurfaceTrait};
+
+/// Start of every voxel file
+const MAGIC: &[u8; 4] = b"VSDF";
+const VERSION: u32 = 1;
+/// Magic, version, sample counts and bounds
+const HEADER_SIZE: usize = 4 + 4 + 3 * 4 + 6 * 4;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// How distances between samples are found
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Blends the 8 closest samples, fast but shows the grid on curved surfaces
+    #[default]
+    Trilinear,
+    /// Catmull-Rom spline through the 64 closest samples, smoother normals
+    Tricubic,
+}
+
+//
+// Voxel grid
+//
+
+/// Distances sampled on a regular grid, with the first and last samples on the sides of bounds
+pub struct VoxelGrid {
+    bounds: Aabb,
+    /// Samples along each axis, at least 2
+    counts: UVec3,
+    /// Indexed by x, then y, then z
+    distances: Vec<f32>,
+}
+
+impl VoxelGrid {
+    /// Samples along every axis are counts, distances are in the order x, then y, then z
+    ///
+    /// Returns an error if there aren't 2 samples along every axis, there are too many samples to
+    /// count or the distances don't fill the grid
+    pub fn new(bounds: Aabb, counts: UVec3, distances: Vec<f32>) -> io::Result<Self> {
+        if counts.min_element() < 2 {
+            return Err(invalid_data(format!(
+                "a grid needs at least 2 samples along every axis, got {counts}"
+            )));
+        }
+        let expected = (counts.x as usize)
+            .checked_mul(counts.y as usize)
+            .and_then(|samples| samples.checked_mul(counts.z as usize))
+            .ok_or_else(|| invalid_data(format!("{counts} samples are too many for a grid")))?;
+        if distances.len() != expected {
+            return Err(invalid_data(format!(
+                "{counts} samples need {expected} distances, got {}",
+                distances.len()
+            )));
+        }
+        Ok(Self {
+            bounds,
+            counts,
+            distances,
+        })
+    }
+
+    /// Samples the sdf of surface inside bounds
+    ///
+    /// resolution is the number of samples along the longest side of bounds, the other sides get
+    /// as many as keep the cells cubes. Bounds should leave some room around the surface, the
+    /// distance outside is only an estimate
+    pub fn bake(surface: &dyn SurfaceTrait, bounds: Aabb, resolution: u32) -> Self {
+        let size = bounds.size();
+        let cell = size.max_element() / (resolution.max(2) - 1) as f32;
+        let counts = ((size / cell).round().as_uvec3() + UVec3::ONE).max(UVec3::splat(2));
+        // Stretch the cells a little on the shorter sides so the grid still covers bounds
+        let step = size / (counts - UVec3::ONE).as_vec3();
+
+        let (nx, ny) = (counts.x as usize, counts.y as usize);
+        let mut distances = vec![0.0; nx * ny * counts.z as usize];
+        distances
+            .par_chunks_mut(nx)
+            .enumerate()
+            .for_each(|(row, distances)| {
+                let (y, z) = (row % ny, row / ny);
+                for (x, distance) in distances.iter_mut().enumerate() {
+                    let index = Vec3::new(x as f32, y as f32, z as f32);
+                    *distance = surface.sdf(bounds.min + index * step);
+                }
+            });
+
+        Self {
+            bounds,
+            counts,
+            distances,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Samples along each axis
+    pub fn counts(&self) -> UVec3 {
+        self.counts
+    }
+
+    /// Distance stored at the sample with the given index along each axis
+    pub fn get(&self, x: u32, y: u32, z: u32) -> f32 {
+        let (nx, ny) = (self.counts.x as usize, self.counts.y as usize);
+        self.distances[x as usize + nx * (y as usize + ny * z as usize)]
+    }
+
+    /// Interpolated distance inside bounds, outside a lower bound of the distance to a surface
+    /// inside bounds
+    pub fn distance(&self, pos: Vec3, interpolation: Interpolation) -> f32 {
+        let inside = pos.clamp(self.bounds.min, self.bounds.max);
+        let sample = match interpolation {
+            Interpolation::Trilinear => self.trilinear(inside),
+            Interpolation::Tricubic => self.tricubic(inside),
+        };
+        let outside = pos.distance(inside);
+        if outside == 0.0 {
+            return sample;
+        }
+        // Surfaces inside are at least as far as the box, and the sdf changes at most by the
+        // distance moved from the closest point on the box
+        outside.max(sample - outside)
+    }
+
+    /// Position in units of cells from bounds.min
+    fn grid_pos(&self, pos: Vec3) -> Vec3 {
+        (pos - self.bounds.min) / self.bounds.size() * (self.counts - UVec3::ONE).as_vec3()
+    }
+
+    /// Sample that may be past the sides of the grid, those continue the slope at the side
+    fn get_extended(&self, index: IVec3) -> f32 {
+        let max = (self.counts - UVec3::ONE).as_ivec3();
+        for axis in 0..3 {
+            if index[axis] < 0 || index[axis] > max[axis] {
+                let (mut side, mut inner) = (index, index);
+                side[axis] = index[axis].clamp(0, max[axis]);
+                inner[axis] = if index[axis] < 0 { 1 } else { max[axis] - 1 };
+                return 2.0 * self.get_extended(side) - self.get_extended(inner);
+            }
+        }
+        self.get(index.x as u32, index.y as u32, index.z as u32)
+    }
+
+    fn trilinear(&self, pos: Vec3) -> f32 {
+        let grid_pos = self.grid_pos(pos);
+        // The last cell includes the far side
+        let cell = grid_pos.floor().min((self.counts - 2).as_vec3()).as_ivec3();
+        let t = grid_pos - cell.as_vec3();
+        let corner = |dx, dy, dz| self.get_extended(cell + IVec3::new(dx, dy, dz));
+
+        let x00 = interpolate_f32(corner(0, 0, 0), corner(1, 0, 0), t.x);
+        let x10 = interpolate_f32(corner(0, 1, 0), corner(1, 1, 0), t.x);
+        let x01 = interpolate_f32(corner(0, 0, 1), corner(1, 0, 1), t.x);
+        let x11 = interpolate_f32(corner(0, 1, 1), corner(1, 1, 1), t.x);
+        interpolate_f32(
+            interpolate_f32(x00, x10, t.y),
+            interpolate_f32(x01, x11, t.y),
+            t.z,
+        )
+    }
+
+    fn tricubic(&self, pos: Vec3) -> f32 {
+        let grid_pos = self.grid_pos(pos);
+        let cell = grid_pos.floor().min((self.counts - 2).as_vec3()).as_ivec3();
+        let t = grid_pos - cell.as_vec3();
+
+        let mut planes = [0.0; 4];
+        for (dz, plane) in planes.iter_mut().enumerate() {
+            let mut rows = [0.0; 4];
+            for (dy, row) in rows.iter_mut().enumerate() {
+                let sample = |dx: i32| {
+                    self.get_extended(cell + IVec3::new(dx, dy as i32 - 1, dz as i32 - 1))
+                };
+                *row = catmull_rom([sample(-1), sample(0), sample(1), sample(2)], t.x);
+            }
+            *plane = catmull_rom(rows, t.y);
+        }
+        catmull_rom(planes, t.z)
+    }
+
+    /// Writes the grid in a small binary format, see load
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Reads a grid written by save
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Little endian header of magic, version, sample counts and bounds, followed by the
+    /// distances
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.distances.len() * 4);
+        bytes.extend(MAGIC);
+        bytes.extend(VERSION.to_le_bytes());
+        for count in self.counts.to_array() {
+            bytes.extend(count.to_le_bytes());
+        }
+        let corners = [self.bounds.min.to_array(), self.bounds.max.to_array()];
+        for value in corners.iter().flatten().chain(&self.distances) {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err(invalid_data("not a voxel file".to_string()));
+        }
+        let words = bytes[4..]
+            .chunks_exact(4)
+            .map(|word| word.try_into().unwrap())
+            .collect::<Vec<[u8; 4]>>();
+        let version = u32::from_le_bytes(words[0]);
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported voxel file version {version}"
+            )));
+        }
+        let counts = UVec3::from_array([1, 2, 3].map(|i| u32::from_le_bytes(words[i])));
+        let floats = words[4..]
+            .iter()
+            .map(|&word| f32::from_le_bytes(word))
+            .collect::<Vec<_>>();
+        let bounds = Aabb::new(
+            Vec3::from_slice(&floats[0..3]),
+            Vec3::from_slice(&floats[3..6]),
+        );
+        Self::new(bounds, counts, floats[6..].to_vec())
+    }
+}
+
+/// Spline through p[1] at t = 0 and p[2] at t = 1
+fn catmull_rom(p: [f32; 4], t: f32) -> f32 {
+    let a = -0.5 * p[0] + 1.5 * p[1] - 1.5 * p[2] + 0.5 * p[3];
+    let b = p[0] - 2.5 * p[1] + 2.0 * p[2] - 0.5 * p[3];
+    let c = -0.5 * p[0] + 0.5 * p[2];
+    ((a * t + b) * t + c) * t + p[1]
+}
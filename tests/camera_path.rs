@@ -0,0 +1,74 @@
+use std::{env, fs};
+
+use glam::{vec3, Vec3};
+use software_raymarcher::camera_path::{CameraPath, CameraPose};
+
+fn path() -> CameraPath {
+    let mut path = CameraPath::new();
+    path.push(0.0, CameraPose::new(Vec3::ZERO, 0.0, 0.0));
+    path.push(1.0, CameraPose::new(vec3(2.0, 0.0, 0.0), 1.0, -0.5));
+    path.push(3.0, CameraPose::new(vec3(2.0, 4.0, 0.0), 0.0, 0.5));
+    path
+}
+
+#[test]
+fn samples_between_poses() {
+    let path = path();
+    assert_eq!(path.duration(), 3.0);
+    assert_eq!(
+        path.sample(0.5),
+        Some(CameraPose::new(vec3(1.0, 0.0, 0.0), 0.5, -0.25))
+    );
+    assert_eq!(
+        path.sample(2.0),
+        Some(CameraPose::new(vec3(2.0, 2.0, 0.0), 0.5, 0.0))
+    );
+    // Recorded poses are hit exactly
+    assert_eq!(
+        path.sample(1.0),
+        Some(CameraPose::new(vec3(2.0, 0.0, 0.0), 1.0, -0.5))
+    );
+}
+
+#[test]
+fn sampling_clamps_to_the_ends() {
+    let path = path();
+    assert_eq!(path.sample(-1.0), path.sample(0.0));
+    assert_eq!(path.sample(10.0), path.sample(3.0));
+    assert_eq!(CameraPath::new().sample(0.0), None);
+}
+
+#[test]
+fn save_load_round_trip() {
+    let file = env::temp_dir().join("software_raymarcher_camera_path.txt");
+    let file = file.to_str().unwrap();
+    let path = path();
+    path.save(file).unwrap();
+    let loaded = CameraPath::load(file).unwrap();
+    fs::remove_file(file).unwrap();
+    assert_eq!(loaded, path);
+}
+
+#[test]
+fn invalid_files_are_errors() {
+    let file = env::temp_dir().join("software_raymarcher_broken_path.txt");
+    let file = file.to_str().unwrap();
+    let message = |text: &str| {
+        fs::write(file, text).unwrap();
+        let message = CameraPath::load(file).err().unwrap().to_string();
+        fs::remove_file(file).unwrap();
+        message
+    };
+    assert_eq!(
+        message("0 0 0 0 0 0\n\n1 0 0 0 0\n"),
+        format!("{file}:3: expected \"time x y z yaw pitch\"")
+    );
+    assert_eq!(
+        message("0 0 0 0 0 0\n2 0 0 0 0 0\n1 0 0 0 0 0\n"),
+        format!("{file}:3: time 1 is before the previous pose at 2")
+    );
+    assert_eq!(
+        message("NaN 0 0 0 0 0\n"),
+        format!("{file}:1: time NaN is not finite")
+    );
+}
@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests, every test file only uses some of them
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use glam::Vec3;
+use proptest::prelude::*;
+use software_raymarcher::{
+    materials::{Unlit, BLUE, RED},
+    surfaces::Material,
+};
+
+pub const TOLERANCE: f32 = 0.001;
+
+pub fn red() -> Material {
+    Arc::new(Unlit::new(RED))
+}
+
+pub fn blue() -> Material {
+    Arc::new(Unlit::new(BLUE))
+}
+
+/// Within TOLERANCE, relative for the large distances of scaled surfaces
+pub fn is_close(a: f32, b: f32) -> bool {
+    (a - b).abs() < TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+pub fn assert_close(a: f32, b: f32) {
+    assert!(is_close(a, b), "{a} != {b}");
+}
+
+/// assert_close for proptest, fails the case instead of panicking
+pub fn prop_assert_close(a: f32, b: f32) -> Result<(), TestCaseError> {
+    prop_assert!(is_close(a, b), "{} != {}", a, b);
+    Ok(())
+}
+
+pub fn assert_close_vec3(a: Vec3, b: Vec3) {
+    assert!(a.distance(b) < TOLERANCE, "{a} != {b}");
+}
+
+/// Points in a cube from -extent to extent along every axis
+pub fn point(extent: f32) -> impl Strategy<Value = Vec3> {
+    prop::array::uniform3(-extent..extent).prop_map(Vec3::from)
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use software_raymarcher::{
+    camera_path::CameraPose,
+    expr::{parse_surface, Env, Value},
+    materials::Normal,
+    scene_file::SceneFile,
+    surfaces::{exact_box, smooth_union, sphere, translation, union, Surface},
+};
+
+use common::{assert_close, is_close};
+
+/// Points around the origin to compare distances at
+fn sample_points() -> Vec<Vec3> {
+    let mut points = Vec::new();
+    for x in -3..=3 {
+        for y in -3..=3 {
+            for z in -3..=3 {
+                points.push(vec3(x as f32, y as f32, z as f32) * 0.7 + 0.1);
+            }
+        }
+    }
+    points
+}
+
+fn assert_same_sdf(a: &Surface, b: &Surface) {
+    for pos in sample_points() {
+        let (da, db) = (a.sdf(pos), b.sdf(pos));
+        assert!(is_close(da, db), "{da} != {db} at {pos}");
+    }
+}
+
+fn eval_number(text: &str) -> f32 {
+    match Env::new().eval(text, "test").unwrap() {
+        Some(Value::Number(number)) => number,
+        _ => panic!("{text} is not a number"),
+    }
+}
+
+fn error_message(text: &str) -> String {
+    match parse_surface(text) {
+        Ok(_) => panic!("{text} should not parse"),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn matches_constructors() {
+    let parsed = parse_surface("smin(sphere(1) @ (0, 1, 0), box(2, 1, 2), 0.5)").unwrap();
+    let built = smooth_union(
+        translation(vec3(0.0, 1.0, 0.0), sphere(1.0, Arc::new(Normal))),
+        exact_box(vec3(2.0, 1.0, 2.0), Arc::new(Normal)),
+        0.5,
+    );
+    assert_same_sdf(&parsed, &built);
+}
+
+#[test]
+fn arithmetic_on_numbers_and_distances() {
+    assert_eq!(eval_number("1 + 2 * 3 - 4 / 2"), 5.0);
+    assert_eq!(eval_number("-(1 + 2) * 2"), -6.0);
+    assert_eq!(eval_number("max(1, 3, 2) + pow(2, 3)"), 11.0);
+
+    let rounded = parse_surface("box(1, 1, 1) - 0.25").unwrap();
+    let pos = vec3(3.0, 0.0, 0.0);
+    assert_close(rounded.sdf(pos), 1.75);
+    let inverted = parse_surface("-sphere(1)").unwrap();
+    assert_close(inverted.sdf(Vec3::ZERO), 1.0);
+}
+
+#[test]
+fn variables_and_functions() {
+    let parsed = parse_surface(
+        "let r = 0.5;
+         fn pillar(h) = box(r, h, r) @ (0, h, 0);
+         union(pillar(1), pillar(2) @ (3, 0, 0))",
+    )
+    .unwrap();
+    let material = Arc::new(Normal);
+    let pillar = |h: f32| {
+        translation(
+            vec3(0.0, h, 0.0),
+            exact_box(vec3(0.5, h, 0.5), material.clone()),
+        )
+    };
+    let built = union(pillar(1.0), translation(vec3(3.0, 0.0, 0.0), pillar(2.0)));
+    assert_same_sdf(&parsed, &built);
+}
+
+#[test]
+fn definitions_persist_between_calls() {
+    let mut env = Env::new();
+    assert!(env.eval("let r = 2;", "console").unwrap().is_none());
+    assert!(env
+        .eval("fn ball(x) = sphere(x * r);", "console")
+        .unwrap()
+        .is_none());
+    let surface = env.eval_surface("ball(1)", "console").unwrap();
+    assert_close(surface.sdf(Vec3::ZERO), -2.0);
+}
+
+#[test]
+fn errors_have_location() {
+    assert_eq!(
+        error_message("sphere(1) +\n  box(1, 1)"),
+        "<expr>:2:7: argument 1 of box should be a vector, got a number"
+    );
+    assert_eq!(
+        error_message("sphere(1"),
+        "<expr>:1:9: expected ',' or ')', found end of input"
+    );
+    assert_eq!(
+        error_message("spher(1)"),
+        "<expr>:1:1: unknown function \"spher\""
+    );
+    assert_eq!(
+        error_message("fn f(x) = f(x); f(1)"),
+        "<expr>:1:11: calls nested deeper than 64"
+    );
+    // Every function calls the one before twice, 2^40 calls without ever nesting deep
+    let mut doubling = "fn f0(x) = x;".to_string();
+    for i in 1..=40 {
+        doubling += &format!("\nfn f{i}(x) = f{}(x) + f{}(x);", i - 1, i - 1);
+    }
+    doubling += "\nsphere(f40(1))";
+    assert!(error_message(&doubling).ends_with("more than 10000 calls"));
+    assert_eq!(
+        error_message(&format!(
+            "{}sphere(1){}",
+            "(".repeat(100_000),
+            ")".repeat(100_000)
+        )),
+        "<expr>:1:130: expressions nested deeper than 128"
+    );
+    assert_eq!(
+        error_message(&format!("{}sphere(1)", "-".repeat(100_000))),
+        "<expr>:1:130: expressions nested deeper than 128"
+    );
+    assert_eq!(
+        error_message("1 + 2"),
+        "<expr>: expected a surface, got a number"
+    );
+}
+
+#[test]
+fn saves_as_expression() {
+    let text = "let r = 0.5;\nsphere(r, red) @ (0, 1, 0) - 0.1";
+    let surface = parse_surface(text).unwrap();
+    let pose = CameraPose::new(vec3(0.0, 1.0, -5.0), 0.0, 0.0);
+    let scene = SceneFile::new(std::slice::from_ref(&surface), pose, Vec3::ONE).unwrap();
+    assert!(scene
+        .to_ron()
+        .contains("Expr(\"let r = 0.5;\\nsphere(r, red)"));
+
+    let loaded = SceneFile::parse(&scene.to_ron(), "expr.ron").unwrap();
+    assert_eq!(loaded, scene);
+    let rebuilt = loaded.build_surfaces("expr.ron").unwrap();
+    assert_same_sdf(&rebuilt[0], &surface);
+}
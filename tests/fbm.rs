@@ -0,0 +1,166 @@
+mod common;
+
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use proptest::prelude::*;
+use software_raymarcher::{
+    expr::parse_surface,
+    fbm::{Fbm, FbmSettings, NoiseKind},
+    materials::RED,
+    scene::Scene,
+    surfaces::{sphere, Displace, FbmTerrain, Surface, SurfaceTrait},
+};
+
+use common::{point, red};
+
+fn settings() -> impl Strategy<Value = FbmSettings> {
+    let kind = prop_oneof![
+        Just(NoiseKind::Perlin),
+        Just(NoiseKind::Simplex),
+        Just(NoiseKind::Value),
+    ];
+    (
+        kind,
+        0u32..100,
+        0.1f32..3.0,
+        0.1f32..2.0,
+        1u32..6,
+        any::<bool>(),
+    )
+        .prop_map(
+            |(kind, seed, frequency, amplitude, octaves, ridged)| FbmSettings {
+                kind,
+                seed,
+                octaves,
+                ridged,
+                ..FbmSettings::new(frequency, amplitude)
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn stays_within_bounds(settings in settings(), a in point(20.0), offset in point(20.0)) {
+        let fbm = Fbm::new(settings);
+        let value = fbm.get(a);
+        prop_assert!(value.abs() <= fbm.max_value() + 1e-5, "{} at {}", value, a);
+        if settings.ridged {
+            prop_assert!(value >= 0.0, "{} at {}", value, a);
+        }
+        // Close enough to land on a slope of the highest octave
+        let b = a + offset / 400.0;
+        let change = (fbm.get(b) - value).abs();
+        let bound = fbm.max_gradient() * a.distance(b);
+        prop_assert!(change <= bound + 1e-4, "{} > {} from {} to {}", change, bound, a, b);
+    }
+}
+
+#[test]
+fn seeds_change_the_noise() {
+    let pos = vec3(1.3, 2.7, -0.4);
+    let noise = |seed| {
+        Fbm::new(FbmSettings {
+            seed,
+            ..FbmSettings::default()
+        })
+        .get(pos)
+    };
+    assert_eq!(noise(3), noise(3));
+    assert_ne!(noise(3), noise(4));
+
+    let silent = Fbm::new(FbmSettings {
+        octaves: 0,
+        ..FbmSettings::default()
+    });
+    assert_eq!(silent.get(pos), 0.0);
+    assert_eq!(silent.max_gradient(), 0.0);
+}
+
+#[test]
+fn displaces_any_surface() {
+    let settings = FbmSettings::new(2.0, 0.2);
+    let ball = sphere(1.0, red());
+    let displaced = Displace::new(settings, ball.clone());
+    let fbm = Fbm::new(settings);
+    for pos in [vec3(0.5, 0.2, 1.0), vec3(-1.1, 0.0, 0.3)] {
+        assert_eq!(displaced.sdf(pos), ball.sdf(pos) - fbm.get(pos));
+    }
+    // Out of reach of the noise
+    let far = vec3(-3.0, 0.0, 0.0);
+    assert_eq!(displaced.sdf(far), 1.8);
+    let bounds = displaced.bounds().unwrap();
+    assert_eq!(bounds.max, Vec3::splat(1.2));
+    assert_eq!(displaced.lipschitz(), 1.0 + fbm.max_gradient());
+    let color = displaced.color(Vec3::Z, Vec3::ZERO, Vec3::Y, Vec3::Y);
+    assert_eq!(color, RED);
+}
+
+#[test]
+fn terrain_follows_the_noise() {
+    let settings = FbmSettings {
+        ridged: true,
+        ..FbmSettings::new(0.1, 3.0)
+    };
+    let terrain = FbmTerrain::new(settings, red());
+    let fbm = Fbm::new(settings);
+    for (x, z) in [(0.0, 0.0), (12.5, -3.0), (-40.0, 7.0)] {
+        let height = fbm.get(vec3(x, 0.0, z));
+        assert_eq!(terrain.height(x, z), height);
+        assert!(terrain.sdf(vec3(x, height, z)).abs() < 1e-6);
+        assert!(terrain.sdf(vec3(x, height - 0.5, z)) < 0.0);
+    }
+    assert!(terrain.bounds().is_none());
+    let slope = fbm.max_gradient();
+    assert_eq!(terrain.lipschitz(), (1.0 + slope * slope).sqrt());
+    // Far above the peaks the distance to them is used as is
+    assert_eq!(terrain.sdf(vec3(0.0, 13.0, 0.0)), 10.0);
+    let layer = terrain.march_bounds().unwrap();
+    assert_eq!((layer.min.y, layer.max.y), (-3.0, 3.0));
+    assert_eq!(layer.distance(vec3(500.0, 13.0, -80.0)), 10.0);
+    assert_eq!(layer.distance(vec3(500.0, 1.0, -80.0)), 0.0);
+}
+
+#[test]
+fn rays_from_the_sky_reach_the_terrain() {
+    let settings = FbmSettings::new(0.1, 3.0);
+    let terrain: Surface = Arc::new(FbmTerrain::new(settings, red()));
+    let scene = Scene::new(Arc::new(vec![terrain]));
+    // Above the layer of peaks the steps aren't shortened by the lipschitz of the terrain
+    let hit = scene
+        .cast_ray(vec3(2.0, 60.0, 5.0), Vec3::NEG_Y, 100.0)
+        .unwrap();
+    assert!((hit.position.y - Fbm::new(settings).get(vec3(2.0, 0.0, 5.0))).abs() < 1e-3);
+    assert!(hit.steps < 100, "{} steps", hit.steps);
+}
+
+#[test]
+fn expressions() {
+    let pos = vec3(0.3, 1.1, -0.2);
+    let displaced = parse_surface("displace(sphere(1), 0.1, 2)").unwrap();
+    let ball = sphere(1.0, red());
+    let expected = Displace::new(FbmSettings::new(2.0, 0.1), ball);
+    assert_eq!(displaced.sdf(pos), expected.sdf(pos));
+
+    let terrain = parse_surface("terrain(2, 0.3, 6, 9, (1, 0, 0))").unwrap();
+    let settings = FbmSettings {
+        octaves: 6,
+        seed: 9,
+        ..FbmSettings::new(0.3, 2.0)
+    };
+    let expected = FbmTerrain::new(settings, red());
+    assert_eq!(terrain.sdf(pos), expected.sdf(pos));
+    assert_eq!(terrain.color(Vec3::Z, pos, Vec3::Y, Vec3::Y), RED);
+    assert!(parse_surface("terrain(2, 0.3, red)").is_ok());
+
+    let err = parse_surface("terrain(2, red, 3)").err().unwrap();
+    assert!(
+        err.to_string()
+            .contains("argument 2 of terrain should be a number"),
+        "{err}"
+    );
+    let err = parse_surface("terrain(2, 0.3, red, 3)").err().unwrap();
+    assert!(err.to_string().contains("takes 2 to 3 arguments"), "{err}");
+    let err = parse_surface("displace(1, 2, 3)").err().unwrap();
+    assert!(err.to_string().contains("should be a surface"), "{err}");
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use proptest::prelude::*;
+use software_raymarcher::{
+    expr::parse_surface,
+    materials::TrapGradient,
+    surfaces::{
+        infinite_repetition, mandelbox, mandelbulb, menger_sponge, sierpinski_tetrahedron,
+        translation, Mandelbox, Mandelbulb, MengerSponge, SierpinskiTetrahedron, Surface,
+    },
+};
+
+use common::{point, red};
+
+const NEAR: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+const FAR: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+/// Fractals with a point that is always part of them
+fn fractals() -> Vec<(&'static str, Surface, Vec3)> {
+    vec![
+        ("mandelbulb", mandelbulb(8.0, red()), Vec3::ZERO),
+        ("quadratic mandelbulb", mandelbulb(2.0, red()), Vec3::ZERO),
+        ("menger sponge", menger_sponge(1.5, red()), Vec3::splat(1.5)),
+        (
+            "sierpinski",
+            sierpinski_tetrahedron(2.0, red()),
+            Vec3::splat(2.0),
+        ),
+        ("mandelbox", mandelbox(2.0, red()), Vec3::ZERO),
+        ("negative mandelbox", mandelbox(-1.5, red()), Vec3::ZERO),
+        (
+            "folded mandelbox",
+            Arc::new(
+                Mandelbox::new(2.5, red())
+                    .with_min_radius(0.3)
+                    .with_fold_limit(1.5),
+            ),
+            Vec3::ZERO,
+        ),
+    ]
+}
+
+/// Fractals whose estimate is a lower bound of the distance to a finite level of the fractal
+fn exact_fractals() -> Vec<(&'static str, Surface)> {
+    vec![
+        ("menger sponge", menger_sponge(1.5, red())),
+        (
+            "deep menger sponge",
+            Arc::new(MengerSponge::new(1.0, red()).with_iterations(6)),
+        ),
+        ("sierpinski", sierpinski_tetrahedron(2.0, red())),
+        (
+            "shallow sierpinski",
+            Arc::new(SierpinskiTetrahedron::new(1.0, red()).with_iterations(2)),
+        ),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn members_bound_distance(pos in point(4.0)) {
+        for (name, fractal, member) in fractals() {
+            let dist = fractal.sdf(pos);
+            prop_assert!(dist <= pos.distance(member) + 1e-4, "{}: {} at {}", name, dist, pos);
+            prop_assert!(fractal.sdf(member) <= 1e-4, "{}: {} at {}", name, dist, member);
+        }
+    }
+
+    #[test]
+    fn distances_change_at_most_by_distance(a in point(4.0), b in point(4.0)) {
+        for (name, fractal) in exact_fractals() {
+            let change = (fractal.sdf(a) - fractal.sdf(b)).abs();
+            prop_assert!(change <= a.distance(b) * 1.001 + 1e-4, "{}: {} from {} to {}", name, change, a, b);
+        }
+    }
+
+    #[test]
+    fn trap_stays_in_range(pos in point(4.0)) {
+        let (_, trap) = Mandelbulb::new(8.0, Arc::new(TrapGradient::new(NEAR, FAR))).orbit(pos);
+        prop_assert!(trap.origin >= 0.0 && trap.origin <= pos.length());
+        prop_assert!(trap.planes.cmple(pos.abs()).all());
+        prop_assert!((0.0..=1.0).contains(&trap.escape));
+    }
+}
+
+#[test]
+fn marches_to_the_bulb_from_far_away() {
+    let bulb = translation(vec3(0.0, 0.0, 10.0), mandelbulb(8.0, red()));
+    let mut pos = Vec3::ZERO;
+    for _ in 0..200 {
+        pos.z += bulb.sdf(pos);
+    }
+    // The bulb reaches just past a radius of 1 along the z axis
+    assert!(pos.z > 8.5 && pos.z < 9.5, "stopped at {}", pos.z);
+    assert!(bulb.sdf(pos) < 0.001);
+}
+
+#[test]
+fn colors_follow_the_trap() {
+    let material = Arc::new(TrapGradient::new(NEAR, FAR));
+    let surfaces: [Surface; 2] = [
+        Arc::new(Mandelbulb::new(8.0, material.clone())),
+        Arc::new(Mandelbox::new(2.0, material)),
+    ];
+    for surface in surfaces {
+        let color = |pos: Vec3| surface.color(Vec3::Z, pos, Vec3::Y, Vec3::Y);
+        // Orbits starting at the origin stay there
+        assert_eq!(color(Vec3::ZERO), NEAR);
+        assert_eq!(color(vec3(30.0, 0.0, 0.0)), FAR);
+        let between = color(vec3(0.0, 0.0, 0.6));
+        assert!(between.x > 0.0 && between.z > 0.0, "{between}");
+    }
+}
+
+#[test]
+fn repeats_without_gaps() {
+    // Sponges touching each other make an infinite sponge with endless tunnels
+    let sponge = menger_sponge(1.0, red());
+    let sponges = infinite_repetition(Vec3::splat(2.0), sponge.clone());
+    let mut pos = Vec3::ZERO;
+    for _ in 0..100 {
+        pos.x += sponges.sdf(pos);
+    }
+    assert!(pos.x > 30.0, "stopped at {pos}");
+
+    let solid = vec3(0.0, 0.8, 0.8);
+    for offset in [
+        vec3(2.0, 0.0, 0.0),
+        vec3(0.0, -4.0, 0.0),
+        vec3(6.0, 2.0, -8.0),
+    ] {
+        assert!((sponges.sdf(solid + offset) - sponge.sdf(solid)).abs() < 1e-5);
+    }
+    assert!(sponge.sdf(solid) <= 0.0);
+}
+
+#[test]
+fn expression_matches_constructor() {
+    let cases: [(&str, Surface); 8] = [
+        ("mandelbulb(8)", mandelbulb(8.0, red())),
+        (
+            "mandelbulb(3, 5, 1.5)",
+            Arc::new(
+                Mandelbulb::new(3.0, red())
+                    .with_iterations(5)
+                    .with_bailout(1.5),
+            ),
+        ),
+        ("menger(2)", menger_sponge(2.0, red())),
+        (
+            "menger(2, 3)",
+            Arc::new(MengerSponge::new(2.0, red()).with_iterations(3)),
+        ),
+        ("sierpinski(1.5, red)", sierpinski_tetrahedron(1.5, red())),
+        (
+            "sierpinski(1.5, 4, red)",
+            Arc::new(SierpinskiTetrahedron::new(1.5, red()).with_iterations(4)),
+        ),
+        ("mandelbox(-1.5)", mandelbox(-1.5, red())),
+        (
+            "mandelbox(2, 0.4, 1.2, 6)",
+            Arc::new(
+                Mandelbox::new(2.0, red())
+                    .with_min_radius(0.4)
+                    .with_fold_limit(1.2)
+                    .with_iterations(6),
+            ),
+        ),
+    ];
+    for (text, built) in cases {
+        let parsed = parse_surface(text).unwrap();
+        for pos in [
+            vec3(0.3, 0.5, -0.2),
+            vec3(1.0, 0.1, 0.4),
+            vec3(0.0, -1.2, 0.7),
+        ] {
+            assert_eq!(parsed.sdf(pos), built.sdf(pos), "{text} at {pos}");
+        }
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use software_raymarcher::{
+    materials,
+    scene::Scene,
+    surfaces::{from_fn, infinite_repetition, smooth_union, sphere, translation, Aabb, FnSurface},
+};
+
+use common::{assert_close, red};
+
+#[test]
+fn closure_matches_builtin_in_operators() {
+    let ball = || from_fn(|p| p.length() - 1.0, red());
+    let pairs = [
+        (
+            smooth_union(ball(), translation(Vec3::X, ball()), 0.5),
+            smooth_union(
+                sphere(1.0, red()),
+                translation(Vec3::X, sphere(1.0, red())),
+                0.5,
+            ),
+        ),
+        (
+            infinite_repetition(vec3(3.0, 3.0, 3.0), ball()),
+            infinite_repetition(vec3(3.0, 3.0, 3.0), sphere(1.0, red())),
+        ),
+    ];
+    for (closure, builtin) in pairs {
+        for pos in [
+            vec3(0.3, 2.0, -1.0),
+            vec3(4.0, 0.5, 2.0),
+            vec3(-0.2, 0.1, 0.0),
+        ] {
+            assert_close(closure.sdf(pos), builtin.sdf(pos));
+        }
+    }
+}
+
+#[test]
+fn lipschitz_hint_prevents_overstepping() {
+    // Three times the distance to a unit sphere, steps overshoot it without the hint
+    let overestimate = |p: Vec3| 3.0 * (p.length() - 1.0);
+    let origin = vec3(0.0, 0.0, -5.0);
+
+    let unhinted = Scene::new(Arc::new(vec![from_fn(overestimate, red())]));
+    assert!(unhinted.cast_ray(origin, Vec3::Z, 100.0).is_none());
+
+    let hinted = FnSurface::new(overestimate, red())
+        .with_lipschitz(3.0)
+        .with_bounds(Aabb::new(Vec3::splat(-1.0), Vec3::ONE));
+    let scene = Scene::new(Arc::new(vec![Arc::new(hinted)]));
+    let hit = scene.cast_ray(origin, Vec3::Z, 100.0).unwrap();
+    assert_close(hit.t, 4.0);
+    assert!(scene.bounds().is_some());
+
+    // Operators keep the hint of their children
+    let moved = translation(
+        Vec3::Y,
+        Arc::new(FnSurface::new(overestimate, red()).with_lipschitz(3.0)),
+    );
+    assert_eq!(moved.lipschitz(), 3.0);
+}
+
+#[test]
+fn closure_material() {
+    let material = materials::from_fn(|p| p.normal.abs() * 0.5 + p.pos.y);
+    let surface = from_fn(|p| p.length() - 1.0, material);
+    let color = surface.color(Vec3::Z, vec3(0.0, 1.0, 0.0), Vec3::Y, Vec3::ZERO);
+    assert_eq!(color, vec3(1.0, 1.5, 1.0));
+}
@@ -0,0 +1,133 @@
+//! Renders every scene in scenes/ and compares it with the reference image in tests/golden/
+//!
+//! Run with UPDATE_GOLDEN=1 to write new references after an intended change. Failing scenes
+//! write the rendered image and a diff to target/tmp/golden/, differing pixels are red in the diff
+
+use std::{env, fs, path::Path};
+
+use image::{Rgba, RgbaImage};
+use software_raymarcher::raymarcher::{Raymarcher, RenderSettings};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+const GOLDEN_DIR: &str = "tests/golden";
+/// Perceptual distance between two colors above which a pixel counts as different, in [0,1]
+const PIXEL_TOLERANCE: f32 = 0.05;
+/// Fraction of pixels that may differ, for edges that fall on the other side of a pixel
+const MAX_DIFFERENT_PIXELS: f32 = 0.005;
+
+/// Weighted euclidean distance, scaled to [0,1]
+///
+/// Weights depend on the mean red like the "redmean" approximation of perceived difference
+fn color_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [r1, g1, b1, _] = a.0.map(|c| c as f32 / 255.0);
+    let [r2, g2, b2, _] = b.0.map(|c| c as f32 / 255.0);
+    let mean_red = (r1 + r2) / 2.0;
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+    let squared = (2.0 + mean_red) * dr * dr + 4.0 * dg * dg + (3.0 - mean_red) * db * db;
+    (squared / 9.0).sqrt()
+}
+
+/// Image with differing pixels in red over a darkened copy of expected
+fn diff_image(expected: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, u32) {
+    let mut different = 0;
+    let diff = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (a, b) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        if color_distance(a, b) > PIXEL_TOLERANCE {
+            different += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = a.0;
+            let gray = ((r as u32 + g as u32 + b as u32) / 9) as u8;
+            Rgba([gray, gray, gray, 255])
+        }
+    });
+    (diff, different)
+}
+
+fn render(scene: &str) -> RgbaImage {
+    let raymarcher = Raymarcher::load(scene)
+        .unwrap_or_else(|err| panic!("{err}"))
+        .with_settings(RenderSettings::new(WIDTH, HEIGHT));
+    let mut pixels = vec![0; (WIDTH * HEIGHT * 4) as usize];
+    raymarcher.render(&mut pixels);
+    RgbaImage::from_raw(WIDTH, HEIGHT, pixels).unwrap()
+}
+
+/// Returns why the scene doesn't match its reference
+fn check(scene: &Path, update: bool) -> Option<String> {
+    let name = scene.file_stem().unwrap().to_str().unwrap();
+    let reference = Path::new(GOLDEN_DIR).join(format!("{name}.png"));
+    let actual = render(scene.to_str().unwrap());
+
+    if update {
+        actual.save(&reference).unwrap();
+        return None;
+    }
+    let expected = match image::open(&reference) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => {
+            return Some(format!(
+                "{name}: no reference at {} ({err}), run with UPDATE_GOLDEN=1",
+                reference.display()
+            ))
+        }
+    };
+    if expected.dimensions() != actual.dimensions() {
+        return Some(format!(
+            "{name}: reference is {:?}, rendered {:?}",
+            expected.dimensions(),
+            actual.dimensions()
+        ));
+    }
+
+    let (diff, different) = diff_image(&expected, &actual);
+    let fraction = different as f32 / (WIDTH * HEIGHT) as f32;
+    if fraction <= MAX_DIFFERENT_PIXELS {
+        return None;
+    }
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out_dir).unwrap();
+    let actual_path = out_dir.join(format!("{name}-actual.png"));
+    let diff_path = out_dir.join(format!("{name}-diff.png"));
+    actual.save(&actual_path).unwrap();
+    diff.save(&diff_path).unwrap();
+    Some(format!(
+        "{name}: {different} pixels ({:.2}%) differ, see {} and {}",
+        fraction * 100.0,
+        actual_path.display(),
+        diff_path.display()
+    ))
+}
+
+#[test]
+fn scenes_match_references() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    if update {
+        fs::create_dir_all(GOLDEN_DIR).unwrap();
+    }
+    let mut scenes = fs::read_dir("scenes")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    scenes.sort();
+
+    let failures = scenes
+        .iter()
+        .filter_map(|scene| check(scene, update))
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn diff_marks_changed_pixels() {
+    let expected = RgbaImage::from_pixel(WIDTH, HEIGHT, Rgba([100, 150, 200, 255]));
+    let mut actual = expected.clone();
+    // Below the tolerance
+    actual.put_pixel(0, 0, Rgba([102, 151, 199, 255]));
+    actual.put_pixel(5, 7, Rgba([200, 150, 200, 255]));
+    let (diff, different) = diff_image(&expected, &actual);
+    assert_eq!(different, 1);
+    assert_eq!(diff.get_pixel(5, 7), &Rgba([255, 0, 0, 255]));
+    assert_ne!(diff.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+}
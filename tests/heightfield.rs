@@ -0,0 +1,137 @@
+mod common;
+
+use std::{
+    f32::consts::PI,
+    sync::{Arc, OnceLock},
+};
+
+use glam::{vec2, vec3, Vec2, Vec3};
+use image::DynamicImage;
+use proptest::prelude::*;
+use software_raymarcher::{
+    camera_path::CameraPose,
+    expr::parse_surface,
+    materials::Textured,
+    scene_file::SceneFile,
+    surfaces::{Heightfield, Surface, SurfaceTrait},
+};
+
+use common::red;
+
+fn terrain() -> &'static Heightfield {
+    static TERRAIN: OnceLock<Heightfield> = OnceLock::new();
+    TERRAIN.get_or_init(|| {
+        Heightfield::load("assets/heightmap.png", Vec2::splat(12.0), 2.5, red()).unwrap()
+    })
+}
+
+fn near_terrain() -> impl Strategy<Value = Vec3> {
+    (-8.0f32..8.0, -1.0f32..4.0, -8.0f32..8.0).prop_map(|(x, y, z)| vec3(x, y, z))
+}
+
+proptest! {
+    #[test]
+    fn steps_never_pass_the_surface(pos in near_terrain(), x in -6.0f32..6.0, z in -6.0f32..6.0) {
+        // Any point on top of the terrain is at least as far as the surface
+        let on_top = vec3(x, terrain().height(x, z), z);
+        let step = terrain().sdf(pos).abs() / terrain().lipschitz();
+        prop_assert!(step <= pos.distance(on_top) + 1e-4, "{} from {} at {}", step, pos, on_top);
+    }
+}
+
+#[test]
+fn interpolates_between_samples() {
+    // The first row is the far side at +z
+    let values = vec![0.0, 0.5, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+    let field = Heightfield::from_grid(3, 3, values, vec2(4.0, 2.0), 2.0, red());
+    assert_eq!(field.height(-2.0, 1.0), 0.0);
+    assert_eq!(field.height(0.0, 1.0), 1.0);
+    assert_eq!(field.height(2.0, 1.0), 2.0);
+    assert_eq!(field.height(0.0, -1.0), 2.0);
+    assert_eq!(field.height(1.0, 1.0), 1.5);
+    assert_eq!(field.height(-1.0, -0.5), 1.0);
+    // Outside takes the closest side
+    assert_eq!(field.height(10.0, 5.0), 2.0);
+
+    // Steepest is from 0 to 2 over a cell 1 deep, bilinear is steeper still on the diagonal
+    assert!(field.max_slope() >= 2.0, "{}", field.max_slope());
+    assert!(
+        field.max_slope() <= 2.0 * 2.0f32.sqrt(),
+        "{}",
+        field.max_slope()
+    );
+}
+
+#[test]
+fn solid_below_the_heights() {
+    let field = Heightfield::from_fn(|pos| 0.5 + 0.5 * pos.x.sin(), Vec2::splat(4.0), 2.0, red());
+    assert!(field.sdf(vec3(0.0, 0.5, 0.0)) < 0.0);
+    assert!((field.sdf(vec3(0.0, 1.5, 0.0)) - 0.5).abs() < 1e-6);
+    assert!(field.sdf(vec3(0.0, -0.5, 0.0)) > 0.0);
+    assert!(field.sdf(vec3(3.0, 0.5, 0.0)) > 0.0);
+    let bounds = field.bounds().unwrap();
+    assert_eq!(bounds.min, vec3(-2.0, 0.0, -2.0));
+    assert_eq!(bounds.max, vec3(2.0, 2.0, 2.0));
+
+    // A function slope of 1 is twice as steep once scaled by the height
+    assert_eq!(field.max_slope(), 2.0);
+    assert_eq!(field.lipschitz(), 5.0f32.sqrt());
+    let steep =
+        Heightfield::from_fn(|pos| (pos.x * PI).sin(), Vec2::ONE, 1.0, red()).with_max_slope(PI);
+    assert_eq!(steep.lipschitz(), (1.0 + PI * PI).sqrt());
+}
+
+#[test]
+fn loads_images() {
+    let field = terrain();
+    let bounds = field.bounds().unwrap();
+    assert_eq!(bounds.max.y, 2.5);
+    assert!(field.max_slope() > 0.0);
+    for (x, z) in [(0.0, 0.0), (-5.5, 3.0), (6.0, -6.0)] {
+        let height = field.height(x, z);
+        assert!((0.0..=2.5).contains(&height), "{height} at {x}, {z}");
+    }
+
+    let textured = Heightfield::load(
+        "assets/heightmap.png",
+        Vec2::splat(12.0),
+        2.5,
+        Arc::new(Textured::new("assets/grass.jpeg")),
+    )
+    .unwrap();
+    let color = textured.color(Vec3::NEG_Y, vec3(1.0, 1.0, 1.0), Vec3::Y, Vec3::Y * 10.0);
+    assert!(color.is_finite());
+
+    let err = Heightfield::load("missing.png", Vec2::ONE, 1.0, red());
+    assert!(err.is_err());
+
+    let thin = DynamicImage::new_luma8(1, 4);
+    let err = Heightfield::from_image(&thin, Vec2::ONE, 1.0, red());
+    assert!(err.is_err());
+}
+
+#[test]
+fn expressions_and_scene_files() {
+    let surface = parse_surface("heightfield(\"assets/heightmap.png\", 12, 12, 2.5)").unwrap();
+    let pos = vec3(1.0, 3.0, -2.0);
+    assert_eq!(surface.sdf(pos), terrain().sdf(pos));
+    let err = parse_surface("heightfield(\"missing.png\", 1, 1, 1)")
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains("could not load heightmap"),
+        "{err}"
+    );
+
+    let pose = CameraPose::new(vec3(0.0, 5.0, -9.0), 0.0, 0.0);
+    let scene = SceneFile::new(std::slice::from_ref(&surface), pose, Vec3::ONE).unwrap();
+    let rebuilt = SceneFile::parse(&scene.to_ron(), "terrain.ron")
+        .unwrap()
+        .build_surfaces("terrain.ron")
+        .unwrap();
+    assert_eq!(rebuilt[0].sdf(pos), surface.sdf(pos));
+
+    // Functions can't be saved
+    let function: Surface = Arc::new(Heightfield::from_fn(|_| 0.0, Vec2::ONE, 1.0, red()));
+    assert!(function.describe().is_none());
+}
@@ -0,0 +1,107 @@
+use std::{env, fs};
+
+use pixelated::input::KeyCode;
+use software_raymarcher::input_map::{key_from_name, key_name, Action, InputMap};
+
+/// Loads text as a config file, the file name is only used in error messages
+fn load(name: &str, text: &str) -> std::io::Result<InputMap> {
+    let path = env::temp_dir().join(format!("software_raymarcher_{name}.txt"));
+    let path = path.to_str().unwrap();
+    fs::write(path, text).unwrap();
+    let input_map = InputMap::load(path);
+    fs::remove_file(path).unwrap();
+    input_map
+}
+
+fn error_message(name: &str, text: &str) -> String {
+    let message = load(name, text).err().unwrap().to_string();
+    // Strip the temporary directory, keeping the line number
+    message.split_once(".txt:").unwrap().1.to_string()
+}
+
+#[test]
+fn azerty_remapping() {
+    let text = "\
+# Movement for AZERTY keyboards
+move_forward = Z
+move_left = Q
+  # Indented comment
+move_down = W
+
+move_up = X, Space
+move_speed = 5
+";
+    let input_map = load("azerty", text).unwrap();
+    assert_eq!(input_map.keys(Action::MoveForward), &[KeyCode::Z]);
+    assert_eq!(input_map.keys(Action::MoveLeft), &[KeyCode::Q]);
+    assert_eq!(input_map.keys(Action::MoveDown), &[KeyCode::W]);
+    assert_eq!(
+        input_map.keys(Action::MoveUp),
+        &[KeyCode::X, KeyCode::Space]
+    );
+    assert_eq!(input_map.move_speed, 5.0);
+
+    // Everything else keeps its default
+    let defaults = InputMap::new();
+    assert_eq!(
+        input_map.keys(Action::MoveBack),
+        defaults.keys(Action::MoveBack)
+    );
+    assert_eq!(input_map.keys(Action::Pause), defaults.keys(Action::Pause));
+    assert_eq!(input_map.rotate_speed, defaults.rotate_speed);
+}
+
+#[test]
+fn empty_bindings_unbind() {
+    let input_map = load("unbind", "screenshot =\npan = ,\n").unwrap();
+    assert!(input_map.keys(Action::Screenshot).is_empty());
+    assert!(input_map.keys(Action::Pan).is_empty());
+}
+
+#[test]
+fn save_load_round_trip() {
+    let mut input_map = InputMap::new();
+    input_map.bind(Action::MoveForward, &[KeyCode::Up, KeyCode::K]);
+    input_map.bind(Action::Screenshot, &[]);
+    input_map.fast_multiplier = 8.0;
+    let path = env::temp_dir().join("software_raymarcher_controls.txt");
+    let path = path.to_str().unwrap();
+    input_map.save(path).unwrap();
+    let loaded = InputMap::load(path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(loaded, input_map);
+}
+
+#[test]
+fn invalid_files_are_errors() {
+    assert_eq!(
+        error_message("no_equals", "# comment\nmove_forward W\n"),
+        "2: expected \"action = key, key\""
+    );
+    assert_eq!(
+        error_message("unknown_action", "fly = W\n"),
+        "1: unknown action \"fly\""
+    );
+    assert_eq!(
+        error_message("unknown_key", "\nmove_forward = W, Hyper\n"),
+        "2: unknown key \"Hyper\""
+    );
+    assert_eq!(
+        error_message("bad_number", "move_speed = fast\n"),
+        "1: invalid number \"fast\""
+    );
+    assert!(InputMap::load("missing_controls.txt").is_err());
+}
+
+#[test]
+fn names_round_trip() {
+    for key in [KeyCode::W, KeyCode::LShift, KeyCode::PageUp, KeyCode::Key1] {
+        assert_eq!(key_from_name(key_name(key).unwrap()), Some(key));
+    }
+    assert_eq!(key_from_name("w"), None);
+    assert_eq!(
+        Action::from_name(Action::ViewStepLimit.name()),
+        Some(Action::ViewStepLimit)
+    );
+    assert_eq!(Action::from_name("MoveForward"), None);
+}
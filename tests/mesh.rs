@@ -0,0 +1,279 @@
+mod common;
+
+use std::{
+    env,
+    f32::consts::PI,
+    fs,
+    sync::{Arc, OnceLock},
+};
+
+use glam::{vec3, Vec3};
+use proptest::prelude::*;
+use software_raymarcher::{
+    expr::parse_surface,
+    materials::Textured,
+    mesh::Mesh,
+    surfaces::{exact_box, sphere, subtraction, translation, MeshSurface, Surface, SurfaceTrait},
+};
+
+use common::{assert_close, is_close, point, prop_assert_close, red, TOLERANCE};
+
+/// Cube from -1 to 1, with faces wound counter clockwise seen from outside
+const CUBE_OBJ: &str = "\
+# cube
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2/1 3/2 7/3 -3/4
+";
+
+fn cube() -> Mesh {
+    Mesh::parse_obj(CUBE_OBJ).unwrap()
+}
+
+fn cube_triangles() -> Vec<[Vec3; 3]> {
+    let corners = [
+        vec3(-1.0, -1.0, -1.0),
+        vec3(1.0, -1.0, -1.0),
+        vec3(1.0, 1.0, -1.0),
+        vec3(-1.0, 1.0, -1.0),
+        vec3(-1.0, -1.0, 1.0),
+        vec3(1.0, -1.0, 1.0),
+        vec3(1.0, 1.0, 1.0),
+        vec3(-1.0, 1.0, 1.0),
+    ];
+    let faces = [
+        [0, 3, 2, 1],
+        [4, 5, 6, 7],
+        [0, 1, 5, 4],
+        [3, 7, 6, 2],
+        [0, 4, 7, 3],
+        [1, 2, 6, 5],
+    ];
+    faces
+        .iter()
+        .flat_map(|f| {
+            [
+                [corners[f[0]], corners[f[1]], corners[f[2]]],
+                [corners[f[0]], corners[f[2]], corners[f[3]]],
+            ]
+        })
+        .collect()
+}
+
+fn binary_stl(triangles: &[[Vec3; 3]]) -> Vec<u8> {
+    // Header starting with solid like some exporters write
+    let mut bytes = b"solid binary".to_vec();
+    bytes.resize(80, 0);
+    bytes.extend((triangles.len() as u32).to_le_bytes());
+    for triangle in triangles {
+        bytes.extend([0u8; 12]);
+        for corner in triangle {
+            for coord in corner.to_array() {
+                bytes.extend(coord.to_le_bytes());
+            }
+        }
+        bytes.extend([0u8; 2]);
+    }
+    bytes
+}
+
+fn ascii_stl(triangles: &[[Vec3; 3]]) -> String {
+    let mut text = "solid cube\n".to_string();
+    for [a, b, c] in triangles {
+        text += "  facet normal 0 0 0\n    outer loop\n";
+        for corner in [a, b, c] {
+            text += &format!("      vertex {} {} {}\n", corner.x, corner.y, corner.z);
+        }
+        text += "    endloop\n  endfacet\n";
+    }
+    text + "endsolid cube\n"
+}
+
+/// UV sphere with the poles closed by fans
+fn sphere_mesh(radius: f32, rings: usize, segments: usize) -> Mesh {
+    let point = |ring: usize, segment: usize| {
+        // sin(PI) isn't exactly 0, which would leave slivers around the bottom pole unwelded
+        if ring == 0 || ring == rings {
+            return vec3(0.0, if ring == 0 { radius } else { -radius }, 0.0);
+        }
+        let theta = PI * ring as f32 / rings as f32;
+        let phi = 2.0 * PI * segment as f32 / segments as f32;
+        radius
+            * vec3(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            )
+    };
+    let mut triangles = Vec::new();
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let next = segment + 1;
+            let (a, b) = (point(ring, segment), point(ring, next));
+            let (c, d) = (point(ring + 1, next), point(ring + 1, segment));
+            triangles.push([a, b, c]);
+            triangles.push([a, c, d]);
+        }
+    }
+    Mesh::new(triangles)
+}
+
+fn box_distance(pos: Vec3) -> f32 {
+    let q = pos.abs() - Vec3::ONE;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+}
+
+proptest! {
+    #[test]
+    fn cube_is_exact(pos in point(4.0)) {
+        let dist = cube().signed_distance(pos);
+        prop_assert_close(dist, box_distance(pos))?;
+    }
+
+    #[test]
+    fn corners_and_edges_have_the_right_sign(dir in prop::array::uniform3(-1.0f32..1.0), d in -0.5f32..0.5) {
+        // Points near the corners and edges of the cube, where face normals disagree
+        let pos = Vec3::from(dir).signum() * (1.0 + d) + Vec3::from(dir) * 0.01;
+        let dist = cube().signed_distance(pos);
+        prop_assert_close(dist, box_distance(pos))?;
+    }
+
+    #[test]
+    fn tesselated_sphere_is_close(pos in point(4.0)) {
+        // Flat triangles are at most this much inside the sphere for 32 rings
+        let sagitta = 1.0 - (PI / 32.0).cos();
+        static MESH: OnceLock<Mesh> = OnceLock::new();
+        let dist = MESH.get_or_init(|| sphere_mesh(1.0, 32, 64)).signed_distance(pos);
+        let expected = pos.length() - 1.0;
+        prop_assert!(dist >= expected - TOLERANCE && dist <= expected + 2.0 * sagitta, "{} at {}", dist, pos);
+    }
+}
+
+#[test]
+fn formats_match() {
+    let triangles = cube_triangles();
+    let meshes = [
+        Mesh::new(triangles.clone()),
+        Mesh::parse_stl(&binary_stl(&triangles)).unwrap(),
+        Mesh::parse_stl(ascii_stl(&triangles).as_bytes()).unwrap(),
+    ];
+    let obj = cube();
+    assert_eq!(obj.triangle_count(), 12);
+    assert_eq!(obj.vertex_count(), 8);
+    for mesh in meshes {
+        assert_eq!(mesh.triangle_count(), 12);
+        // Corners shared by triangles are merged
+        assert_eq!(mesh.vertex_count(), 8);
+        assert_eq!(mesh.bounds(), obj.bounds());
+        for pos in [
+            vec3(0.5, 2.0, 0.1),
+            vec3(1.5, 1.5, -1.5),
+            vec3(0.2, -0.3, 0.9),
+        ] {
+            assert_close(mesh.signed_distance(pos), obj.signed_distance(pos));
+        }
+    }
+}
+
+#[test]
+fn loads_files() {
+    let dir = env::temp_dir();
+    let obj_path = dir.join("software_raymarcher_cube.OBJ");
+    let stl_path = dir.join("software_raymarcher_cube.stl");
+    fs::write(&obj_path, CUBE_OBJ).unwrap();
+    fs::write(&stl_path, binary_stl(&cube_triangles())).unwrap();
+    let obj = Mesh::load(&obj_path);
+    let stl = Mesh::load(&stl_path);
+    fs::remove_file(obj_path).unwrap();
+    fs::remove_file(stl_path).unwrap();
+    assert_eq!(obj.unwrap().triangle_count(), 12);
+    assert_eq!(stl.unwrap().triangle_count(), 12);
+
+    let knot = Mesh::load("assets/knot.obj").unwrap();
+    // The tube of the knot passes through (1.5, 0, 0)
+    assert!(knot.signed_distance(vec3(1.5, 0.0, 0.0)) < -0.2);
+    assert!(knot.signed_distance(Vec3::ZERO) > 0.0);
+}
+
+#[test]
+fn invalid_files_are_errors() {
+    let message = |result: std::io::Result<Mesh>| result.err().unwrap().to_string();
+    assert_eq!(
+        message(Mesh::parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3")),
+        "line 3: face index out of range"
+    );
+    assert_eq!(
+        message(Mesh::parse_obj("v 0 x 0")),
+        "line 1: invalid vertex"
+    );
+    assert_eq!(
+        message(Mesh::parse_obj("v 0 0 0\nf 1 1")),
+        "line 2: face needs at least 3 corners"
+    );
+    assert!(message(Mesh::parse_stl(&[0; 90])).contains("not an STL file"));
+    assert!(
+        message(Mesh::parse_stl(b"solid x\nvertex 0 0 0\nvertex 1 0 0\n")).contains("2 vertices")
+    );
+    assert!(message(Mesh::load("assets/checkerboard.png")).contains("unknown mesh format"));
+}
+
+#[test]
+fn works_like_primitives() {
+    let cube: Surface = Arc::new(MeshSurface::new(Arc::new(cube()), red()));
+    let cut = || translation(vec3(1.0, 1.0, 0.0), sphere(0.8, red()));
+    let mesh_cut = subtraction(cube, cut());
+    let box_cut = subtraction(exact_box(Vec3::ONE, red()), cut());
+    for pos in [
+        vec3(0.9, 0.9, 0.0),
+        vec3(0.5, 0.2, -0.3),
+        vec3(2.0, -1.0, 3.0),
+        vec3(1.0, 1.0, 0.5),
+    ] {
+        assert!(is_close(mesh_cut.sdf(pos), box_cut.sdf(pos)), "at {pos}");
+    }
+
+    // Triplanar textures only need the position and normal
+    let textured = || Arc::new(Textured::new("assets/checkerboard.png"));
+    let mesh = MeshSurface::new(Arc::new(sphere_mesh(1.0, 8, 16)), textured());
+    let reference = sphere(1.0, textured());
+    let (pos, normal) = (vec3(0.3, 0.8, -0.5), vec3(0.3, 0.8, -0.5).normalize());
+    assert_eq!(
+        mesh.color(Vec3::Z, pos, normal, Vec3::Y),
+        reference.color(Vec3::Z, pos, normal, Vec3::Y)
+    );
+    // Meshes that weren't loaded from a file can't be saved
+    assert!(mesh.describe().is_none());
+}
+
+#[test]
+fn expressions_load_meshes() {
+    let knot = parse_surface("mesh(\"assets/knot.obj\", red)").unwrap();
+    assert!(knot.sdf(vec3(1.5, 0.0, 0.0)) < 0.0);
+    let err = parse_surface("mesh(\"assets/missing.obj\")").err().unwrap();
+    assert!(
+        err.to_string()
+            .contains("could not load mesh \"assets/missing.obj\""),
+        "{err}"
+    );
+}
+
+#[test]
+fn poles_of_tesselated_sphere() {
+    let mesh = sphere_mesh(1.0, 32, 64);
+    for y in [-3.6257145, -1.5, 1.5, 3.6] {
+        let dist = mesh.signed_distance(vec3(0.0, y, 0.0));
+        assert!(is_close(dist, y.abs() - 1.0), "{dist} at {y}");
+    }
+    assert!(mesh.signed_distance(vec3(0.0, -0.5, 0.0)) < 0.0);
+}
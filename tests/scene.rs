@@ -0,0 +1,160 @@
+mod common;
+
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use software_raymarcher::{
+    expr::parse_surface,
+    scene::Scene,
+    surfaces::{exact_box, plane, sphere, translation, FnSurface},
+};
+
+use common::{assert_close, assert_close_vec3, red};
+
+#[test]
+fn cast_ray_sphere() {
+    let scene = Scene::new(Arc::new(vec![sphere(1.0, red())]));
+    let hit = scene
+        .cast_ray(vec3(0.0, 0.0, -5.0), vec3(0.0, 0.0, 2.0), 100.0)
+        .unwrap();
+
+    assert_close(hit.t, 4.0);
+    assert_close_vec3(hit.position, vec3(0.0, 0.0, -1.0));
+    assert_close_vec3(hit.normal, vec3(0.0, 0.0, -1.0));
+    assert_eq!(hit.surface_index, 0);
+    assert!(hit.steps > 0);
+}
+
+#[test]
+fn cast_ray_reports_surface_index() {
+    let scene = Scene::new(Arc::new(vec![
+        plane(vec3(0.0, 1.0, 0.0), -2.0, red()),
+        translation(vec3(3.0, 0.0, 0.0), sphere(1.0, red())),
+    ]));
+
+    let down = scene
+        .cast_ray(Vec3::ZERO, vec3(0.0, -1.0, 0.0), 100.0)
+        .unwrap();
+    assert_close(down.t, 2.0);
+    assert_close_vec3(down.normal, Vec3::Y);
+    assert_eq!(down.surface_index, 0);
+
+    let right = scene.cast_ray(Vec3::ZERO, Vec3::X, 100.0).unwrap();
+    assert_close(right.t, 2.0);
+    assert_close_vec3(right.normal, -Vec3::X);
+    assert_eq!(right.surface_index, 1);
+}
+
+#[test]
+fn cast_ray_miss() {
+    let scene = Scene::new(Arc::new(vec![sphere(1.0, red())]));
+    assert!(scene
+        .cast_ray(vec3(0.0, 0.0, -5.0), vec3(0.0, 0.0, -1.0), 100.0)
+        .is_none());
+    assert!(scene
+        .cast_ray(vec3(0.0, 0.0, -5.0), vec3(0.0, 0.0, 1.0), 3.0)
+        .is_none());
+}
+
+#[test]
+fn cast_ray_from_inside_is_none() {
+    let scene = Scene::new(Arc::new(vec![sphere(1.0, red())]));
+    assert!(scene.cast_ray(Vec3::ZERO, Vec3::Z, 100.0).is_none());
+    assert!(scene
+        .cast_ray(vec3(0.0, 0.0, 0.5), vec3(0.0, 0.0, -1.0), 100.0)
+        .is_none());
+}
+
+#[test]
+fn cast_ray_out_of_steps_is_none() {
+    // Never within the surface distance, and too slow to reach max_dist before running out of steps
+    let fog = FnSurface::new(|_: Vec3| 0.001, red());
+    let scene = Scene::new(Arc::new(vec![Arc::new(fog)]));
+    assert!(scene.cast_ray(Vec3::ZERO, Vec3::Z, 100.0).is_none());
+}
+
+#[test]
+fn lipschitz_only_slows_its_own_surface() {
+    let origin = vec3(0.0, 0.0, -5.0);
+    let alone = Scene::new(Arc::new(vec![sphere(1.0, red())]));
+    // Far away, but would take a step 50 times shorter than the sphere if scaled scene wide
+    let far = FnSurface::new(|pos: Vec3| pos.distance(vec3(0.0, 500.0, 0.0)), red());
+    let with_far = Scene::new(Arc::new(vec![
+        sphere(1.0, red()),
+        Arc::new(far.with_lipschitz(50.0)),
+    ]));
+    let hit = alone.cast_ray(origin, Vec3::Z, 100.0).unwrap();
+    assert_eq!(with_far.cast_ray(origin, Vec3::Z, 100.0), Some(hit));
+}
+
+#[test]
+fn zero_lipschitz_does_not_divide_by_zero() {
+    let flat = parse_surface("sphere(1) * 0").unwrap();
+    assert_eq!(flat.lipschitz(), 0.0);
+    let scene = Scene::new(Arc::new(vec![flat]));
+    let hit = scene
+        .cast_ray(vec3(0.0, 0.0, -5.0), Vec3::Z, 100.0)
+        .unwrap();
+    assert_eq!(hit.t, 0.0);
+}
+
+#[test]
+fn distance_box_exact() {
+    let scene = Scene::new(Arc::new(vec![exact_box(vec3(1.0, 2.0, 3.0), red())]));
+
+    assert_close(scene.distance(vec3(3.0, 0.0, 0.0)), 2.0);
+    assert_close(scene.distance(vec3(2.0, 3.0, 0.0)), 2.0_f32.sqrt());
+    assert_close(scene.distance(vec3(2.0, 3.0, 4.0)), 3.0_f32.sqrt());
+    assert_close(scene.distance(Vec3::ZERO), -1.0);
+    assert_close(scene.distance(vec3(0.0, 1.5, 0.0)), -0.5);
+}
+
+#[test]
+fn distance_is_min_over_surfaces() {
+    let scene = Scene::new(Arc::new(vec![
+        sphere(1.0, red()),
+        plane(vec3(0.0, 1.0, 0.0), -2.0, red()),
+    ]));
+
+    assert_close(scene.distance(vec3(0.0, 3.0, 0.0)), 2.0);
+    assert_close(scene.distance(vec3(0.0, -1.5, 0.0)), 0.5);
+}
+
+#[test]
+fn gradient_has_unit_length() {
+    let scene = Scene::new(Arc::new(vec![
+        sphere(1.0, red()),
+        translation(vec3(5.0, 0.0, 0.0), exact_box(vec3(1.0, 1.0, 1.0), red())),
+    ]));
+
+    assert_close_vec3(scene.gradient(vec3(0.0, 2.0, 0.0)), Vec3::Y);
+    assert_close_vec3(
+        scene.gradient(vec3(1.0, 1.0, 1.0)),
+        vec3(1.0, 1.0, 1.0).normalize(),
+    );
+    assert_close_vec3(scene.gradient(vec3(7.0, 0.5, 0.0)), Vec3::X);
+    assert_close_vec3(scene.normal(vec3(5.0, -3.0, 0.2)), -Vec3::Y);
+}
+
+#[test]
+fn closest_point_projects_onto_surface() {
+    let scene = Scene::new(Arc::new(vec![sphere(1.0, red())]));
+    assert_close_vec3(scene.closest_point(vec3(3.0, 0.0, 0.0)), Vec3::X);
+    assert_close_vec3(scene.closest_point(vec3(0.0, 0.2, 0.0)), Vec3::Y);
+
+    let scene = Scene::new(Arc::new(vec![plane(vec3(0.0, 1.0, 0.0), -2.0, red())]));
+    assert_close_vec3(
+        scene.closest_point(vec3(1.0, 4.0, -3.0)),
+        vec3(1.0, -2.0, -3.0),
+    );
+
+    let scene = Scene::new(Arc::new(vec![exact_box(vec3(1.0, 2.0, 3.0), red())]));
+    assert_close_vec3(
+        scene.closest_point(vec3(3.0, 0.5, 0.0)),
+        vec3(1.0, 0.5, 0.0),
+    );
+    assert_close_vec3(
+        scene.closest_point(vec3(0.8, 0.5, 0.0)),
+        vec3(1.0, 0.5, 0.0),
+    );
+}
@@ -0,0 +1,281 @@
+use std::{env, f32::consts::PI, fs, sync::Arc};
+
+use glam::{vec3, Quat, Vec2, Vec3};
+use software_raymarcher::{
+    camera_path::CameraPose,
+    fbm::{FbmSettings, NoiseKind},
+    materials::{Normal, Textured, TrapGradient, Unlit, BLUE, RED},
+    raymarcher::{Raymarcher, RenderSettings},
+    scene_file::SceneFile,
+    surfaces::{
+        box_frame, capped_cone, capped_cylinder, capped_torus, capsule, cylinder, displace,
+        ellipsoid, exact_box, fbm_terrain, heightfield, hexagonal_prism, infinite_repetition,
+        intersection, link, mesh, octahedron, perlin_sphere, pertrubed_sphere, plane, pyramid,
+        rotation, round_cone, rounded_box, scale, sierpinski_tetrahedron, smooth_intersection,
+        smooth_subtraction, smooth_union, sphere, subtraction, torus, translation,
+        translation_rotation_scale, triangular_prism, union, Mandelbox, Mandelbulb, MengerSponge,
+        Surface, SurfaceList,
+    },
+};
+
+/// A scene using every surface and material that can be saved
+///
+/// Leaves out PerlinSphere, which is too slow to render in unoptimized tests
+fn every_surface() -> Vec<Surface> {
+    let red = Arc::new(Unlit::new(RED));
+    let blue = Arc::new(Unlit::new(BLUE));
+    let textured = Arc::new(
+        Textured::new("assets/checkerboard.png")
+            .with_scale(0.5)
+            .with_blend_sharpness(4.0),
+    );
+    vec![
+        plane(vec3(0.0, 1.0, 0.0), -2.0, textured.clone()),
+        union(
+            translation(vec3(-3.0, 0.0, 2.0), sphere(1.0, red.clone())),
+            subtraction(
+                exact_box(vec3(1.0, 1.0, 1.0), blue.clone()),
+                sphere(1.2, red.clone()),
+            ),
+        ),
+        intersection(
+            translation(
+                vec3(3.0, 0.0, 0.0),
+                exact_box(vec3(1.0, 2.0, 1.0), red.clone()),
+            ),
+            translation(vec3(3.0, 0.5, 0.0), sphere(1.5, Arc::new(Normal))),
+        ),
+        smooth_union(
+            translation(vec3(0.0, 2.5, 3.0), sphere(0.8, red.clone())),
+            translation(vec3(1.0, 2.5, 3.0), sphere(0.8, blue.clone())),
+            0.5,
+        ),
+        smooth_subtraction(
+            translation(
+                vec3(-2.0, 2.5, 3.0),
+                exact_box(vec3(0.8, 0.8, 0.8), blue.clone()),
+            ),
+            translation(vec3(-2.0, 3.0, 3.0), sphere(0.7, red.clone())),
+            0.3,
+        ),
+        smooth_intersection(
+            translation(
+                vec3(-4.0, 2.5, 3.0),
+                exact_box(vec3(0.8, 0.8, 0.8), blue.clone()),
+            ),
+            translation(vec3(-4.0, 2.5, 3.0), sphere(1.0, textured)),
+            0.3,
+        ),
+        translation(
+            vec3(4.0, 3.0, 4.0),
+            rotation(
+                Quat::from_rotation_z(PI / 4.0),
+                scale(0.5, exact_box(vec3(1.0, 2.0, 1.0), red.clone())),
+            ),
+        ),
+        translation_rotation_scale(
+            vec3(-1.0, -1.0, -1.0),
+            Quat::from_rotation_y(PI / 6.0),
+            0.3,
+            exact_box(vec3(1.0, 1.0, 2.0), blue.clone()),
+        ),
+        translation(
+            vec3(0.0, 0.0, 30.0),
+            infinite_repetition(vec3(3.0, 0.0, 0.0), sphere(0.5, red.clone())),
+        ),
+        translation(vec3(-5.0, 1.0, 8.0), pertrubed_sphere(1.0, 0.1, 0.5, red)),
+    ]
+}
+
+/// Primitives that are only checked for round trips, to keep the render test fast
+fn more_primitives() -> Vec<Surface> {
+    let red = Arc::new(Unlit::new(RED));
+    vec![
+        torus(1.0, 0.25, red.clone()),
+        capped_torus(PI / 3.0, 1.0, 0.2, red.clone()),
+        link(0.5, 0.4, 0.1, red.clone()),
+        capsule(vec3(-1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0), 0.3, red.clone()),
+        cylinder(1.0, 0.5, red.clone()),
+        capped_cylinder(Vec3::ZERO, vec3(1.0, 2.0, 0.0), 0.5, red.clone()),
+        capped_cone(1.0, 0.8, 0.2, red.clone()),
+        round_cone(0.6, 0.2, 1.5, red.clone()),
+        rounded_box(vec3(1.0, 0.5, 0.8), 0.2, red.clone()),
+        box_frame(Vec3::ONE, 0.1, red.clone()),
+        ellipsoid(vec3(1.0, 0.5, 2.0), red.clone()),
+        octahedron(1.0, red.clone()),
+        pyramid(0.5, 1.2, red.clone()),
+        hexagonal_prism(0.5, 1.0, red.clone()),
+        triangular_prism(0.5, 1.0, red.clone()),
+        Arc::new(
+            Mandelbulb::new(6.0, Arc::new(TrapGradient::new(RED, BLUE)))
+                .with_iterations(8)
+                .with_bailout(1.5),
+        ),
+        Arc::new(MengerSponge::new(1.0, red.clone()).with_iterations(3)),
+        sierpinski_tetrahedron(1.0, red.clone()),
+        mesh("assets/knot.obj", red.clone()),
+        heightfield("assets/heightmap.png", Vec2::splat(10.0), 2.0, red.clone()),
+        displace(
+            FbmSettings {
+                kind: NoiseKind::Simplex,
+                seed: 3,
+                ridged: true,
+                ..FbmSettings::new(2.0, 0.1)
+            },
+            sphere(1.0, red.clone()),
+        ),
+        fbm_terrain(FbmSettings::new(0.2, 3.0), red.clone()),
+        Arc::new(
+            Mandelbox::new(-1.5, red)
+                .with_min_radius(0.4)
+                .with_fold_limit(1.2),
+        ),
+    ]
+}
+
+#[test]
+fn save_load_round_trip() {
+    let mut surfaces = every_surface();
+    surfaces.extend(more_primitives());
+    surfaces.push(translation(
+        vec3(5.0, 1.0, 8.0),
+        perlin_sphere(1.0, 0.2, Arc::new(Unlit::new(BLUE))),
+    ));
+    let pose = CameraPose::new(vec3(0.0, 1.0, -6.0), 0.0, 0.0);
+    let scene = SceneFile::new(&surfaces, pose, vec3(-2.0, 3.0, -2.0)).unwrap();
+
+    let loaded = SceneFile::parse(&scene.to_ron(), "round_trip.ron").unwrap();
+    assert_eq!(loaded, scene);
+
+    let rebuilt = loaded.build_surfaces("round_trip.ron").unwrap();
+    let described = SceneFile::new(&rebuilt, loaded.camera_pose(), loaded.light).unwrap();
+    assert_eq!(described, scene);
+}
+
+#[test]
+fn save_load_renders_identical() {
+    let camera_pos = vec3(0.0, 1.0, -6.0);
+    let light_pos = vec3(-2.0, 3.0, -2.0);
+    let settings = RenderSettings::new(256, 256);
+    let raymarcher =
+        Raymarcher::new(Arc::new(every_surface()), camera_pos, light_pos).with_settings(settings);
+    let mut expected = vec![0; 256 * 256 * 4];
+    raymarcher.render(&mut expected);
+
+    let path = env::temp_dir().join("software_raymarcher_round_trip.ron");
+    let path = path.to_str().unwrap();
+    raymarcher.save(path).unwrap();
+    let loaded = Raymarcher::load(path).unwrap().with_settings(settings);
+    fs::remove_file(path).unwrap();
+
+    let mut pixels = vec![0; 256 * 256 * 4];
+    loaded.render(&mut pixels);
+    let different = expected
+        .chunks(4)
+        .zip(pixels.chunks(4))
+        .filter(|(a, b)| a != b)
+        .count();
+    assert_eq!(different, 0, "{different} pixels differ");
+}
+
+#[test]
+fn surfaces_without_description_are_an_error() {
+    struct Custom;
+    impl software_raymarcher::surfaces::SurfaceTrait for Custom {
+        fn sdf(&self, pos: Vec3) -> f32 {
+            pos.length() - 1.0
+        }
+
+        fn color(&self, _ray: Vec3, _pos: Vec3, _normal: Vec3, _light_pos: Vec3) -> Vec3 {
+            RED
+        }
+    }
+
+    let surfaces: SurfaceList = Arc::new(vec![sphere(1.0, Arc::new(Normal)), Arc::new(Custom)]);
+    let pose = CameraPose::new(Vec3::ZERO, 0.0, 0.0);
+    let err = SceneFile::new(&surfaces, pose, Vec3::ONE).unwrap_err();
+    assert!(err.to_string().contains("surface 1"), "{}", err);
+}
+
+#[test]
+fn example_scenes_build() {
+    for entry in fs::read_dir("scenes").unwrap() {
+        let path = entry.unwrap().path();
+        let path = path.to_str().unwrap();
+        let scene = SceneFile::load(path).unwrap_or_else(|err| panic!("{err}"));
+        let surfaces = scene
+            .build_surfaces(path)
+            .unwrap_or_else(|err| panic!("{path}: {err}"));
+        assert_eq!(surfaces.len(), scene.surfaces.len());
+    }
+}
+
+#[test]
+fn parse_error_has_location() {
+    let text = "Scene(\n    camera: (position: (0, 0, -5)),\n    light: (0, 1, 0),\n    surfaces: [Sphere(radius: 1)],\n)";
+    let err = SceneFile::parse(text, "test.ron").unwrap_err();
+    assert!(
+        err.to_string().starts_with("test.ron:4:"),
+        "{}",
+        err.to_string()
+    );
+    assert!(err.to_string().contains("material"), "{}", err.to_string());
+}
+
+#[test]
+fn empty_union_is_an_error() {
+    let text = "Scene(camera: (position: (0, 0, -5)), light: (0, 1, 0), surfaces: [Union([])])";
+    let err = SceneFile::parse(text, "test.ron").unwrap_err();
+    assert!(
+        err.to_string().contains("at least one surface"),
+        "{}",
+        err.to_string()
+    );
+}
+
+#[test]
+fn unknown_material_is_an_error() {
+    let text = r#"Scene(
+        camera: (position: (0, 0, -5)),
+        light: (0, 1, 0),
+        materials: {"red": Unlit((1, 0, 0))},
+        surfaces: [Sphere(radius: 1, material: Named("blue"))],
+    )"#;
+    let scene = SceneFile::parse(text, "test.ron").unwrap();
+    let Err(err) = scene.build_surfaces("test.ron") else {
+        panic!("expected an error");
+    };
+    assert!(err.to_string().contains("\"blue\""), "{}", err.to_string());
+    assert!(
+        err.to_string().starts_with("test.ron: surfaces[0]: "),
+        "{}",
+        err.to_string()
+    );
+}
+
+#[test]
+fn build_errors_name_the_surface() {
+    let text = r#"Scene(
+        camera: (position: (0, 0, -5)),
+        light: (0, 1, 0),
+        materials: {"brick": Textured(path: "missing.png")},
+        surfaces: [
+            Sphere(radius: 1, material: Normal),
+            Union([Sphere(radius: 1, material: Normal), Expr("sphere(1)\nspher(2)")]),
+        ],
+    )"#;
+    let scene = SceneFile::parse(text, "broken.ron").unwrap();
+    let err = scene.build_surfaces("broken.ron").err().unwrap().to_string();
+    assert!(
+        err.starts_with("broken.ron: materials[\"brick\"]: "),
+        "{err}"
+    );
+
+    let text = text.replace(r#""brick": Textured(path: "missing.png")"#, "");
+    let scene = SceneFile::parse(&text, "broken.ron").unwrap();
+    let err = scene.build_surfaces("broken.ron").err().unwrap().to_string();
+    assert!(
+        err.starts_with("broken.ron: surfaces[1]: expression:2:1: "),
+        "{err}"
+    );
+}
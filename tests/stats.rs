@@ -0,0 +1,129 @@
+use std::{env, fs, sync::Arc, time::Duration};
+
+use glam::{vec3, Vec3};
+use software_raymarcher::{
+    camera_path::{CameraPath, CameraPose},
+    materials::Normal,
+    raymarcher::{Antialiasing, Raymarcher, RenderSettings, Shadows, Threading},
+    stats::{RenderStats, StatsLog},
+    surfaces::{plane, sphere},
+};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 24;
+
+fn render(settings: RenderSettings) -> RenderStats {
+    let surfaces = Arc::new(vec![
+        sphere(1.0, Arc::new(Normal)),
+        plane(Vec3::Y, 1.0, Arc::new(Normal)),
+    ]);
+    let raymarcher = Raymarcher::new(surfaces, vec3(0.0, 0.0, -5.0), vec3(-2.0, 3.0, -2.0))
+        .with_settings(settings);
+    let mut pixels = vec![0; (settings.width * settings.height * 4) as usize];
+    raymarcher.render(&mut pixels)
+}
+
+#[test]
+fn counts_match_settings() {
+    let mut settings = RenderSettings::new(WIDTH, HEIGHT);
+    settings.shadows = Shadows::Hard;
+    let stats = render(settings);
+    assert_eq!(stats.primary_rays, (WIDTH * HEIGHT) as u64);
+    assert!(stats.shadow_rays > 0 && stats.shadow_rays <= stats.primary_rays);
+    assert!(stats.primary_steps >= stats.primary_rays);
+    assert!(stats.max_steps as f32 >= stats.average_steps());
+    // Every step evaluates both surfaces
+    assert!(stats.estimated_sdf_evaluations >= 2 * stats.primary_steps);
+
+    settings.anti_aliasing = Antialiasing::AAx4;
+    assert_eq!(render(settings).primary_rays, 4 * (WIDTH * HEIGHT) as u64);
+
+    settings.anti_aliasing = Antialiasing::None;
+    settings.shadows = Shadows::None;
+    assert_eq!(render(settings).shadow_rays, 0);
+}
+
+#[test]
+fn timings_only_when_asked() {
+    let mut settings = RenderSettings::new(WIDTH, HEIGHT);
+    let stats = render(settings);
+    assert_eq!(stats.march_time + stats.shading_time, Duration::ZERO);
+    assert!(stats.frame_time > Duration::ZERO);
+    assert!(!stats.lines().iter().any(|line| line.starts_with("march")));
+
+    settings.timings = true;
+    let stats = render(settings);
+    assert!(stats.march_time > Duration::ZERO && stats.shading_time > Duration::ZERO);
+    assert!(stats.lines().iter().any(|line| line.starts_with("march")));
+}
+
+#[test]
+fn threading_does_not_change_counts() {
+    let mut settings = RenderSettings::new(WIDTH, HEIGHT);
+    let counts = |stats: RenderStats| {
+        (
+            stats.primary_rays,
+            stats.shadow_rays,
+            stats.estimated_sdf_evaluations,
+            stats.primary_steps,
+            stats.max_steps,
+            stats.step_limit_rays,
+        )
+    };
+    settings.threading = Threading::Single;
+    let single = counts(render(settings));
+    for threading in [Threading::ChunkMut(), Threading::LineChunkMut(5)] {
+        settings.threading = threading;
+        assert_eq!(counts(render(settings)), single);
+    }
+}
+
+#[test]
+fn log_output() {
+    let mut log = StatsLog::new().with_limit(2);
+    for rays in 1..=3 {
+        log.push(RenderStats {
+            primary_rays: rays,
+            max_steps: 10,
+            ..RenderStats::new()
+        });
+    }
+    assert_eq!(log.len(), 2);
+    assert_eq!(log.total().primary_rays, 5);
+
+    let csv = log.to_csv();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("frame,primary_rays,shadow_rays,"));
+    assert!(lines[1].starts_with("0,2,0,"));
+    assert!(lines[2].starts_with("1,3,0,"));
+
+    let json = log.to_json();
+    assert!(json.starts_with("[\n  {\"frame\": 0, \"primary_rays\": 2,"));
+    assert!(json.contains("\"max_steps\": 10"));
+    assert!(json.trim_end().ends_with("}\n]"));
+}
+
+#[test]
+fn camera_path_counts_rendered_frames() {
+    let surfaces = Arc::new(vec![sphere(1.0, Arc::new(Normal))]);
+    let mut raymarcher = Raymarcher::new(surfaces, vec3(0.0, 0.0, -5.0), Vec3::ONE)
+        .with_settings(RenderSettings::new(WIDTH, HEIGHT));
+    let dir = env::temp_dir().join("software_raymarcher_path_frames");
+    let dir = dir.to_str().unwrap();
+
+    // Nothing to sample, so nothing is rendered
+    let log = raymarcher
+        .render_camera_path(&CameraPath::new(), 30.0, dir)
+        .unwrap();
+    assert!(log.is_empty());
+    assert_eq!(log.average_frame_time(), Duration::ZERO);
+
+    let mut path = CameraPath::new();
+    path.push(0.0, CameraPose::new(vec3(0.0, 0.0, -5.0), 0.0, 0.0));
+    path.push(0.1, CameraPose::new(vec3(0.0, 1.0, -5.0), 0.0, 0.0));
+    let log = raymarcher.render_camera_path(&path, 30.0, dir).unwrap();
+    assert_eq!(log.len(), 4);
+    assert_eq!(log.average_frame_time(), log.total().frame_time / 4);
+    fs::remove_dir_all(dir).unwrap();
+}
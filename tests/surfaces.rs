@@ -0,0 +1,323 @@
+mod common;
+
+use std::{f32::consts::PI, sync::Arc};
+
+use glam::{EulerRot, Quat, Vec3};
+use proptest::prelude::*;
+use software_raymarcher::{
+    expr::parse_surface,
+    surfaces::{
+        box_frame, capped_cone, capped_cylinder, capped_torus, capsule, cylinder, ellipsoid,
+        exact_box, hexagonal_prism, infinite_repetition, intersection, link, octahedron, plane,
+        pyramid, rotation, round_cone, rounded_box, scale, smooth_intersection, smooth_subtraction,
+        smooth_union, sphere, subtraction, torus, translation, translation_rotation_scale,
+        triangular_prism, union, FnSurface, Surface,
+    },
+};
+
+use common::{blue, point, prop_assert_close, red, TOLERANCE};
+
+fn half_size() -> impl Strategy<Value = Vec3> {
+    prop::array::uniform3(0.1f32..4.0).prop_map(Vec3::from)
+}
+
+fn direction() -> impl Strategy<Value = Vec3> {
+    point(10.0).prop_filter("needs a direction", |p| p.length() > 0.1)
+}
+
+fn quat() -> impl Strategy<Value = Quat> {
+    prop::array::uniform3(-PI..PI).prop_map(|[x, y, z]| Quat::from_euler(EulerRot::XYZ, x, y, z))
+}
+
+fn box_distance(pos: Vec3, half_size: Vec3) -> f32 {
+    let q = pos.abs() - half_size;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+}
+
+/// Sphere at the origin and a box next to it, overlapping for some sizes
+fn pair(radius: f32, half_size: Vec3) -> (Surface, Surface) {
+    let ball = sphere(radius, red());
+    let cube = translation(Vec3::X, exact_box(half_size, blue()));
+    (ball, cube)
+}
+
+/// Surfaces that should have an exact or conservative distance with their reported bound
+fn bounded_surfaces() -> Vec<(&'static str, Surface)> {
+    let (a, b) = pair(1.5, Vec3::new(1.0, 2.0, 0.5));
+    let overestimate =
+        FnSurface::new(|p: Vec3| 2.0 * (p.length() - 1.0), red()).with_lipschitz(2.0);
+    vec![
+        ("sphere", a.clone()),
+        ("box", b.clone()),
+        ("plane", plane(Vec3::new(1.0, 2.0, 3.0), 0.5, red())),
+        ("union", union(a.clone(), b.clone())),
+        ("intersection", intersection(a.clone(), b.clone())),
+        ("subtraction", subtraction(a.clone(), b.clone())),
+        ("smooth union", smooth_union(a.clone(), b.clone(), 0.8)),
+        (
+            "smooth intersection",
+            smooth_intersection(a.clone(), b.clone(), 0.8),
+        ),
+        (
+            "smooth subtraction",
+            smooth_subtraction(a.clone(), b.clone(), 0.8),
+        ),
+        ("translation", translation(Vec3::ONE, a.clone())),
+        ("rotation", rotation(Quat::from_rotation_y(0.7), b.clone())),
+        ("scale", scale(2.5, b.clone())),
+        (
+            "transform",
+            translation_rotation_scale(Vec3::ONE, Quat::from_rotation_z(1.2), 0.4, b.clone()),
+        ),
+        ("repetition", infinite_repetition(Vec3::splat(4.0), a)),
+        ("torus", torus(2.0, 0.5, red())),
+        ("capped torus", capped_torus(1.0, 2.0, 0.5, red())),
+        ("link", link(1.0, 1.5, 0.3, red())),
+        (
+            "capsule",
+            capsule(Vec3::ONE, Vec3::new(-1.0, 2.0, 0.5), 0.5, red()),
+        ),
+        ("cylinder", cylinder(1.5, 0.7, red())),
+        (
+            "capped cylinder",
+            capped_cylinder(Vec3::ZERO, Vec3::new(1.0, 2.0, -1.0), 0.7, red()),
+        ),
+        ("capped cone", capped_cone(1.5, 1.0, 0.3, red())),
+        ("round cone", round_cone(1.0, 0.4, 2.0, red())),
+        (
+            "rounded box",
+            rounded_box(Vec3::new(1.0, 2.0, 0.5), 0.3, red()),
+        ),
+        ("box frame", box_frame(Vec3::new(1.0, 2.0, 1.5), 0.2, red())),
+        ("ellipsoid", ellipsoid(Vec3::new(0.5, 1.0, 2.0), red())),
+        ("octahedron", octahedron(1.5, red())),
+        ("pyramid", pyramid(0.8, 2.0, red())),
+        ("hexagonal prism", hexagonal_prism(1.0, 0.5, red())),
+        ("triangular prism", triangular_prism(1.0, 0.5, red())),
+        ("closure", Arc::new(overestimate)),
+        ("scaled expression", parse_surface("sphere(1) * 3").unwrap()),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn sphere_is_exact(pos in point(10.0), radius in 0.1f32..5.0) {
+        prop_assert_close(sphere(radius, red()).sdf(pos), pos.length() - radius)?;
+    }
+
+    #[test]
+    fn box_is_exact(pos in point(10.0), half_size in half_size()) {
+        prop_assert_close(exact_box(half_size, red()).sdf(pos), box_distance(pos, half_size))?;
+    }
+
+    #[test]
+    fn plane_is_exact(pos in point(10.0), normal in direction(), distance in -5.0f32..5.0) {
+        let expected = pos.dot(normal.normalize()) - distance;
+        prop_assert_close(plane(normal, distance, red()).sdf(pos), expected)?;
+    }
+
+    #[test]
+    fn round_primitives_are_exact(
+        pos in point(10.0),
+        major in 0.5f32..4.0,
+        minor in 0.1f32..0.5,
+        start in point(10.0),
+        end in point(10.0),
+    ) {
+        let ring = Vec3::new(pos.x, 0.0, pos.z).normalize_or_zero() * major;
+        prop_assert_close(torus(major, minor, red()).sdf(pos), pos.distance(ring) - minor)?;
+
+        // Closest point on the segment by sampling it densely
+        let closest = (0..=10000)
+            .map(|i| pos.distance(start.lerp(end, i as f32 / 10000.0)))
+            .fold(f32::INFINITY, f32::min);
+        let dist = capsule(start, end, minor, red()).sdf(pos);
+        prop_assert!((dist - (closest - minor)).abs() < 0.01, "{} != {}", dist, closest - minor);
+    }
+
+    #[test]
+    fn special_cases_match(pos in point(10.0), half_height in 0.1f32..3.0, radius in 0.1f32..3.0) {
+        let up = Vec3::Y * half_height;
+        let expected = cylinder(half_height, radius, red()).sdf(pos);
+        prop_assert_close(capped_cylinder(-up, up, radius, red()).sdf(pos), expected)?;
+        prop_assert_close(capped_cone(half_height, radius, radius, red()).sdf(pos), expected)?;
+
+        let expected = capsule(Vec3::ZERO, 2.0 * up, radius, red()).sdf(pos);
+        prop_assert_close(round_cone(radius, radius, 2.0 * half_height, red()).sdf(pos), expected)?;
+
+        // A full arc is a torus around the z axis, and a link of length 0 too
+        let expected = rotation(Quat::from_rotation_x(PI / 2.0), torus(radius + 0.5, 0.3, red()));
+        let expected = expected.sdf(pos);
+        prop_assert_close(capped_torus(PI, radius + 0.5, 0.3, red()).sdf(pos), expected)?;
+        prop_assert_close(link(0.0, radius + 0.5, 0.3, red()).sdf(pos), expected)?;
+    }
+
+    #[test]
+    fn boxes_match_unions_of_boxes(
+        pos in point(10.0),
+        half_size in half_size(),
+        fraction in 0.05f32..0.5,
+    ) {
+        let radius = half_size.min_element() * fraction;
+        let expected = exact_box(half_size - radius, red()).sdf(pos) - radius;
+        prop_assert_close(rounded_box(half_size, radius, red()).sdf(pos), expected)?;
+
+        // The frame is twelve thin boxes along the edges
+        let thickness = radius;
+        let mut edges = Vec::new();
+        for axis in 0..3 {
+            let mut edge_size = Vec3::splat(thickness);
+            edge_size[axis] = half_size[axis];
+            for corner in [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)] {
+                let mut offset = Vec3::ZERO;
+                offset[(axis + 1) % 3] = corner.0 * (half_size[(axis + 1) % 3] - thickness);
+                offset[(axis + 2) % 3] = corner.1 * (half_size[(axis + 2) % 3] - thickness);
+                edges.push(exact_box(edge_size, red()).sdf(pos - offset));
+            }
+        }
+        let expected = edges.into_iter().fold(f32::INFINITY, f32::min);
+        prop_assert_close(box_frame(half_size, thickness, red()).sdf(pos), expected)?;
+    }
+
+    #[test]
+    fn ellipsoid_never_overestimates(pos in point(10.0), radii in half_size(), dir in direction()) {
+        // Any point on the surface is at least as far as the closest one
+        let on_surface = radii * dir.normalize();
+        let surface = ellipsoid(radii, red());
+        prop_assert!(surface.sdf(pos) <= pos.distance(on_surface) + TOLERANCE);
+        prop_assert_eq!(surface.sdf(pos) < 0.0, (pos / radii).length() < 1.0);
+
+        let radius = radii.x;
+        prop_assert_close(ellipsoid(Vec3::splat(radius), red()).sdf(pos), pos.length() - radius)?;
+    }
+
+    #[test]
+    fn polyhedra_are_exact_off_faces(size in 0.1f32..3.0, length in 0.1f32..3.0, d in 0.0f32..5.0) {
+        let octahedron = octahedron(size, red());
+        prop_assert_close(octahedron.sdf(Vec3::new(0.0, size + d, 0.0)), d)?;
+        let face = Vec3::splat(size / 3.0);
+        prop_assert_close(octahedron.sdf(face + Vec3::ONE.normalize() * d), d)?;
+
+        let pyramid = pyramid(size, length, red());
+        prop_assert_close(pyramid.sdf(Vec3::new(0.0, -d, 0.0)), d)?;
+        prop_assert_close(pyramid.sdf(Vec3::new(0.0, length + d, 0.0)), d)?;
+        prop_assert!(pyramid.sdf(Vec3::new(0.0, length / 4.0, 0.0)) < 0.0);
+
+        let hexagon = hexagonal_prism(size, length, red());
+        prop_assert_close(hexagon.sdf(Vec3::new(0.0, size + d, 0.0)), d)?;
+        prop_assert_close(hexagon.sdf(Vec3::new(0.0, 0.0, length + d)), d)?;
+        let triangle = triangular_prism(size, length, red());
+        prop_assert_close(triangle.sdf(Vec3::new(0.0, -size - d, 0.0)), d)?;
+        prop_assert_close(triangle.sdf(Vec3::new(0.0, 0.0, -length - d)), d)?;
+    }
+
+    #[test]
+    fn triangular_prism_is_exact_past_corners(
+        size in 0.1f32..3.0,
+        length in 0.1f32..3.0,
+        d in 0.0f32..5.0,
+        angle in -PI / 2.0..PI / 6.0,
+        z in -4.0f32..4.0,
+    ) {
+        // Directions between the normals of the bottom and right side all have the corner closest
+        let corner = Vec3::new(size * 3f32.sqrt(), -size, z);
+        let pos = corner + Vec3::new(angle.cos(), angle.sin(), 0.0) * d;
+        let beyond = (z.abs() - length).max(0.0);
+        let expected = (d * d + beyond * beyond).sqrt();
+        let triangle = triangular_prism(size, length, red());
+        prop_assert_close(triangle.sdf(pos), expected)?;
+        // The top corner is twice as far from the center as the sides
+        let top = Vec3::new(0.0, 2.0 * size + d, 0.0);
+        prop_assert_close(triangle.sdf(top), d)?;
+    }
+
+    #[test]
+    fn operators_are_min_and_max(pos in point(10.0), radius in 0.1f32..3.0, half_size in half_size()) {
+        let (a, b) = pair(radius, half_size);
+        let (da, db) = (a.sdf(pos), b.sdf(pos));
+        prop_assert_close(union(a.clone(), b.clone()).sdf(pos), da.min(db))?;
+        prop_assert_close(intersection(a.clone(), b.clone()).sdf(pos), da.max(db))?;
+        prop_assert_close(subtraction(a, b).sdf(pos), da.max(-db))?;
+    }
+
+    #[test]
+    fn smooth_operators_stay_near_hard_ones(
+        pos in point(10.0),
+        radius in 0.1f32..3.0,
+        half_size in half_size(),
+        blend in 0.0001f32..2.0,
+    ) {
+        // The polynomial blend moves the distance by at most a quarter of the blend factor
+        let (a, b) = pair(radius, half_size);
+        let (da, db) = (a.sdf(pos), b.sdf(pos));
+        let limit = blend / 4.0 + TOLERANCE;
+        let smooth = smooth_union(a.clone(), b.clone(), blend).sdf(pos);
+        prop_assert!(smooth <= da.min(db) && da.min(db) - smooth <= limit);
+        let smooth = smooth_intersection(a.clone(), b.clone(), blend).sdf(pos);
+        prop_assert!(smooth >= da.max(db) && smooth - da.max(db) <= limit);
+        let smooth = smooth_subtraction(a, b, blend).sdf(pos);
+        prop_assert!(smooth >= da.max(-db) && smooth - da.max(-db) <= limit);
+    }
+
+    #[test]
+    fn smooth_colors_blend_like_distances(
+        pos in point(10.0),
+        radius in 0.1f32..3.0,
+        half_size in half_size(),
+        blend in 0.1f32..2.0,
+    ) {
+        // Red is the weight of the first surface, which has to give back the blended distance
+        let (a, b) = pair(radius, half_size);
+        let (da, db) = (a.sdf(pos), b.sdf(pos));
+        let blended = |surface: Surface, db: f32, sign: f32| {
+            let w = surface.color(Vec3::Z, pos, Vec3::Y, Vec3::ZERO).x;
+            (surface.sdf(pos), w * da + (1.0 - w) * db + sign * blend * w * (1.0 - w))
+        };
+        let (dist, expected) = blended(smooth_union(a.clone(), b.clone(), blend), db, -1.0);
+        prop_assert_close(dist, expected)?;
+        let (dist, expected) = blended(smooth_intersection(a.clone(), b.clone(), blend), db, 1.0);
+        prop_assert_close(dist, expected)?;
+        let (dist, expected) = blended(smooth_subtraction(a, b, blend), -db, 1.0);
+        prop_assert_close(dist, expected)?;
+    }
+
+    #[test]
+    fn transforms_preserve_distances(
+        pos in point(10.0),
+        offset in point(10.0),
+        rotation_quat in quat(),
+        factor in 0.1f32..5.0,
+        half_size in half_size(),
+    ) {
+        let cube = || exact_box(half_size, red());
+        let exact = |pos: Vec3| box_distance(pos, half_size);
+        prop_assert_close(translation(offset, cube()).sdf(pos), exact(pos - offset))?;
+        prop_assert_close(rotation(rotation_quat, cube()).sdf(pos), exact(rotation_quat * pos))?;
+        prop_assert_close(scale(factor, cube()).sdf(pos), exact(pos / factor) * factor)?;
+        let transformed = translation_rotation_scale(offset, rotation_quat, factor, cube());
+        let local = rotation_quat * (pos - offset) / factor;
+        prop_assert_close(transformed.sdf(pos), exact(local) * factor)?;
+    }
+
+    #[test]
+    fn distances_change_at_most_by_lipschitz(a in point(10.0), b in point(10.0)) {
+        // The ellipsoid changes faster than 1 away from its axes, but stays below the distance,
+        // see ellipsoid_never_overestimates
+        for (name, surface) in bounded_surfaces().into_iter().filter(|(name, _)| *name != "ellipsoid") {
+            let change = (surface.sdf(a) - surface.sdf(b)).abs();
+            let limit = surface.lipschitz() * a.distance(b) * (1.0 + TOLERANCE) + TOLERANCE;
+            prop_assert!(change <= limit, "{} changed by {} over {}", name, change, a.distance(b));
+        }
+    }
+}
+
+#[test]
+fn smooth_operators_converge_to_hard_ones() {
+    let (a, b) = pair(1.5, Vec3::new(1.0, 2.0, 0.5));
+    let pos = Vec3::new(0.3, 0.8, -0.2);
+    for blend in [1.0, 0.1, 0.01, 0.0001] {
+        let hard = union(a.clone(), b.clone()).sdf(pos);
+        let smooth = smooth_union(a.clone(), b.clone(), blend).sdf(pos);
+        assert!((hard - smooth).abs() <= blend / 4.0 + 1e-6);
+    }
+}
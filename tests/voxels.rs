@@ -0,0 +1,172 @@
+mod common;
+
+use std::{
+    env, fs,
+    sync::{Arc, OnceLock},
+};
+
+use glam::{vec3, UVec3, Vec3};
+use proptest::prelude::*;
+use software_raymarcher::{
+    camera_path::CameraPose,
+    expr::parse_surface,
+    materials::{BLUE, RED},
+    scene_file::SceneFile,
+    surfaces::{
+        bake, exact_box, sphere, translation, union, Aabb, Surface, SurfaceTrait, VoxelSdf,
+    },
+    voxels::{Interpolation, VoxelGrid},
+};
+
+use common::{blue, red};
+
+const RESOLUTION: u32 = 33;
+
+fn bounds() -> Aabb {
+    Aabb::new(Vec3::splat(-2.0), Vec3::splat(2.0))
+}
+
+fn ball() -> Surface {
+    sphere(1.2, red())
+}
+
+fn ball_grid() -> &'static VoxelGrid {
+    static GRID: OnceLock<VoxelGrid> = OnceLock::new();
+    GRID.get_or_init(|| VoxelGrid::bake(ball().as_ref(), bounds(), RESOLUTION))
+}
+
+fn inside() -> impl Strategy<Value = Vec3> {
+    prop::array::uniform3(-2.0f32..2.0).prop_map(Vec3::from)
+}
+
+fn outside() -> impl Strategy<Value = Vec3> {
+    (prop::array::uniform3(-1.0f32..1.0), 2.0f32..10.0)
+        .prop_filter("direction", |(dir, _)| Vec3::from(*dir).length() > 0.1)
+        .prop_map(|(dir, length)| Vec3::from(dir).normalize() * length * 2.0)
+}
+
+proptest! {
+    #[test]
+    fn interpolates_close_to_the_surface(pos in inside()) {
+        // The distance field has a kink at the center that no interpolation follows
+        prop_assume!(pos.length() > 0.6);
+        let expected = ball().sdf(pos);
+        // Cells are 1/8 wide, interpolating the curved distance field errs by much less
+        let linear = ball_grid().distance(pos, Interpolation::Trilinear);
+        prop_assert!((linear - expected).abs() < 0.01, "{} != {}", linear, expected);
+        let cubic = ball_grid().distance(pos, Interpolation::Tricubic);
+        prop_assert!((cubic - expected).abs() < 0.002, "{} != {}", cubic, expected);
+    }
+
+    #[test]
+    fn never_overestimates_outside(pos in outside()) {
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tricubic] {
+            let dist = ball_grid().distance(pos, interpolation);
+            prop_assert!(dist <= ball().sdf(pos) + 0.001, "{} at {}", dist, pos);
+            let box_dist = (pos.abs() - Vec3::splat(2.0)).max(Vec3::ZERO).length();
+            prop_assert!(dist >= box_dist - 0.001, "{} at {}", dist, pos);
+        }
+    }
+}
+
+#[test]
+fn samples_match_surface() {
+    let ball = translation(vec3(0.5, 0.0, 0.0), ball());
+    // Longer along x, the other sides get fewer samples with the same spacing
+    let bounds = Aabb::new(vec3(-2.0, -1.0, -1.0), vec3(2.0, 1.0, 1.5));
+    let grid = VoxelGrid::bake(ball.as_ref(), bounds, 9);
+    assert_eq!(grid.counts(), UVec3::new(9, 5, 6));
+    let step = bounds.size() / (grid.counts() - UVec3::ONE).as_vec3();
+    for (x, y, z) in [(0, 0, 0), (8, 4, 5), (3, 1, 2), (5, 4, 0)] {
+        let pos = bounds.min + vec3(x as f32, y as f32, z as f32) * step;
+        assert_eq!(grid.get(x, y, z), ball.sdf(pos));
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tricubic] {
+            assert!((grid.distance(pos, interpolation) - ball.sdf(pos)).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn keeps_colors_of_baked_surface() {
+    let surface = union(
+        sphere(1.0, red()),
+        translation(vec3(0.0, 2.0, 0.0), exact_box(Vec3::splat(0.5), blue())),
+    );
+    let bounds = Aabb::new(vec3(-1.5, -1.5, -1.5), vec3(1.5, 3.0, 1.5));
+    let baked = bake(&surface, bounds, 32);
+    let color = |pos| baked.color(Vec3::Z, pos, Vec3::Y, Vec3::Y);
+    assert_eq!(color(vec3(0.0, -1.0, 0.0)), RED);
+    assert_eq!(color(vec3(0.0, 2.5, 0.0)), BLUE);
+    // Can't be saved without a file and a material that can be saved
+    assert!(baked.describe().is_none());
+    let recolored = baked.with_material(blue());
+    assert_eq!(recolored.color(Vec3::Z, Vec3::ZERO, Vec3::Y, Vec3::Y), BLUE);
+}
+
+#[test]
+fn save_load_round_trip() {
+    let grid = VoxelGrid::bake(ball().as_ref(), bounds(), 17);
+    let path = env::temp_dir().join("software_raymarcher_ball.vsdf");
+    let path = path.to_str().unwrap();
+    grid.save(path).unwrap();
+    let loaded = VoxelGrid::load(path).unwrap();
+    assert_eq!(loaded.to_bytes(), grid.to_bytes());
+    assert_eq!(loaded.bounds(), grid.bounds());
+
+    let voxels: Surface = Arc::new(
+        VoxelSdf::load(path, red())
+            .unwrap()
+            .with_interpolation(Interpolation::Tricubic),
+    );
+    let pose = CameraPose::new(vec3(0.0, 1.0, -6.0), 0.0, 0.0);
+    let scene = SceneFile::new(std::slice::from_ref(&voxels), pose, Vec3::ONE).unwrap();
+    let ron = scene.to_ron();
+    assert!(ron.contains("interpolation: Tricubic"), "{ron}");
+    let rebuilt = SceneFile::parse(&ron, "voxels.ron")
+        .unwrap()
+        .build_surfaces("voxels.ron")
+        .unwrap();
+    fs::remove_file(path).unwrap();
+    let pos = vec3(0.3, -0.7, 1.1);
+    assert_eq!(rebuilt[0].sdf(pos), voxels.sdf(pos));
+}
+
+#[test]
+fn invalid_files_are_errors() {
+    let bytes = VoxelGrid::bake(ball().as_ref(), bounds(), 4).to_bytes();
+    let message = |bytes: &[u8]| VoxelGrid::from_bytes(bytes).err().unwrap().to_string();
+    assert_eq!(message(b"PNG"), "not a voxel file");
+    let mut version = bytes.clone();
+    version[4] = 9;
+    assert_eq!(message(&version), "unsupported voxel file version 9");
+    assert_eq!(
+        message(&bytes[..bytes.len() - 4]),
+        "[4, 4, 4] samples need 64 distances, got 63"
+    );
+    let mut huge = bytes.clone();
+    huge[8..20].fill(0xff);
+    assert!(message(&huge).contains("too many"));
+    let flat = VoxelGrid::new(bounds(), UVec3::new(4, 1, 4), vec![0.0; 16]);
+    assert!(flat
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("at least 2 samples"));
+}
+
+#[test]
+fn expressions_bake_and_load() {
+    let baked = parse_surface("bake(sphere(1) @ (1, 0, 0), 24)").unwrap();
+    for pos in [
+        vec3(1.6, 0.0, 0.0),
+        vec3(2.1, 0.3, 0.0),
+        vec3(0.5, 0.5, 0.5),
+    ] {
+        let expected = (pos - Vec3::X).length() - 1.0;
+        assert!((baked.sdf(pos) - expected).abs() < 0.01, "at {pos}");
+    }
+    let err = parse_surface("bake(plane((0, 1, 0), 0), 8)").err().unwrap();
+    assert!(err.to_string().contains("without bounds"), "{err}");
+    let err = parse_surface("voxels(\"missing.vsdf\")").err().unwrap();
+    assert!(err.to_string().contains("could not load voxels"), "{err}");
+}